@@ -0,0 +1,185 @@
+//! The user's configuration file.
+//!
+//! The configuration file sets default values for command line
+//! options, using the same names as the long option:
+//!
+//! ```toml
+//! display = "inline"
+//! context = 5
+//! skip-unchanged = true
+//!
+//! [languages]
+//! h = "c"
+//! ```
+//!
+//! Command line arguments and `DFT_*` environment variables take
+//! precedence over the configuration file. Flags can be turned off
+//! with `--no-<flag>`, e.g. `--no-skip-unchanged`.
+//!
+//! `summary`, `stat`, `list-changes` and `check` can't be set here.
+//! They replace the normal output rather than configuring it, so
+//! setting them by default would also change what git and other
+//! tools calling difftastic see. `language` can't be set either, as
+//! it would parse every file with the same parser. Use `[languages]`
+//! instead.
+
+use std::{collections::BTreeMap, env, fs, io::ErrorKind, path::PathBuf};
+
+use serde::Deserialize;
+
+use crate::exit_codes::EXIT_ERROR;
+
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Config {
+    pub display: Option<String>,
+    pub color: Option<String>,
+    pub background: Option<String>,
+    pub theme: Option<String>,
+    pub syntax_highlight: Option<String>,
+    pub context: Option<u32>,
+    pub width: Option<usize>,
+    pub tab_width: Option<usize>,
+    pub byte_limit: Option<usize>,
+    pub graph_limit: Option<usize>,
+    pub time_limit: Option<String>,
+    pub memory_limit: Option<String>,
+    #[serde(default)]
+    pub skip_unchanged: bool,
+    #[serde(default)]
+    pub skip_formatting_only: bool,
+    #[serde(default)]
+    pub ignore_comments: bool,
+    #[serde(default)]
+    pub exit_code: bool,
+    #[serde(default)]
+    pub missing_as_empty: bool,
+    #[serde(default)]
+    pub interactive: bool,
+    /// Parse files with these extensions as if they had a different
+    /// extension, e.g. `h = "c"` to treat all .h files as C.
+    #[serde(default)]
+    pub languages: BTreeMap<String, String>,
+}
+
+impl Config {
+    /// The options that take a value that are set in this
+    /// configuration, as pairs of the long option name and the value.
+    pub fn option_values(&self) -> Vec<(&'static str, String)> {
+        let strings = [
+            ("display", &self.display),
+            ("color", &self.color),
+            ("background", &self.background),
+            ("theme", &self.theme),
+            ("syntax-highlight", &self.syntax_highlight),
+            ("time-limit", &self.time_limit),
+            ("memory-limit", &self.memory_limit),
+        ];
+        let numbers = [
+            ("width", self.width),
+            ("tab-width", self.tab_width),
+            ("byte-limit", self.byte_limit),
+            ("graph-limit", self.graph_limit),
+        ];
+
+        let mut values: Vec<(&'static str, String)> = strings
+            .iter()
+            .filter_map(|(name, value)| value.as_ref().map(|value| (*name, value.clone())))
+            .collect();
+        values.extend(
+            numbers
+                .iter()
+                .filter_map(|(name, value)| value.map(|value| (*name, value.to_string()))),
+        );
+        if let Some(context) = self.context {
+            values.push(("context", context.to_string()));
+        }
+        values
+    }
+}
+
+/// The user's configuration directory for difftastic, usually
+/// `~/.config/difftastic`.
+pub fn config_dir() -> Option<PathBuf> {
+    let config_dir = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+    Some(config_dir.join("difftastic"))
+}
+
+/// The path of the configuration file: `DFT_CONFIG` if set,
+/// otherwise `config.toml` in the configuration directory.
+fn config_path() -> Option<PathBuf> {
+    match env::var_os("DFT_CONFIG") {
+        Some(path) => Some(PathBuf::from(path)),
+        None => Some(config_dir()?.join("config.toml")),
+    }
+}
+
+fn parse_config(src: &str) -> Result<Config, String> {
+    toml::from_str(src).map_err(|e| e.to_string())
+}
+
+/// Read the user's configuration file, or the default configuration
+/// if there isn't one. Terminate the process if the file is invalid.
+pub fn load_or_die() -> Config {
+    let path = match config_path() {
+        Some(path) => path,
+        None => return Config::default(),
+    };
+
+    let src = match fs::read_to_string(&path) {
+        Ok(src) => src,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Config::default(),
+        Err(e) => {
+            eprintln!("error: Could not read {}: {}", path.display(), e);
+            std::process::exit(EXIT_ERROR);
+        }
+    };
+
+    match parse_config(&src) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("error: Invalid configuration in {}: {}", path.display(), e);
+            std::process::exit(EXIT_ERROR);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_parse_config() {
+        let config = parse_config(
+            "display = \"inline\"\ncontext = 5\nskip-unchanged = true\n\n[languages]\nh = \"c\"\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            config,
+            Config {
+                display: Some("inline".into()),
+                context: Some(5),
+                skip_unchanged: true,
+                languages: vec![("h".to_owned(), "c".to_owned())].into_iter().collect(),
+                ..Config::default()
+            }
+        );
+        assert_eq!(
+            config.option_values(),
+            vec![
+                ("display", "inline".to_owned()),
+                ("context", "5".to_owned())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_config_unknown_option() {
+        assert!(parse_config("no-such-option = 1\n").is_err());
+    }
+}
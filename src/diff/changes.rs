@@ -10,7 +10,11 @@ use crate::parse::syntax::Syntax;
 pub enum ChangeKind<'a> {
     Unchanged(&'a Syntax<'a>),
     ReplacedComment(&'a Syntax<'a>, &'a Syntax<'a>),
+    ReplacedString(&'a Syntax<'a>, &'a Syntax<'a>),
     Novel,
+    /// A node that was novel, but has identical content to a novel
+    /// node elsewhere on the other side.
+    Moved(&'a Syntax<'a>),
 }
 
 #[derive(Debug, Default)]
@@ -28,6 +32,43 @@ impl<'a> ChangeMap<'a> {
     }
 }
 
+/// Pair up the children of two lists with the same content.
+/// Unordered children (e.g. JSON object members) may be in a
+/// different order on each side, so pair them by content. Everything
+/// else is paired by position.
+fn paired_children<'a>(
+    node_children: &[&'a Syntax<'a>],
+    opposite_children: &[&'a Syntax<'a>],
+) -> Vec<(&'a Syntax<'a>, &'a Syntax<'a>)> {
+    let mut ordered_opposites = opposite_children.iter().filter(|c| !c.is_unordered());
+    let mut unordered_opposites: FxHashMap<u32, Vec<&'a Syntax<'a>>> = FxHashMap::default();
+    // Iterate in reverse, so popping gives the first opposite child
+    // with this content.
+    for opposite_child in opposite_children.iter().rev() {
+        if opposite_child.is_unordered() {
+            unordered_opposites
+                .entry(opposite_child.content_id())
+                .or_default()
+                .push(opposite_child);
+        }
+    }
+
+    let mut pairs = vec![];
+    for child in node_children {
+        let opposite_child = if child.is_unordered() {
+            unordered_opposites
+                .get_mut(&child.content_id())
+                .and_then(|opposites| opposites.pop())
+        } else {
+            ordered_opposites.next().copied()
+        };
+        if let Some(opposite_child) = opposite_child {
+            pairs.push((*child, opposite_child));
+        }
+    }
+    pairs
+}
+
 pub fn insert_deep_unchanged<'a>(
     node: &'a Syntax<'a>,
     opposite_node: &'a Syntax<'a>,
@@ -46,7 +87,7 @@ pub fn insert_deep_unchanged<'a>(
                 ..
             },
         ) => {
-            for (child, opposite_child) in node_children.iter().zip(opposite_children) {
+            for (child, opposite_child) in paired_children(node_children, opposite_children) {
                 insert_deep_unchanged(child, opposite_child, change_map);
             }
         }
@@ -55,6 +96,33 @@ pub fn insert_deep_unchanged<'a>(
     }
 }
 
+pub fn insert_deep_moved<'a>(
+    node: &'a Syntax<'a>,
+    opposite_node: &'a Syntax<'a>,
+    change_map: &mut ChangeMap<'a>,
+) {
+    change_map.insert(node, ChangeKind::Moved(opposite_node));
+
+    match (node, opposite_node) {
+        (
+            Syntax::List {
+                children: node_children,
+                ..
+            },
+            Syntax::List {
+                children: opposite_children,
+                ..
+            },
+        ) => {
+            for (child, opposite_child) in paired_children(node_children, opposite_children) {
+                insert_deep_moved(child, opposite_child, change_map);
+            }
+        }
+        (Syntax::Atom { .. }, Syntax::Atom { .. }) => {}
+        _ => unreachable!("Moved nodes should be both lists, or both atoms"),
+    }
+}
+
 pub fn insert_deep_novel<'a>(node: &'a Syntax<'a>, change_map: &mut ChangeMap<'a>) {
     change_map.insert(node, ChangeKind::Novel);
 
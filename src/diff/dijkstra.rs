@@ -1,7 +1,14 @@
 //! Implements Dijkstra's algorithm for shortest path, to find an
 //! optimal and readable diff between two ASTs.
-
-use std::{cmp::Reverse, env};
+//!
+//! The search is guided by a lower bound on the remaining cost (A*),
+//! so we can skip vertices that can't be on the shortest route.
+
+use std::{
+    cmp::{max, min, Reverse},
+    env, mem,
+    time::Instant,
+};
 
 use crate::{
     diff::changes::ChangeMap,
@@ -13,33 +20,102 @@ use itertools::Itertools;
 use radix_heap::RadixHeapMap;
 use rustc_hash::FxHashMap;
 
-#[derive(Debug)]
-pub struct ExceededGraphLimit {}
+/// The resources that a graph search may use before we give up.
+#[derive(Debug, Clone, Copy)]
+pub struct SearchLimits {
+    /// The maximum number of vertices to visit.
+    pub graph_limit: usize,
+    /// Give up if the search is still running at this time.
+    pub deadline: Option<Instant>,
+    /// The maximum number of bytes that vertices may use.
+    pub memory_limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExceededLimit {
+    Graph,
+    Time,
+    Memory,
+}
+
+/// How many vertices we visit between checking the time and memory
+/// limits. Checking on every vertex is measurably slower.
+const LIMIT_CHECK_INTERVAL: usize = 1024;
+
+/// When there's a memory limit, reserve at most this fraction of it
+/// for `seen` before we start searching.
+const MEMORY_LIMIT_RESERVE_FRACTION: usize = 8;
+
+/// Roughly how many bytes we need for every vertex we've seen, in
+/// addition to the vertex itself in the arena: its neighbours and its
+/// entries in `seen` and the heap.
+fn bytes_per_vertex() -> usize {
+    mem::size_of::<(&Vertex, Vec<&Vertex>)>()
+        + 4 * mem::size_of::<(Edge, &Vertex)>()
+        + mem::size_of::<(Reverse<u64>, &Vertex)>()
+}
+
+/// The cheapest edge that isn't [`Edge::UnchangedNode`] or exiting a
+/// delimiter, which is [`Edge::EnterUnchangedDelimiter`].
+const MIN_NON_UNCHANGED_COST: u64 = 100;
+
+/// A lower bound on the cost of any route from `v` to the end vertex.
+///
+/// Every remaining node must be consumed by an edge, and every edge
+/// costs at least 1 and consumes at most one node on each side. If
+/// the two sides have a different number of nodes remaining, we also
+/// need at least one edge that isn't [`Edge::UnchangedNode`], which
+/// costs at least 100.
+///
+/// This is consistent: no edge reduces the estimate by more than its
+/// cost. That's required for the priorities to be monotone, as
+/// `RadixHeapMap` requires.
+fn remaining_cost_bound(v: &Vertex) -> u64 {
+    let (lhs_remaining, rhs_remaining) = v.num_remaining();
+
+    let mut bound = max(lhs_remaining, rhs_remaining) as u64;
+    if lhs_remaining != rhs_remaining {
+        bound += MIN_NON_UNCHANGED_COST - 1;
+    }
+    bound
+}
 
 /// Return the shortest route from `start` to the end vertex.
 fn shortest_vertex_path<'a, 'b>(
     start: &'b Vertex<'a, 'b>,
     vertex_arena: &'b Bump,
     size_hint: usize,
-    graph_limit: usize,
-) -> Result<Vec<&'b Vertex<'a, 'b>>, ExceededGraphLimit> {
+    limits: &SearchLimits,
+) -> Result<Vec<&'b Vertex<'a, 'b>>, ExceededLimit> {
     // We want to visit nodes with the shortest distance first, but
     // RadixHeapMap is a max-heap. Ensure nodes are wrapped with
     // Reverse to flip comparisons.
     let mut heap: RadixHeapMap<Reverse<_>, &'b Vertex<'a, 'b>> = RadixHeapMap::new();
 
-    heap.push(Reverse(0), start);
+    heap.push(Reverse(remaining_cost_bound(start)), start);
 
+    // Only reserve a small part of the memory limit up front, so the
+    // reservation itself can't use up the budget.
+    let size_hint = match limits.memory_limit {
+        Some(memory_limit) => min(
+            size_hint,
+            memory_limit / MEMORY_LIMIT_RESERVE_FRACTION / bytes_per_vertex(),
+        ),
+        None => size_hint,
+    };
     let mut seen = FxHashMap::default();
     seen.reserve(size_hint);
 
+    let mut num_visited: usize = 0;
     let end: &'b Vertex<'a, 'b> = loop {
         match heap.pop() {
-            Some((Reverse(distance), current)) => {
+            Some((Reverse(estimate), current)) => {
                 if current.is_end() {
                     break current;
                 }
 
+                let distance = estimate - remaining_cost_bound(current);
+
                 for neighbour in &get_set_neighbours(current, vertex_arena, &mut seen) {
                     let (edge, next) = neighbour;
                     let distance_to_next = distance + edge.cost();
@@ -51,12 +127,28 @@ fn shortest_vertex_path<'a, 'b>(
 
                     if found_shorter_route {
                         next.predecessor.replace(Some((distance_to_next, current)));
-                        heap.push(Reverse(distance_to_next), next);
+                        heap.push(Reverse(distance_to_next + remaining_cost_bound(next)), next);
                     }
                 }
 
-                if seen.len() > graph_limit {
-                    return Err(ExceededGraphLimit {});
+                if seen.len() > limits.graph_limit {
+                    return Err(ExceededLimit::Graph);
+                }
+
+                num_visited += 1;
+                if num_visited % LIMIT_CHECK_INTERVAL == 0 {
+                    if let Some(deadline) = limits.deadline {
+                        if Instant::now() > deadline {
+                            return Err(ExceededLimit::Time);
+                        }
+                    }
+                    if let Some(memory_limit) = limits.memory_limit {
+                        let memory_used =
+                            vertex_arena.allocated_bytes() + seen.len() * bytes_per_vertex();
+                        if memory_used > memory_limit {
+                            return Err(ExceededLimit::Memory);
+                        }
+                    }
                 }
             }
             None => panic!("Ran out of graph nodes before reaching end"),
@@ -66,7 +158,7 @@ fn shortest_vertex_path<'a, 'b>(
     debug!(
         "Saw {} vertices (a Vertex is {} bytes), with {} left on heap.",
         seen.len(),
-        std::mem::size_of::<Vertex>(),
+        mem::size_of::<Vertex>(),
         heap.len(),
     );
 
@@ -109,10 +201,10 @@ fn shortest_path<'a, 'b>(
     start: Vertex<'a, 'b>,
     vertex_arena: &'b Bump,
     size_hint: usize,
-    graph_limit: usize,
-) -> Result<Vec<(Edge, &'b Vertex<'a, 'b>)>, ExceededGraphLimit> {
+    limits: &SearchLimits,
+) -> Result<Vec<(Edge, &'b Vertex<'a, 'b>)>, ExceededLimit> {
     let start: &'b Vertex<'a, 'b> = vertex_arena.alloc(start.clone());
-    let vertex_path = shortest_vertex_path(start, vertex_arena, size_hint, graph_limit)?;
+    let vertex_path = shortest_vertex_path(start, vertex_arena, size_hint, limits)?;
     Ok(shortest_path_with_edges(&vertex_path))
 }
 
@@ -183,8 +275,8 @@ pub fn mark_syntax<'a>(
     lhs_syntax: Option<&'a Syntax<'a>>,
     rhs_syntax: Option<&'a Syntax<'a>>,
     change_map: &mut ChangeMap<'a>,
-    graph_limit: usize,
-) -> Result<(), ExceededGraphLimit> {
+    limits: &SearchLimits,
+) -> Result<(), ExceededLimit> {
     let lhs_node_count = node_count(lhs_syntax) as usize;
     let rhs_node_count = node_count(rhs_syntax) as usize;
     info!(
@@ -204,7 +296,7 @@ pub fn mark_syntax<'a>(
     let start = Vertex::new(lhs_syntax, rhs_syntax);
     let vertex_arena = Bump::new();
 
-    let route = shortest_path(start, &vertex_arena, size_hint, graph_limit)?;
+    let route = shortest_path(start, &vertex_arena, size_hint, limits)?;
 
     let print_length = if env::var("DFT_VERBOSE").is_ok() {
         50
@@ -249,6 +341,12 @@ mod tests {
     use itertools::Itertools;
     use typed_arena::Arena;
 
+    const LIMITS: SearchLimits = SearchLimits {
+        graph_limit: DEFAULT_GRAPH_LIMIT,
+        deadline: None,
+        memory_limit: None,
+    };
+
     fn pos_helper(line: u32) -> Vec<SingleLineSpan> {
         vec![SingleLineSpan {
             line: line.into(),
@@ -276,7 +374,7 @@ mod tests {
 
         let start = Vertex::new(Some(lhs), Some(rhs));
         let vertex_arena = Bump::new();
-        let route = shortest_path(start, &vertex_arena, 0, DEFAULT_GRAPH_LIMIT).unwrap();
+        let route = shortest_path(start, &vertex_arena, 0, &LIMITS).unwrap();
 
         let actions = route.iter().map(|(action, _)| *action).collect_vec();
         assert_eq!(
@@ -287,6 +385,31 @@ mod tests {
         );
     }
 
+    #[test]
+    fn remaining_cost_bound_counts_nodes() {
+        let arena = Arena::new();
+
+        let lhs = vec![
+            Syntax::new_atom(&arena, pos_helper(0), "foo", AtomKind::Normal),
+            Syntax::new_atom(&arena, pos_helper(1), "bar", AtomKind::Normal),
+        ];
+        let rhs = vec![Syntax::new_atom(
+            &arena,
+            pos_helper(0),
+            "foo",
+            AtomKind::Normal,
+        )];
+        init_all_info(&lhs, &rhs);
+
+        // Two LHS nodes remain, and we need a non-unchanged edge
+        // to handle the extra one.
+        let start = Vertex::new(lhs.first().copied(), rhs.first().copied());
+        assert_eq!(remaining_cost_bound(&start), 2 + MIN_NON_UNCHANGED_COST - 1);
+
+        let end = Vertex::new(None, None);
+        assert_eq!(remaining_cost_bound(&end), 0);
+    }
+
     #[test]
     fn extra_atom_lhs() {
         let arena = Arena::new();
@@ -317,7 +440,7 @@ mod tests {
 
         let start = Vertex::new(lhs.get(0).copied(), rhs.get(0).copied());
         let vertex_arena = Bump::new();
-        let route = shortest_path(start, &vertex_arena, 0, DEFAULT_GRAPH_LIMIT).unwrap();
+        let route = shortest_path(start, &vertex_arena, 0, &LIMITS).unwrap();
 
         let actions = route.iter().map(|(action, _)| *action).collect_vec();
         assert_eq!(
@@ -363,7 +486,7 @@ mod tests {
 
         let start = Vertex::new(lhs.get(0).copied(), rhs.get(0).copied());
         let vertex_arena = Bump::new();
-        let route = shortest_path(start, &vertex_arena, 0, DEFAULT_GRAPH_LIMIT).unwrap();
+        let route = shortest_path(start, &vertex_arena, 0, &LIMITS).unwrap();
 
         let actions = route.iter().map(|(action, _)| *action).collect_vec();
         assert_eq!(
@@ -416,7 +539,7 @@ mod tests {
 
         let start = Vertex::new(lhs.get(0).copied(), rhs.get(0).copied());
         let vertex_arena = Bump::new();
-        let route = shortest_path(start, &vertex_arena, 0, DEFAULT_GRAPH_LIMIT).unwrap();
+        let route = shortest_path(start, &vertex_arena, 0, &LIMITS).unwrap();
 
         let actions = route.iter().map(|(action, _)| *action).collect_vec();
         assert_eq!(
@@ -456,7 +579,7 @@ mod tests {
 
         let start = Vertex::new(lhs.get(0).copied(), rhs.get(0).copied());
         let vertex_arena = Bump::new();
-        let route = shortest_path(start, &vertex_arena, 0, DEFAULT_GRAPH_LIMIT).unwrap();
+        let route = shortest_path(start, &vertex_arena, 0, &LIMITS).unwrap();
 
         let actions = route.iter().map(|(action, _)| *action).collect_vec();
         assert_eq!(
@@ -500,7 +623,7 @@ mod tests {
 
         let start = Vertex::new(lhs.get(0).copied(), rhs.get(0).copied());
         let vertex_arena = Bump::new();
-        let route = shortest_path(start, &vertex_arena, 0, DEFAULT_GRAPH_LIMIT).unwrap();
+        let route = shortest_path(start, &vertex_arena, 0, &LIMITS).unwrap();
 
         let actions = route.iter().map(|(action, _)| *action).collect_vec();
         assert_eq!(
@@ -542,7 +665,7 @@ mod tests {
 
         let start = Vertex::new(lhs.get(0).copied(), rhs.get(0).copied());
         let vertex_arena = Bump::new();
-        let route = shortest_path(start, &vertex_arena, 0, DEFAULT_GRAPH_LIMIT).unwrap();
+        let route = shortest_path(start, &vertex_arena, 0, &LIMITS).unwrap();
 
         let actions = route.iter().map(|(action, _)| *action).collect_vec();
         assert_eq!(
@@ -583,7 +706,7 @@ mod tests {
 
         let start = Vertex::new(lhs.get(0).copied(), rhs.get(0).copied());
         let vertex_arena = Bump::new();
-        let route = shortest_path(start, &vertex_arena, 0, DEFAULT_GRAPH_LIMIT).unwrap();
+        let route = shortest_path(start, &vertex_arena, 0, &LIMITS).unwrap();
 
         let actions = route.iter().map(|(action, _)| *action).collect_vec();
         assert_eq!(
@@ -594,6 +717,38 @@ mod tests {
         );
     }
 
+    #[test]
+    fn replace_similar_string() {
+        let arena = Arena::new();
+
+        let lhs = vec![Syntax::new_atom(
+            &arena,
+            pos_helper(1),
+            "\"the quick brown fox\"",
+            AtomKind::String,
+        )];
+
+        let rhs = vec![Syntax::new_atom(
+            &arena,
+            pos_helper(1),
+            "\"the quick brown cat\"",
+            AtomKind::String,
+        )];
+        init_all_info(&lhs, &rhs);
+
+        let start = Vertex::new(lhs.first().copied(), rhs.first().copied());
+        let vertex_arena = Bump::new();
+        let route = shortest_path(start, &vertex_arena, 0, &LIMITS).unwrap();
+
+        let actions = route.iter().map(|(action, _)| *action).collect_vec();
+        assert_eq!(
+            actions,
+            vec![ReplacedString {
+                levenshtein_pct: 86
+            }]
+        );
+    }
+
     #[test]
     fn replace_very_different_comment() {
         let arena = Arena::new();
@@ -615,7 +770,7 @@ mod tests {
 
         let start = Vertex::new(lhs.get(0).copied(), rhs.get(0).copied());
         let vertex_arena = Bump::new();
-        let route = shortest_path(start, &vertex_arena, 0, DEFAULT_GRAPH_LIMIT).unwrap();
+        let route = shortest_path(start, &vertex_arena, 0, &LIMITS).unwrap();
 
         let actions = route.iter().map(|(action, _)| *action).collect_vec();
         assert_eq!(
@@ -655,7 +810,7 @@ mod tests {
 
         let start = Vertex::new(lhs.get(0).copied(), rhs.get(0).copied());
         let vertex_arena = Bump::new();
-        let route = shortest_path(start, &vertex_arena, 0, DEFAULT_GRAPH_LIMIT).unwrap();
+        let route = shortest_path(start, &vertex_arena, 0, &LIMITS).unwrap();
 
         let actions = route.iter().map(|(action, _)| *action).collect_vec();
         assert_eq!(
@@ -680,7 +835,7 @@ mod tests {
         init_all_info(&[lhs], &[rhs]);
 
         let mut change_map = ChangeMap::default();
-        mark_syntax(Some(lhs), Some(rhs), &mut change_map, DEFAULT_GRAPH_LIMIT).unwrap();
+        mark_syntax(Some(lhs), Some(rhs), &mut change_map, &LIMITS).unwrap();
 
         assert_eq!(change_map.get(lhs), Some(ChangeKind::Unchanged(rhs)));
         assert_eq!(change_map.get(rhs), Some(ChangeKind::Unchanged(lhs)));
@@ -694,8 +849,96 @@ mod tests {
         init_all_info(&[lhs], &[rhs]);
 
         let mut change_map = ChangeMap::default();
-        mark_syntax(Some(lhs), Some(rhs), &mut change_map, DEFAULT_GRAPH_LIMIT).unwrap();
+        mark_syntax(Some(lhs), Some(rhs), &mut change_map, &LIMITS).unwrap();
         assert_eq!(change_map.get(lhs), Some(ChangeKind::Novel));
         assert_eq!(change_map.get(rhs), Some(ChangeKind::Novel));
     }
+
+    fn atoms_helper<'a>(arena: &'a Arena<Syntax<'a>>, contents: &[String]) -> Vec<&'a Syntax<'a>> {
+        contents
+            .iter()
+            .enumerate()
+            .map(|(i, content)| {
+                Syntax::new_atom(arena, pos_helper(i as u32), content, AtomKind::Normal)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn memory_limit_small_diff() {
+        let lhs_contents: Vec<String> = (0..2000).map(|i| format!("a{}", i)).collect();
+        let mut rhs_contents = lhs_contents.clone();
+        rhs_contents[1000] = "changed".to_owned();
+
+        let arena = Arena::new();
+        let lhs = atoms_helper(&arena, &lhs_contents);
+        let rhs = atoms_helper(&arena, &rhs_contents);
+        init_all_info(&lhs, &rhs);
+
+        // The quadratic size hint is far beyond this limit, but
+        // we only need to visit a few thousand vertices.
+        let limits = SearchLimits {
+            memory_limit: Some(4 * 1024 * 1024),
+            ..LIMITS
+        };
+        let mut change_map = ChangeMap::default();
+        mark_syntax(
+            lhs.first().copied(),
+            rhs.first().copied(),
+            &mut change_map,
+            &limits,
+        )
+        .unwrap();
+
+        assert_eq!(change_map.get(lhs[0]), Some(ChangeKind::Unchanged(rhs[0])));
+        assert_eq!(change_map.get(lhs[1000]), Some(ChangeKind::Novel));
+    }
+
+    #[test]
+    fn memory_limit_exceeded() {
+        let lhs_contents: Vec<String> = (0..200).map(|i| format!("a{}", i)).collect();
+        let rhs_contents: Vec<String> = (0..200).map(|i| format!("b{}", i)).collect();
+
+        let arena = Arena::new();
+        let lhs = atoms_helper(&arena, &lhs_contents);
+        let rhs = atoms_helper(&arena, &rhs_contents);
+        init_all_info(&lhs, &rhs);
+
+        let limits = SearchLimits {
+            memory_limit: Some(64 * 1024),
+            ..LIMITS
+        };
+        let mut change_map = ChangeMap::default();
+        let res = mark_syntax(
+            lhs.first().copied(),
+            rhs.first().copied(),
+            &mut change_map,
+            &limits,
+        );
+        assert_eq!(res, Err(ExceededLimit::Memory));
+    }
+
+    #[test]
+    fn time_limit_exceeded() {
+        let lhs_contents: Vec<String> = (0..200).map(|i| format!("a{}", i)).collect();
+        let rhs_contents: Vec<String> = (0..200).map(|i| format!("b{}", i)).collect();
+
+        let arena = Arena::new();
+        let lhs = atoms_helper(&arena, &lhs_contents);
+        let rhs = atoms_helper(&arena, &rhs_contents);
+        init_all_info(&lhs, &rhs);
+
+        let limits = SearchLimits {
+            deadline: Some(Instant::now()),
+            ..LIMITS
+        };
+        let mut change_map = ChangeMap::default();
+        let res = mark_syntax(
+            lhs.first().copied(),
+            rhs.first().copied(),
+            &mut change_map,
+            &limits,
+        );
+        assert_eq!(res, Err(ExceededLimit::Time));
+    }
 }
@@ -0,0 +1,170 @@
+//! A cheap tree diff, used for sections of a file that are too large
+//! for the graph search.
+//!
+//! This is a linear diff of each level of the tree, so it can't find
+//! the minimal diff. It's still much more precise than falling back
+//! to a line-oriented diff of the whole file.
+
+use crate::diff::changes::{insert_deep_novel, insert_deep_unchanged, ChangeKind, ChangeMap};
+use crate::diff::myers_diff::{self, DiffResult};
+use crate::parse::syntax::Syntax;
+
+/// Set [`ChangeKind`] on every node in `lhs_nodes` and `rhs_nodes`,
+/// by diffing the content of sibling nodes and recursing into lists
+/// that were replaced.
+pub fn mark_syntax<'a>(
+    lhs_nodes: &[&'a Syntax<'a>],
+    rhs_nodes: &[&'a Syntax<'a>],
+    change_map: &mut ChangeMap<'a>,
+) {
+    let lhs_ids: Vec<u32> = lhs_nodes.iter().map(|node| node.content_id()).collect();
+    let rhs_ids: Vec<u32> = rhs_nodes.iter().map(|node| node.content_id()).collect();
+
+    let mut lhs_i = 0;
+    let mut rhs_i = 0;
+    let mut lhs_replaced = vec![];
+    let mut rhs_replaced = vec![];
+    for diff_res in myers_diff::slice(&lhs_ids, &rhs_ids) {
+        match diff_res {
+            DiffResult::Left(_) => {
+                lhs_replaced.push(lhs_nodes[lhs_i]);
+                lhs_i += 1;
+            }
+            DiffResult::Right(_) => {
+                rhs_replaced.push(rhs_nodes[rhs_i]);
+                rhs_i += 1;
+            }
+            DiffResult::Both(_, _) => {
+                mark_replaced(&lhs_replaced, &rhs_replaced, change_map);
+                lhs_replaced.clear();
+                rhs_replaced.clear();
+
+                let lhs = lhs_nodes[lhs_i];
+                let rhs = rhs_nodes[rhs_i];
+                insert_deep_unchanged(lhs, rhs, change_map);
+                insert_deep_unchanged(rhs, lhs, change_map);
+                lhs_i += 1;
+                rhs_i += 1;
+            }
+        }
+    }
+    mark_replaced(&lhs_replaced, &rhs_replaced, change_map);
+}
+
+fn same_delimiters(lhs: &Syntax, rhs: &Syntax) -> bool {
+    match (lhs, rhs) {
+        (
+            Syntax::List {
+                open_content: lhs_open,
+                close_content: lhs_close,
+                ..
+            },
+            Syntax::List {
+                open_content: rhs_open,
+                close_content: rhs_close,
+                ..
+            },
+        ) => lhs_open == rhs_open && lhs_close == rhs_close,
+        _ => false,
+    }
+}
+
+/// Mark nodes that occur between the same unchanged siblings. Lists
+/// with the same delimiters are paired up in order, and we diff their
+/// children. Everything else is novel.
+fn mark_replaced<'a>(
+    lhs_nodes: &[&'a Syntax<'a>],
+    rhs_nodes: &[&'a Syntax<'a>],
+    change_map: &mut ChangeMap<'a>,
+) {
+    let mut rhs_is_paired = vec![false; rhs_nodes.len()];
+    let mut rhs_start = 0;
+
+    for lhs in lhs_nodes {
+        let rhs_i = (rhs_start..rhs_nodes.len()).find(|i| same_delimiters(lhs, rhs_nodes[*i]));
+        match (rhs_i, lhs) {
+            (
+                Some(rhs_i),
+                Syntax::List {
+                    children: lhs_children,
+                    ..
+                },
+            ) => {
+                let rhs = rhs_nodes[rhs_i];
+                rhs_is_paired[rhs_i] = true;
+                rhs_start = rhs_i + 1;
+
+                change_map.insert(lhs, ChangeKind::Unchanged(rhs));
+                change_map.insert(rhs, ChangeKind::Unchanged(lhs));
+                if let Syntax::List {
+                    children: rhs_children,
+                    ..
+                } = rhs
+                {
+                    mark_syntax(lhs_children, rhs_children, change_map);
+                }
+            }
+            _ => insert_deep_novel(lhs, change_map),
+        }
+    }
+
+    for (rhs, is_paired) in rhs_nodes.iter().zip(rhs_is_paired) {
+        if !is_paired {
+            insert_deep_novel(rhs, change_map);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        parse::guess_language,
+        parse::tree_sitter_parser::{from_language, parse},
+        syntax::init_all_info,
+    };
+    use typed_arena::Arena;
+
+    #[test]
+    fn test_mark_syntax_recurses_into_replaced_lists() {
+        let arena = Arena::new();
+        let config = from_language(guess_language::Language::EmacsLisp);
+
+        let lhs_nodes = parse(&arena, "(a b) (c d)", &config);
+        let rhs_nodes = parse(&arena, "(a x) (c d)", &config);
+        init_all_info(&lhs_nodes, &rhs_nodes);
+
+        let mut change_map = ChangeMap::default();
+        mark_syntax(&lhs_nodes, &rhs_nodes, &mut change_map);
+
+        assert_eq!(
+            change_map.get(lhs_nodes[0]),
+            Some(ChangeKind::Unchanged(rhs_nodes[0]))
+        );
+        assert_eq!(
+            change_map.get(lhs_nodes[1]),
+            Some(ChangeKind::Unchanged(rhs_nodes[1]))
+        );
+
+        match (lhs_nodes[0], rhs_nodes[0]) {
+            (
+                Syntax::List {
+                    children: lhs_children,
+                    ..
+                },
+                Syntax::List {
+                    children: rhs_children,
+                    ..
+                },
+            ) => {
+                assert_eq!(
+                    change_map.get(lhs_children[0]),
+                    Some(ChangeKind::Unchanged(rhs_children[0]))
+                );
+                assert_eq!(change_map.get(lhs_children[1]), Some(ChangeKind::Novel));
+                assert_eq!(change_map.get(rhs_children[1]), Some(ChangeKind::Novel));
+            }
+            _ => unreachable!(),
+        }
+    }
+}
@@ -214,6 +214,30 @@ fn try_pop_rhs<'a>(
     }
 }
 
+/// The most recently entered LHS and RHS delimiters.
+fn innermost_parents<'a>(
+    entered: &Stack<EnteredDelimiter<'a>>,
+) -> (Option<&'a Syntax<'a>>, Option<&'a Syntax<'a>>) {
+    match entered.peek() {
+        Some(EnteredDelimiter::PopBoth((lhs_delim, rhs_delim))) => {
+            (Some(lhs_delim), Some(rhs_delim))
+        }
+        Some(EnteredDelimiter::PopEither((lhs_delims, rhs_delims))) => {
+            // We never have two PopEither entries adjacent on the
+            // stack, so the next entry is a PopBoth if present.
+            let (outer_lhs, outer_rhs) = match entered.pop() {
+                Some(rest) => innermost_parents(&rest),
+                None => (None, None),
+            };
+            (
+                lhs_delims.peek().copied().or(outer_lhs),
+                rhs_delims.peek().copied().or(outer_rhs),
+            )
+        }
+        None => (None, None),
+    }
+}
+
 fn push_lhs_delimiter<'a>(
     entered: &Stack<EnteredDelimiter<'a>>,
     delimiter: &'a Syntax<'a>,
@@ -249,6 +273,28 @@ impl<'a, 'b> Vertex<'a, 'b> {
         self.lhs_syntax.is_none() && self.rhs_syntax.is_none() && self.parents.is_empty()
     }
 
+    /// The number of nodes on the LHS and RHS that we haven't
+    /// reached yet, not counting the descendants of those nodes.
+    ///
+    /// Every edge reduces each count by at most one, and only
+    /// entering a delimiter or marking a node as novel changes the
+    /// difference between them.
+    pub fn num_remaining(&self) -> (usize, usize) {
+        let (lhs_parent, rhs_parent) = innermost_parents(&self.parents);
+
+        let lhs_remaining = match (self.lhs_syntax, lhs_parent) {
+            (Some(node), _) => node.num_after() + 1,
+            (None, Some(parent)) => parent.num_after(),
+            (None, None) => 0,
+        };
+        let rhs_remaining = match (self.rhs_syntax, rhs_parent) {
+            (Some(node), _) => node.num_after() + 1,
+            (None, Some(parent)) => parent.num_after(),
+            (None, None) => 0,
+        };
+        (lhs_remaining, rhs_remaining)
+    }
+
     pub fn new(lhs_syntax: Option<&'a Syntax<'a>>, rhs_syntax: Option<&'a Syntax<'a>>) -> Self {
         let parents = Stack::new();
         Vertex {
@@ -281,6 +327,9 @@ pub enum Edge {
     ReplacedComment {
         levenshtein_pct: u8,
     },
+    ReplacedString {
+        levenshtein_pct: u8,
+    },
     NovelAtomLHS {
         contiguous: bool,
         probably_punctuation: bool,
@@ -325,8 +374,11 @@ impl Edge {
                 100 + min(40, u64::from(depth_difference))
             }
 
-            // Replacing a comment is better than treating it as novel.
-            ReplacedComment { levenshtein_pct } => 150 + u64::from(100 - levenshtein_pct),
+            // Replacing a comment or a string is better than treating
+            // it as novel.
+            ReplacedComment { levenshtein_pct } | ReplacedString { levenshtein_pct } => {
+                150 + u64::from(100 - levenshtein_pct)
+            }
 
             // Otherwise, we've added/removed a node.
             NovelAtomLHS {
@@ -572,23 +624,32 @@ pub fn get_set_neighbours<'syn, 'b>(
         if let (
             Syntax::Atom {
                 content: lhs_content,
-                kind: AtomKind::Comment,
+                kind: lhs_kind,
                 ..
             },
             Syntax::Atom {
                 content: rhs_content,
-                kind: AtomKind::Comment,
+                kind: rhs_kind,
                 ..
             },
         ) = (lhs_syntax, rhs_syntax)
         {
-            // Both sides are comments and their content is reasonably
-            // similar.
-            if lhs_content != rhs_content {
+            // Both sides are comments, or both sides are strings, and
+            // their content is different. We only show the words that
+            // changed if the content is reasonably similar, see
+            // populate_change_map.
+            let is_replaceable =
+                lhs_kind == rhs_kind && matches!(lhs_kind, AtomKind::Comment | AtomKind::String);
+            if is_replaceable && lhs_content != rhs_content {
                 let levenshtein_pct =
                     (normalized_levenshtein(lhs_content, rhs_content) * 100.0).round() as u8;
+                let edge = if *lhs_kind == AtomKind::Comment {
+                    ReplacedComment { levenshtein_pct }
+                } else {
+                    ReplacedString { levenshtein_pct }
+                };
                 res.push((
-                    ReplacedComment { levenshtein_pct },
+                    edge,
                     allocate_if_new(
                         Vertex {
                             neighbours: RefCell::new(None),
@@ -758,6 +819,18 @@ pub fn populate_change_map<'a, 'b>(
                     change_map.insert(rhs, ChangeKind::Novel);
                 }
             }
+            ReplacedString { levenshtein_pct } => {
+                let lhs = v.lhs_syntax.unwrap();
+                let rhs = v.rhs_syntax.unwrap();
+
+                if *levenshtein_pct > 40 {
+                    change_map.insert(lhs, ChangeKind::ReplacedString(lhs, rhs));
+                    change_map.insert(rhs, ChangeKind::ReplacedString(rhs, lhs));
+                } else {
+                    change_map.insert(lhs, ChangeKind::Novel);
+                    change_map.insert(rhs, ChangeKind::Novel);
+                }
+            }
             NovelAtomLHS { .. } | EnterNovelDelimiterLHS { .. } => {
                 let lhs = v.lhs_syntax.unwrap();
                 change_map.insert(lhs, ChangeKind::Novel);
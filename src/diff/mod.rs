@@ -1,6 +1,8 @@
 pub mod changes;
 pub mod dijkstra;
+pub mod fallback;
 mod graph;
+pub mod moves;
 pub mod myers_diff;
 pub mod sliders;
 mod stack;
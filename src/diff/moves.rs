@@ -0,0 +1,163 @@
+//! Detect novel subtrees that have moved.
+//!
+//! After diffing, code that has been reordered (e.g. swapping two
+//! functions) shows up as a novel subtree on both sides. If a novel
+//! subtree on the LHS has identical content to a novel subtree on
+//! the RHS, and that content occurs exactly once on each side, we
+//! mark the pair as moved.
+
+use rustc_hash::FxHashMap;
+
+use crate::{
+    diff::changes::{insert_deep_moved, ChangeKind, ChangeMap},
+    parse::syntax::Syntax,
+};
+
+/// The minimum number of nodes in a subtree for it to be considered
+/// moved. Small subtrees (e.g. a single identifier) often have unique
+/// content by chance, and showing them as moved is noise.
+const MIN_MOVED_SIZE: usize = 8;
+
+/// Add every subtree in `nodes` that is entirely novel and large
+/// enough to be considered moved to `candidates`, keyed by
+/// `content_id`.
+///
+/// Returns whether all of `nodes` are novel, along with the total
+/// number of nodes.
+fn find_candidates<'a>(
+    nodes: &[&'a Syntax<'a>],
+    change_map: &ChangeMap<'a>,
+    candidates: &mut FxHashMap<u32, &'a Syntax<'a>>,
+) -> (bool, usize) {
+    let mut all_novel = true;
+    let mut total_size = 0;
+
+    for node in nodes {
+        let mut is_novel = matches!(change_map.get(node), Some(ChangeKind::Novel));
+        let mut size = 1;
+
+        if let Syntax::List { children, .. } = node {
+            let (children_novel, children_size) = find_candidates(children, change_map, candidates);
+            is_novel = is_novel && children_novel;
+            size += children_size;
+        }
+
+        if is_novel && size >= MIN_MOVED_SIZE && node.content_is_unique() {
+            candidates.insert(node.content_id(), node);
+        }
+
+        all_novel = all_novel && is_novel;
+        total_size += size;
+    }
+
+    (all_novel, total_size)
+}
+
+/// Mark the outermost LHS candidates that have a matching RHS
+/// candidate as moved.
+fn mark_outermost<'a>(
+    nodes: &[&'a Syntax<'a>],
+    lhs_candidates: &FxHashMap<u32, &'a Syntax<'a>>,
+    rhs_candidates: &FxHashMap<u32, &'a Syntax<'a>>,
+    change_map: &mut ChangeMap<'a>,
+) {
+    for node in nodes {
+        let is_candidate = lhs_candidates
+            .get(&node.content_id())
+            .map_or(false, |candidate| candidate.id() == node.id());
+
+        if is_candidate {
+            if let Some(opposite) = rhs_candidates.get(&node.content_id()) {
+                insert_deep_moved(node, opposite, change_map);
+                insert_deep_moved(opposite, node, change_map);
+                continue;
+            }
+        }
+
+        if let Syntax::List { children, .. } = node {
+            mark_outermost(children, lhs_candidates, rhs_candidates, change_map);
+        }
+    }
+}
+
+/// Find novel subtrees on the LHS that have identical content to a
+/// novel subtree on the RHS, and mark both as moved.
+pub fn mark_moved<'a>(
+    lhs_nodes: &[&'a Syntax<'a>],
+    rhs_nodes: &[&'a Syntax<'a>],
+    change_map: &mut ChangeMap<'a>,
+) {
+    let mut lhs_candidates = FxHashMap::default();
+    find_candidates(lhs_nodes, change_map, &mut lhs_candidates);
+
+    let mut rhs_candidates = FxHashMap::default();
+    find_candidates(rhs_nodes, change_map, &mut rhs_candidates);
+
+    if lhs_candidates.is_empty() || rhs_candidates.is_empty() {
+        return;
+    }
+
+    mark_outermost(lhs_nodes, &lhs_candidates, &rhs_candidates, change_map);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        diff::changes::insert_deep_novel,
+        parse::guess_language,
+        parse::syntax::init_all_info,
+        parse::tree_sitter_parser::{from_language, parse},
+    };
+    use typed_arena::Arena;
+
+    #[test]
+    fn test_swapped_functions_are_moved() {
+        let arena = Arena::new();
+        let config = from_language(guess_language::Language::Python);
+
+        let lhs = parse(
+            &arena,
+            "def foo(x):\n    return x + 1\n\ndef bar(y):\n    return y * 2\n",
+            &config,
+        );
+        let rhs = parse(
+            &arena,
+            "def bar(y):\n    return y * 2\n\ndef foo(x):\n    return x + 1\n",
+            &config,
+        );
+        init_all_info(&lhs, &rhs);
+
+        // Treat everything as novel, as if the diff couldn't match
+        // anything.
+        let mut change_map = ChangeMap::default();
+        for node in lhs.iter().chain(rhs.iter()) {
+            insert_deep_novel(node, &mut change_map);
+        }
+
+        mark_moved(&lhs, &rhs, &mut change_map);
+
+        assert_eq!(change_map.get(lhs[0]), Some(ChangeKind::Moved(rhs[1])));
+        assert_eq!(change_map.get(rhs[1]), Some(ChangeKind::Moved(lhs[0])));
+        assert_eq!(change_map.get(lhs[1]), Some(ChangeKind::Moved(rhs[0])));
+    }
+
+    #[test]
+    fn test_small_subtrees_are_not_moved() {
+        let arena = Arena::new();
+        let config = from_language(guess_language::Language::Python);
+
+        let lhs = parse(&arena, "foo(1)\n", &config);
+        let rhs = parse(&arena, "bar()\nfoo(1)\n", &config);
+        init_all_info(&lhs, &rhs);
+
+        let mut change_map = ChangeMap::default();
+        for node in lhs.iter().chain(rhs.iter()) {
+            insert_deep_novel(node, &mut change_map);
+        }
+
+        mark_moved(&lhs, &rhs, &mut change_map);
+
+        assert_eq!(change_map.get(lhs[0]), Some(ChangeKind::Novel));
+    }
+}
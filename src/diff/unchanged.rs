@@ -3,8 +3,8 @@
 
 use std::collections::HashSet;
 
-use crate::diff::changes::{insert_deep_unchanged, ChangeKind, ChangeMap};
-use crate::diff::myers_diff;
+use crate::diff::changes::{insert_deep_novel, insert_deep_unchanged, ChangeKind, ChangeMap};
+use crate::diff::{fallback, myers_diff};
 
 use crate::parse::syntax::Syntax;
 
@@ -440,10 +440,115 @@ fn shrink_unchanged_at_ends<'a>(
     }
 }
 
+/// The content of the first atom in `node`, which is usually the key
+/// of a key-value pair.
+fn first_atom_content<'a>(node: &'a Syntax<'a>) -> Option<&'a str> {
+    match node {
+        Syntax::List { children, .. } => children.first().and_then(|c| first_atom_content(c)),
+        Syntax::Atom { content, .. } => Some(content),
+    }
+}
+
+/// Has the diff paired `node` with a node on the other side that has
+/// the same first atom (e.g. the same key)?
+fn is_paired_by_key<'a>(node: &'a Syntax<'a>, change_map: &ChangeMap<'a>) -> bool {
+    match change_map.get(node) {
+        Some(ChangeKind::Unchanged(opposite)) => {
+            first_atom_content(node) == first_atom_content(opposite)
+        }
+        _ => false,
+    }
+}
+
+/// Pair up the unordered children in `lhs_nodes` and `rhs_nodes`
+/// that the diff couldn't pair by key, because they were reordered.
+/// Children with the same content are unchanged. Children with the
+/// same first atom (e.g. the same key) are diffed against each other.
+/// Anything else is novel.
+fn mark_reordered_siblings<'a>(
+    lhs_nodes: &[&'a Syntax<'a>],
+    rhs_nodes: &[&'a Syntax<'a>],
+    change_map: &mut ChangeMap<'a>,
+) {
+    let unpaired = |nodes: &[&'a Syntax<'a>], change_map: &ChangeMap<'a>| {
+        nodes
+            .iter()
+            .copied()
+            .filter(|node| node.is_unordered() && !is_paired_by_key(node, change_map))
+            .collect::<Vec<_>>()
+    };
+    let lhs_unpaired = unpaired(lhs_nodes, change_map);
+    let mut rhs_unpaired = unpaired(rhs_nodes, change_map);
+    if lhs_unpaired.is_empty() || rhs_unpaired.is_empty() {
+        return;
+    }
+
+    // Discard any partial matches between nodes with different keys.
+    for node in lhs_unpaired.iter().chain(rhs_unpaired.iter()) {
+        insert_deep_novel(node, change_map);
+    }
+
+    let mut lhs_unmatched = vec![];
+    for lhs_node in lhs_unpaired {
+        match rhs_unpaired
+            .iter()
+            .position(|rhs_node| rhs_node.content_id() == lhs_node.content_id())
+        {
+            Some(i) => {
+                let rhs_node = rhs_unpaired.remove(i);
+                insert_deep_unchanged(lhs_node, rhs_node, change_map);
+                insert_deep_unchanged(rhs_node, lhs_node, change_map);
+            }
+            None => lhs_unmatched.push(lhs_node),
+        }
+    }
+
+    for lhs_node in lhs_unmatched {
+        let key = first_atom_content(lhs_node);
+        if let Some(i) = rhs_unpaired
+            .iter()
+            .position(|rhs_node| key.is_some() && first_atom_content(rhs_node) == key)
+        {
+            let rhs_node = rhs_unpaired.remove(i);
+            fallback::mark_syntax(&[lhs_node], &[rhs_node], change_map);
+        }
+    }
+}
+
+/// Unordered children (e.g. JSON object members) are diffed in
+/// source order, so reordering them makes them novel on both sides.
+/// Find these children in lists that are otherwise unchanged, and
+/// pair them up again.
+pub fn mark_reordered<'a>(
+    lhs_nodes: &[&'a Syntax<'a>],
+    rhs_nodes: &[&'a Syntax<'a>],
+    change_map: &mut ChangeMap<'a>,
+) {
+    mark_reordered_siblings(lhs_nodes, rhs_nodes, change_map);
+
+    for lhs_node in lhs_nodes {
+        if let (
+            Syntax::List {
+                children: lhs_children,
+                ..
+            },
+            Some(ChangeKind::Unchanged(Syntax::List {
+                children: rhs_children,
+                ..
+            })),
+        ) = (lhs_node, change_map.get(lhs_node))
+        {
+            mark_reordered(lhs_children, rhs_children, change_map);
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::{
+        diff::dijkstra::{self, SearchLimits},
+        options::DEFAULT_GRAPH_LIMIT,
         parse::guess_language,
         parse::tree_sitter_parser::{from_language, parse},
         syntax::init_all_info,
@@ -777,4 +882,72 @@ mod tests {
             2
         );
     }
+
+    fn novel_atoms<'a>(nodes: &[&'a Syntax<'a>], change_map: &ChangeMap<'a>) -> Vec<&'a str> {
+        let mut novel = vec![];
+        for node in nodes {
+            match node {
+                Syntax::List { children, .. } => novel.extend(novel_atoms(children, change_map)),
+                Syntax::Atom { content, .. } => {
+                    if change_map.get(node) == Some(ChangeKind::Novel) {
+                        novel.push(content.as_str());
+                    }
+                }
+            }
+        }
+        novel
+    }
+
+    #[test]
+    fn test_reordered_members_are_unchanged() {
+        let arena = Arena::new();
+        let config = from_language(guess_language::Language::Json);
+
+        let lhs_nodes = parse(&arena, "{\"a\": 1, \"b\": 2}", &config);
+        let rhs_nodes = parse(&arena, "{\"b\": 2, \"a\": 1}", &config);
+        init_all_info(&lhs_nodes, &rhs_nodes);
+
+        let mut change_map = ChangeMap::default();
+        let res = mark_unchanged(&lhs_nodes, &rhs_nodes, &mut change_map);
+        assert!(res.is_empty());
+
+        // Each member is paired with the member with the same key.
+        let lhs_first_member = match lhs_nodes[0] {
+            Syntax::List { children, .. } => children[0],
+            _ => unreachable!(),
+        };
+        match change_map.get(lhs_first_member) {
+            Some(ChangeKind::Unchanged(opposite)) => {
+                assert_eq!(first_atom_content(opposite), Some("\"a\""));
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_mark_reordered_with_changed_value() {
+        let arena = Arena::new();
+        let config = from_language(guess_language::Language::Json);
+
+        let lhs_nodes = parse(&arena, "{\"a\": 1, \"b\": 2}", &config);
+        let rhs_nodes = parse(&arena, "{\"b\": 3, \"a\": 1}", &config);
+        init_all_info(&lhs_nodes, &rhs_nodes);
+
+        let mut change_map = ChangeMap::default();
+        dijkstra::mark_syntax(
+            lhs_nodes.first().copied(),
+            rhs_nodes.first().copied(),
+            &mut change_map,
+            &SearchLimits {
+                graph_limit: DEFAULT_GRAPH_LIMIT,
+                deadline: None,
+                memory_limit: None,
+            },
+        )
+        .unwrap();
+        mark_reordered(&lhs_nodes, &rhs_nodes, &mut change_map);
+
+        assert_eq!(novel_atoms(&lhs_nodes, &change_map), vec!["2"]);
+        assert_eq!(novel_atoms(&rhs_nodes, &change_map), vec!["3"]);
+    }
 }
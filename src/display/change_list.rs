@@ -0,0 +1,73 @@
+//! A list of files grouped by whether they have syntactic changes,
+//! or only formatting changes.
+
+use crate::{
+    options::DisplayOptions,
+    summary::{ChangeStatus, DiffResult},
+};
+
+fn format_change_list(summaries: &[DiffResult], display_options: &DisplayOptions) -> Vec<String> {
+    let mut groups = vec![
+        ("Syntactic changes:", ChangeStatus::Changed),
+        ("Formatting changes only:", ChangeStatus::FormattingOnly),
+    ];
+    if display_options.print_unchanged {
+        groups.push(("Unchanged:", ChangeStatus::Unchanged));
+    }
+
+    let mut lines = vec![];
+    for (heading, status) in groups {
+        let paths: Vec<String> = summaries
+            .iter()
+            .filter(|summary| summary.change_status() == status)
+            .map(|summary| summary.display_path(display_options.in_vcs))
+            .collect();
+        if paths.is_empty() {
+            continue;
+        }
+
+        if !lines.is_empty() {
+            lines.push("".to_owned());
+        }
+        lines.push(heading.to_owned());
+        lines.extend(paths.into_iter().map(|path| format!("  {}", path)));
+    }
+
+    if lines.is_empty() {
+        lines.push("No changes.".to_owned());
+    }
+    lines
+}
+
+pub fn print(summaries: &[DiffResult], display_options: &DisplayOptions) {
+    for line in format_change_list(summaries, display_options) {
+        println!("{}", line);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        options::DisplayMode,
+        test_helpers::{display_options, text_result},
+    };
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_format_change_list() {
+        let display_options = DisplayOptions {
+            print_unchanged: false,
+            ..display_options(DisplayMode::ChangeList)
+        };
+
+        let summaries = vec![
+            text_result("a.js", "foo(1,2)", "foo(1, 2)"),
+            text_result("b.js", "foo()", "foo()"),
+        ];
+        assert_eq!(
+            format_change_list(&summaries, &display_options),
+            vec!["Formatting changes only:", "  a.js"]
+        );
+    }
+}
@@ -0,0 +1,426 @@
+//! Self-contained HTML display of diffs.
+//!
+//! This produces a single page with inline CSS and no external
+//! resources, so it can be attached to CI artifacts or emails.
+
+use rustc_hash::FxHashMap;
+use std::{cmp::min, collections::HashSet, fmt::Write};
+
+use crate::{
+    constants::Side,
+    display::{
+        context::{all_matched_lines_filled, opposite_positions},
+        hunks::{matched_lines_indexes_for_hunk, matched_pos_to_hunks, merge_adjacent, Hunk},
+        side_by_side::lines_with_novel,
+        style::BackgroundColor,
+        tabs,
+    },
+    lines::{byte_len, split_on_newlines, LineNumber, MaxLine},
+    options::DisplayOptions,
+    parse::syntax::{AtomKind, MatchKind, MatchedPos, TokenKind},
+    summary::{DiffResult, FileContent},
+};
+
+const STYLESHEET: &str = "
+body { font-family: sans-serif; margin: 1em; }
+body.dark { background: #1e1e1e; color: #d4d4d4; }
+body.light { background: #ffffff; color: #1e1e1e; }
+h2 { font-size: 1em; font-weight: bold; }
+body.dark h2 { color: #e5c07b; }
+body.light h2 { color: #986801; }
+h2 .language { font-weight: normal; opacity: 0.7; }
+table.diff { border-collapse: collapse; width: 100%; table-layout: fixed; margin-bottom: 2em; }
+table.diff td { font-family: monospace; white-space: pre-wrap; word-break: break-all; vertical-align: top; padding: 0 0.5em; }
+td.line-num { width: 4em; text-align: right; opacity: 0.5; user-select: none; }
+td.line-num.novel { opacity: 1; font-weight: bold; }
+tr.hunk-header td { font-family: sans-serif; opacity: 0.6; padding-top: 1em; }
+td.lhs.line-num.novel, td.lhs .novel { color: #e06c75; }
+td.rhs.line-num.novel, td.rhs .novel { color: #98c379; }
+body.light td.lhs.line-num.novel, body.light td.lhs .novel { color: #c0392b; }
+body.light td.rhs.line-num.novel, body.light td.rhs .novel { color: #2e7d32; }
+.novel-word { font-weight: bold; text-decoration: underline; }
+body.dark .moved { color: #56b6c2; }
+body.light .moved { color: #0184bc; }
+.keyword, .type { font-weight: bold; }
+.comment { font-style: italic; }
+body.dark .string:not(.novel):not(.moved) { color: #c678dd; }
+body.light .string:not(.novel):not(.moved) { color: #a626a4; }
+body.dark .comment:not(.novel):not(.moved) { color: #61afef; }
+body.light .comment:not(.novel):not(.moved) { color: #4078f2; }
+.tree-sitter-error:not(.novel):not(.moved) { color: #a626a4; }
+td.lhs .novel.delimiter, td.rhs .novel.delimiter { font-weight: bold; }
+";
+
+/// Escape `s` so it can be included in HTML text or attribute values.
+fn escape_html(s: &str) -> String {
+    let mut res = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => res.push_str("&amp;"),
+            '<' => res.push_str("&lt;"),
+            '>' => res.push_str("&gt;"),
+            '"' => res.push_str("&quot;"),
+            '\'' => res.push_str("&#39;"),
+            _ => res.push(c),
+        }
+    }
+    res
+}
+
+fn token_class(highlight: TokenKind) -> &'static str {
+    match highlight {
+        TokenKind::Delimiter => "delimiter",
+        TokenKind::Atom(AtomKind::Normal) => "normal",
+        TokenKind::Atom(AtomKind::String) => "string",
+        TokenKind::Atom(AtomKind::Type) => "type",
+        TokenKind::Atom(AtomKind::Comment) => "comment",
+        TokenKind::Atom(AtomKind::Keyword) => "keyword",
+        TokenKind::Atom(AtomKind::TreeSitterError) => "tree-sitter-error",
+    }
+}
+
+/// The CSS classes for a matched position. These mirror the terminal
+/// styles in `style::color_positions`.
+fn position_classes(kind: &MatchKind, syntax_highlight: bool) -> String {
+    let (change_class, highlight) = match kind {
+        MatchKind::UnchangedToken { highlight, .. } => ("unchanged", *highlight),
+        MatchKind::Novel { highlight } => ("novel", *highlight),
+        MatchKind::NovelLinePart { highlight, .. } => ("novel", *highlight),
+        MatchKind::NovelWord { highlight } => ("novel novel-word", *highlight),
+        MatchKind::Moved { highlight, .. } => ("moved", *highlight),
+    };
+
+    if syntax_highlight {
+        format!("{} {}", change_class, token_class(highlight))
+    } else {
+        change_class.to_owned()
+    }
+}
+
+/// Render `line` as HTML, wrapping every position in a `<span>` with
+/// the appropriate classes.
+fn highlight_line(line: &str, positions: &[&MatchedPos], syntax_highlight: bool) -> String {
+    let mut positions = positions.to_vec();
+    positions.sort_by_key(|mp| mp.pos.start_col);
+
+    let line_bytes = byte_len(line);
+    let mut res = String::with_capacity(line.len());
+    let mut i = 0;
+    for mp in positions {
+        let start_col = mp.pos.start_col as usize;
+        let end_col = min(mp.pos.end_col as usize, line_bytes);
+
+        // Ignore spans that are beyond the end of the line or overlap
+        // a span we've already rendered.
+        if start_col >= line_bytes || start_col < i {
+            continue;
+        }
+
+        if i < start_col {
+            res.push_str(&escape_html(&line[i..start_col]));
+        }
+
+        write!(
+            res,
+            "<span class=\"{}\">{}</span>",
+            position_classes(&mp.kind, syntax_highlight),
+            escape_html(&line[start_col..end_col])
+        )
+        .unwrap();
+        i = end_col;
+    }
+
+    if i < line_bytes {
+        res.push_str(&escape_html(&line[i..]));
+    }
+    res
+}
+
+fn group_by_line(mps: &[MatchedPos]) -> FxHashMap<LineNumber, Vec<&MatchedPos>> {
+    let mut res: FxHashMap<LineNumber, Vec<&MatchedPos>> = FxHashMap::default();
+    for mp in mps {
+        res.entry(mp.pos.line).or_default().push(mp);
+    }
+    res
+}
+
+/// The HTML for one side of a single line of the diff: a line number
+/// cell and a content cell.
+fn side_cells(
+    line_num: Option<LineNumber>,
+    side: Side,
+    lines: &[&str],
+    positions_by_line: &FxHashMap<LineNumber, Vec<&MatchedPos>>,
+    lines_with_novel: &HashSet<LineNumber>,
+    syntax_highlight: bool,
+) -> String {
+    let side_class = match side {
+        Side::Left => "lhs",
+        Side::Right => "rhs",
+    };
+
+    match line_num {
+        Some(line_num) => {
+            let novel_class = if lines_with_novel.contains(&line_num) {
+                " novel"
+            } else {
+                ""
+            };
+            let content = highlight_line(
+                lines.get(line_num.as_usize()).copied().unwrap_or(""),
+                positions_by_line
+                    .get(&line_num)
+                    .map(|mps| &mps[..])
+                    .unwrap_or(&[]),
+                syntax_highlight,
+            );
+
+            format!(
+                "<td class=\"line-num {}{}\">{}</td><td class=\"code {}\">{}</td>",
+                side_class,
+                novel_class,
+                line_num.one_indexed(),
+                side_class,
+                content
+            )
+        }
+        None => format!(
+            "<td class=\"line-num {}\"></td><td class=\"code {}\"></td>",
+            side_class, side_class
+        ),
+    }
+}
+
+fn file_heading(summary: &DiffResult, lang_name: &str) -> String {
+    let path = if summary.lhs_display_path != summary.rhs_display_path {
+        format!(
+            "{} &rarr; {}",
+            escape_html(&summary.lhs_display_path),
+            escape_html(&summary.rhs_display_path)
+        )
+    } else {
+        escape_html(&summary.rhs_display_path)
+    };
+
+    format!(
+        "<h2>{} <span class=\"language\">&mdash; {}</span></h2>\n",
+        path,
+        escape_html(lang_name)
+    )
+}
+
+fn text_file_table(
+    hunks: &[Hunk],
+    lhs_src: &str,
+    rhs_src: &str,
+    lhs_mps: &[MatchedPos],
+    rhs_mps: &[MatchedPos],
+    display_options: &DisplayOptions,
+) -> String {
+    let lhs_lines = split_on_newlines(lhs_src);
+    let rhs_lines = split_on_newlines(rhs_src);
+
+    let lhs_positions = group_by_line(lhs_mps);
+    let rhs_positions = group_by_line(rhs_mps);
+    let (lhs_lines_with_novel, rhs_lines_with_novel) = lines_with_novel(lhs_mps, rhs_mps);
+
+    // When a file has been added or removed, there's nothing to
+    // align, so show every line from the other side.
+    let hunk_lines: Vec<Vec<(Option<LineNumber>, Option<LineNumber>)>> = if lhs_src.is_empty() {
+        vec![(0..rhs_lines.len())
+            .map(|i| (None, Some((i as u32).into())))
+            .collect()]
+    } else if rhs_src.is_empty() {
+        vec![(0..lhs_lines.len())
+            .map(|i| (Some((i as u32).into()), None))
+            .collect()]
+    } else {
+        let matched_lines = all_matched_lines_filled(lhs_mps, rhs_mps, &lhs_lines, &rhs_lines);
+        let mut matched_lines_to_print = &matched_lines[..];
+
+        let mut res = vec![];
+        for hunk in hunks {
+            let (start_i, end_i) = matched_lines_indexes_for_hunk(
+                matched_lines_to_print,
+                hunk,
+                display_options.num_context_lines as usize,
+            );
+            res.push(matched_lines_to_print[start_i..end_i].to_vec());
+            matched_lines_to_print = &matched_lines_to_print[start_i..];
+        }
+        res
+    };
+
+    let mut out = String::new();
+    out.push_str("<table class=\"diff\">\n");
+    for (i, aligned_lines) in hunk_lines.iter().enumerate() {
+        if hunk_lines.len() > 1 {
+            writeln!(
+                out,
+                "<tr class=\"hunk-header\"><td colspan=\"4\">{}/{}</td></tr>",
+                i + 1,
+                hunk_lines.len()
+            )
+            .unwrap();
+        }
+
+        for (lhs_line_num, rhs_line_num) in aligned_lines {
+            writeln!(
+                out,
+                "<tr>{}{}</tr>",
+                side_cells(
+                    *lhs_line_num,
+                    Side::Left,
+                    &lhs_lines,
+                    &lhs_positions,
+                    &lhs_lines_with_novel,
+                    display_options.syntax_highlight,
+                ),
+                side_cells(
+                    *rhs_line_num,
+                    Side::Right,
+                    &rhs_lines,
+                    &rhs_positions,
+                    &rhs_lines_with_novel,
+                    display_options.syntax_highlight,
+                )
+            )
+            .unwrap();
+        }
+    }
+    out.push_str("</table>\n");
+    out
+}
+
+/// The HTML for a single file, or None if there's nothing to show.
+fn file_section(summary: &DiffResult, display_options: &DisplayOptions) -> Option<String> {
+    match (&summary.lhs_src, &summary.rhs_src) {
+        (FileContent::Text(lhs_src), FileContent::Text(rhs_src)) => {
+            let lang_name = summary.language.clone().unwrap_or_else(|| "Text".into());
+
+            let opposite_to_lhs = opposite_positions(&summary.lhs_positions);
+            let opposite_to_rhs = opposite_positions(&summary.rhs_positions);
+            let hunks = matched_pos_to_hunks(&summary.lhs_positions, &summary.rhs_positions);
+            let hunks = merge_adjacent(
+                &hunks,
+                &opposite_to_lhs,
+                &opposite_to_rhs,
+                lhs_src.max_line(),
+                rhs_src.max_line(),
+                display_options.num_context_lines as usize,
+            );
+
+            if hunks.is_empty() {
+                if !display_options.print_unchanged {
+                    return None;
+                }
+
+                let message = if lang_name == "Text" || summary.lhs_src == summary.rhs_src {
+                    "No changes."
+                } else {
+                    "No syntactic changes."
+                };
+                return Some(format!(
+                    "{}<p>{}</p>\n",
+                    file_heading(summary, &lang_name),
+                    message
+                ));
+            }
+
+            let (lhs_src, rhs_src, lhs_positions, rhs_positions) = tabs::expand_tabs(
+                lhs_src,
+                rhs_src,
+                &summary.lhs_positions,
+                &summary.rhs_positions,
+                display_options.tab_width,
+            );
+            Some(format!(
+                "{}{}",
+                file_heading(summary, &lang_name),
+                text_file_table(
+                    &hunks,
+                    &lhs_src,
+                    &rhs_src,
+                    &lhs_positions,
+                    &rhs_positions,
+                    display_options,
+                )
+            ))
+        }
+        (FileContent::Binary(lhs_bytes), FileContent::Binary(rhs_bytes)) => {
+            let changed = lhs_bytes != rhs_bytes;
+            if !display_options.print_unchanged && !changed {
+                return None;
+            }
+
+            let message = if changed {
+                "Binary contents changed."
+            } else {
+                "No changes."
+            };
+            Some(format!(
+                "{}<p>{}</p>\n",
+                file_heading(summary, "binary"),
+                message
+            ))
+        }
+        (_, FileContent::Binary(_)) | (FileContent::Binary(_), _) => Some(format!(
+            "{}<p>Binary contents changed.</p>\n",
+            file_heading(summary, "binary")
+        )),
+    }
+}
+
+/// Print all the diff results as a single self-contained HTML page.
+pub fn print(summaries: &[DiffResult], display_options: &DisplayOptions) {
+    let body_class = match display_options.background_color {
+        BackgroundColor::Dark => "dark",
+        BackgroundColor::Light => "light",
+    };
+
+    let mut out = String::new();
+    out.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+    out.push_str("<title>Difftastic</title>\n");
+    writeln!(out, "<style>{}</style>", STYLESHEET).unwrap();
+    writeln!(out, "</head>\n<body class=\"{}\">", body_class).unwrap();
+
+    for summary in summaries {
+        if let Some(section) = file_section(summary, display_options) {
+            out.push_str(&section);
+        }
+    }
+
+    out.push_str("</body>\n</html>");
+    println!("{}", out);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::positions::SingleLineSpan;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_escape_html() {
+        assert_eq!(escape_html("a < b && c"), "a &lt; b &amp;&amp; c");
+    }
+
+    #[test]
+    fn test_highlight_line() {
+        let mp = MatchedPos {
+            kind: MatchKind::Novel {
+                highlight: TokenKind::Atom(AtomKind::String),
+            },
+            pos: SingleLineSpan {
+                line: 0.into(),
+                start_col: 4,
+                end_col: 9,
+            },
+        };
+
+        assert_eq!(
+            highlight_line("foo(\"<a>\");", &[&mp], true),
+            "foo(<span class=\"novel string\">&quot;&lt;a&gt;&quot;</span>);"
+        );
+    }
+}
@@ -5,7 +5,10 @@
 /// If we exceed this, the lines are stored in separate hunks.
 const MAX_DISTANCE: u32 = 4;
 
-use std::collections::HashSet;
+use std::{
+    collections::HashSet,
+    io::{self, Write},
+};
 
 use rustc_hash::FxHashMap;
 
@@ -651,6 +654,39 @@ pub fn matched_lines_indexes_for_hunk(
     (start_i, end_i)
 }
 
+/// A writer that counts the lines written through it, so we can
+/// record the line where each hunk starts in the output.
+pub struct LineCounter<'a, W: Write> {
+    inner: &'a mut W,
+    num_lines: usize,
+}
+
+impl<'a, W: Write> LineCounter<'a, W> {
+    pub fn new(inner: &'a mut W) -> Self {
+        Self {
+            inner,
+            num_lines: 0,
+        }
+    }
+
+    /// The number of complete lines written so far.
+    pub fn num_lines(&self) -> usize {
+        self.num_lines
+    }
+}
+
+impl<'a, W: Write> Write for LineCounter<'a, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let num_bytes = self.inner.write(buf)?;
+        self.num_lines += buf[..num_bytes].iter().filter(|b| **b == b'\n').count();
+        Ok(num_bytes)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::iter::FromIterator;
@@ -832,4 +868,13 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn test_line_counter() {
+        let mut out = vec![];
+        let mut counter = LineCounter::new(&mut out);
+        write!(counter, "foo\nbar\nbaz").unwrap();
+        assert_eq!(counter.num_lines(), 2);
+        assert_eq!(out, b"foo\nbar\nbaz");
+    }
 }
@@ -1,9 +1,11 @@
 //! Inline, or "unified" diff display.
 
+use std::io::{self, Write};
+
 use crate::{
     constants::Side,
     display::context::{calculate_after_context, calculate_before_context, opposite_positions},
-    display::hunks::Hunk,
+    display::hunks::{Hunk, LineCounter},
     display::style::{self, apply_colors, apply_line_number_color},
     lines::{format_line_num, split_on_newlines, MaxLine},
     options::DisplayOptions,
@@ -11,17 +13,19 @@ use crate::{
 };
 
 pub fn print(
+    out: &mut impl Write,
     lhs_src: &str,
     rhs_src: &str,
     display_options: &DisplayOptions,
     lhs_positions: &[MatchedPos],
     rhs_positions: &[MatchedPos],
     hunks: &[Hunk],
+    hunk_definitions: &[Option<&str>],
     lhs_display_path: &str,
     rhs_display_path: &str,
     lang_name: &str,
     language: Option<Language>,
-) {
+) -> io::Result<Vec<usize>> {
     let (lhs_colored_lines, rhs_colored_lines) = if display_options.use_color {
         (
             apply_colors(
@@ -29,7 +33,7 @@ pub fn print(
                 Side::Left,
                 display_options.syntax_highlight,
                 language,
-                display_options.background_color,
+                &display_options.theme,
                 lhs_positions,
             ),
             apply_colors(
@@ -37,7 +41,7 @@ pub fn print(
                 Side::Right,
                 display_options.syntax_highlight,
                 language,
-                display_options.background_color,
+                &display_options.theme,
                 rhs_positions,
             ),
         )
@@ -57,8 +61,12 @@ pub fn print(
     let opposite_to_lhs = opposite_positions(lhs_positions);
     let opposite_to_rhs = opposite_positions(rhs_positions);
 
+    let mut out = LineCounter::new(out);
+    let mut hunk_starts = vec![];
     for (i, hunk) in hunks.iter().enumerate() {
-        println!(
+        hunk_starts.push(out.num_lines());
+        writeln!(
+            out,
             "{}",
             style::header(
                 lhs_display_path,
@@ -66,9 +74,10 @@ pub fn print(
                 i + 1,
                 hunks.len(),
                 lang_name,
+                hunk_definitions[i],
                 display_options
             )
-        );
+        )?;
 
         let hunk_lines = hunk.lines.clone();
 
@@ -86,7 +95,8 @@ pub fn print(
 
         for (lhs_line, _) in before_lines {
             if let Some(lhs_line) = lhs_line {
-                print!(
+                write!(
+                    out,
                     "{}   {}",
                     apply_line_number_color(
                         &format_line_num(lhs_line),
@@ -95,13 +105,14 @@ pub fn print(
                         display_options,
                     ),
                     lhs_colored_lines[lhs_line.as_usize()]
-                );
+                )?;
             }
         }
 
         for (lhs_line, _) in &hunk_lines {
             if let Some(lhs_line) = lhs_line {
-                print!(
+                write!(
+                    out,
                     "{}   {}",
                     apply_line_number_color(
                         &format_line_num(*lhs_line),
@@ -110,12 +121,13 @@ pub fn print(
                         display_options,
                     ),
                     lhs_colored_lines[lhs_line.as_usize()]
-                );
+                )?;
             }
         }
         for (_, rhs_line) in &hunk_lines {
             if let Some(rhs_line) = rhs_line {
-                print!(
+                write!(
+                    out,
                     "   {}{}",
                     apply_line_number_color(
                         &format_line_num(*rhs_line),
@@ -124,13 +136,14 @@ pub fn print(
                         display_options,
                     ),
                     rhs_colored_lines[rhs_line.as_usize()]
-                );
+                )?;
             }
         }
 
         for (_, rhs_line) in &after_lines {
             if let Some(rhs_line) = rhs_line {
-                print!(
+                write!(
+                    out,
                     "   {}{}",
                     apply_line_number_color(
                         &format_line_num(*rhs_line),
@@ -139,9 +152,10 @@ pub fn print(
                         display_options,
                     ),
                     rhs_colored_lines[rhs_line.as_usize()]
-                );
+                )?;
             }
         }
-        println!();
+        writeln!(out)?;
     }
+    Ok(hunk_starts)
 }
@@ -0,0 +1,222 @@
+//! Machine-readable JSON output of diff results.
+//!
+//! The document format is versioned with [`JSON_FORMAT_VERSION`], so
+//! consumers can detect incompatible changes. All line numbers and
+//! columns are zero-indexed, matching [`SingleLineSpan`].
+
+use serde::Serialize;
+
+use crate::{
+    display::{
+        context::opposite_positions,
+        hunks::{matched_pos_to_hunks, merge_adjacent, Hunk},
+    },
+    lines::{LineNumber, MaxLine},
+    options::DisplayOptions,
+    parse::syntax::{AtomKind, MatchKind, MatchedPos, TokenKind},
+    positions::SingleLineSpan,
+    summary::{DiffResult, FileContent},
+};
+
+/// The version of the JSON document format. Increment this when
+/// making a backwards-incompatible change to the output.
+pub const JSON_FORMAT_VERSION: u32 = 1;
+
+#[derive(Serialize)]
+struct Document<'a> {
+    version: u32,
+    files: Vec<File<'a>>,
+}
+
+#[derive(Serialize)]
+struct File<'a> {
+    lhs_path: &'a str,
+    rhs_path: &'a str,
+    language: Option<&'a str>,
+    /// Either "text" or "binary".
+    content: &'static str,
+    /// Either "unchanged" or "changed".
+    status: &'static str,
+    lhs_positions: Vec<Position>,
+    rhs_positions: Vec<Position>,
+    hunks: Vec<JsonHunk>,
+}
+
+#[derive(Serialize)]
+struct Span {
+    line: u32,
+    start_col: u32,
+    end_col: u32,
+}
+
+impl From<&SingleLineSpan> for Span {
+    fn from(span: &SingleLineSpan) -> Self {
+        Self {
+            line: span.line.0,
+            start_col: span.start_col,
+            end_col: span.end_col,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Position {
+    kind: &'static str,
+    highlight: &'static str,
+    #[serde(flatten)]
+    span: Span,
+    /// The corresponding spans on the other side, if any.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    opposite: Vec<Span>,
+}
+
+#[derive(Serialize)]
+struct JsonHunk {
+    novel_lhs: Vec<u32>,
+    novel_rhs: Vec<u32>,
+    lines: Vec<(Option<u32>, Option<u32>)>,
+}
+
+fn token_kind_name(highlight: TokenKind) -> &'static str {
+    match highlight {
+        TokenKind::Delimiter => "delimiter",
+        TokenKind::Atom(AtomKind::Normal) => "normal",
+        TokenKind::Atom(AtomKind::String) => "string",
+        TokenKind::Atom(AtomKind::Type) => "type",
+        TokenKind::Atom(AtomKind::Comment) => "comment",
+        TokenKind::Atom(AtomKind::Keyword) => "keyword",
+        TokenKind::Atom(AtomKind::TreeSitterError) => "tree_sitter_error",
+    }
+}
+
+impl From<&MatchedPos> for Position {
+    fn from(mp: &MatchedPos) -> Self {
+        let (kind, highlight, opposite) = match &mp.kind {
+            MatchKind::UnchangedToken {
+                highlight,
+                opposite_pos,
+                ..
+            } => ("unchanged", *highlight, opposite_pos.iter().collect()),
+            MatchKind::Novel { highlight } => ("novel", *highlight, vec![]),
+            MatchKind::NovelLinePart {
+                highlight,
+                opposite_pos,
+                ..
+            } => ("novel_line_part", *highlight, opposite_pos.iter().collect()),
+            MatchKind::NovelWord { highlight } => ("novel_word", *highlight, vec![]),
+            MatchKind::Moved {
+                highlight,
+                opposite_pos,
+            } => ("moved", *highlight, opposite_pos.iter().collect()),
+        };
+
+        Self {
+            kind,
+            highlight: token_kind_name(highlight),
+            span: Span::from(&mp.pos),
+            opposite: opposite.into_iter().map(Span::from).collect(),
+        }
+    }
+}
+
+fn sorted_line_numbers<'a>(lines: impl Iterator<Item = &'a LineNumber>) -> Vec<u32> {
+    let mut lines: Vec<u32> = lines.map(|ln| ln.0).collect();
+    lines.sort_unstable();
+    lines
+}
+
+impl From<&Hunk> for JsonHunk {
+    fn from(hunk: &Hunk) -> Self {
+        Self {
+            novel_lhs: sorted_line_numbers(hunk.novel_lhs.iter()),
+            novel_rhs: sorted_line_numbers(hunk.novel_rhs.iter()),
+            lines: hunk
+                .lines
+                .iter()
+                .map(|(lhs, rhs)| (lhs.map(|ln| ln.0), rhs.map(|ln| ln.0)))
+                .collect(),
+        }
+    }
+}
+
+fn file_from_result<'a>(summary: &'a DiffResult, display_options: &DisplayOptions) -> File<'a> {
+    let (content, changed, hunks) = match (&summary.lhs_src, &summary.rhs_src) {
+        (FileContent::Text(lhs_src), FileContent::Text(rhs_src)) => {
+            let opposite_to_lhs = opposite_positions(&summary.lhs_positions);
+            let opposite_to_rhs = opposite_positions(&summary.rhs_positions);
+
+            let hunks = matched_pos_to_hunks(&summary.lhs_positions, &summary.rhs_positions);
+            let hunks = merge_adjacent(
+                &hunks,
+                &opposite_to_lhs,
+                &opposite_to_rhs,
+                lhs_src.max_line(),
+                rhs_src.max_line(),
+                display_options.num_context_lines as usize,
+            );
+
+            ("text", !hunks.is_empty(), hunks)
+        }
+        (FileContent::Binary(lhs_bytes), FileContent::Binary(rhs_bytes)) => {
+            ("binary", lhs_bytes != rhs_bytes, vec![])
+        }
+        // We're diffing a binary file against a text file.
+        _ => ("binary", true, vec![]),
+    };
+
+    File {
+        lhs_path: &summary.lhs_display_path,
+        rhs_path: &summary.rhs_display_path,
+        language: summary.language.as_deref(),
+        content,
+        status: if changed { "changed" } else { "unchanged" },
+        lhs_positions: summary.lhs_positions.iter().map(Position::from).collect(),
+        rhs_positions: summary.rhs_positions.iter().map(Position::from).collect(),
+        hunks: hunks.iter().map(JsonHunk::from).collect(),
+    }
+}
+
+/// Print all the diff results as a single JSON document.
+pub fn print(summaries: &[DiffResult], display_options: &DisplayOptions) {
+    let files = summaries
+        .iter()
+        .map(|summary| file_from_result(summary, display_options))
+        .filter(|file| display_options.print_unchanged || file.status != "unchanged")
+        .collect();
+
+    let document = Document {
+        version: JSON_FORMAT_VERSION,
+        files,
+    };
+
+    println!(
+        "{}",
+        serde_json::to_string(&document).expect("Diff results should always serialize")
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_position_serialization() {
+        let mp = MatchedPos {
+            kind: MatchKind::Novel {
+                highlight: TokenKind::Atom(AtomKind::Comment),
+            },
+            pos: SingleLineSpan {
+                line: 1.into(),
+                start_col: 2,
+                end_col: 5,
+            },
+        };
+
+        let json = serde_json::to_string(&Position::from(&mp)).unwrap();
+        assert_eq!(
+            json,
+            r#"{"kind":"novel","highlight":"comment","line":1,"start_col":2,"end_col":5}"#
+        );
+    }
+}
@@ -1,5 +1,14 @@
+pub mod change_list;
 pub mod context;
+pub mod html;
 pub mod hunks;
 pub mod inline;
+pub mod json;
+pub mod pager;
+pub mod patch;
 pub mod side_by_side;
+pub mod stat;
 pub mod style;
+pub mod summary;
+pub mod tabs;
+pub mod theme;
@@ -0,0 +1,505 @@
+//! An interactive full-screen pager for terminal output.
+//!
+//! The pager re-renders the diff results whenever the display mode,
+//! context size or terminal width changes, so users can explore a
+//! large diff without re-running difftastic.
+
+use std::io::{self, Write};
+
+use crate::options::{DisplayMode, DisplayOptions};
+
+const HELP: &str = "n/p: hunk  ]/[: file  t: inline/side-by-side  +/-: context  q: quit";
+
+/// The rendered output for all files, with the offsets of the files
+/// and hunks so we can navigate between them.
+#[derive(Debug, PartialEq)]
+struct Document {
+    lines: Vec<String>,
+    /// The index in `lines` where each file starts.
+    file_starts: Vec<usize>,
+    /// The index in `lines` of each hunk header.
+    hunk_starts: Vec<usize>,
+}
+
+/// The terminal output for a single file.
+pub struct RenderedFile {
+    pub text: String,
+    /// The line offset of each hunk header in `text`.
+    pub hunk_starts: Vec<usize>,
+}
+
+impl Document {
+    /// Build a document from the terminal output of each file.
+    fn new(files: &[RenderedFile]) -> Self {
+        let mut lines = vec![];
+        let mut file_starts = vec![];
+        let mut hunk_starts = vec![];
+
+        for file in files {
+            if file.text.is_empty() {
+                continue;
+            }
+
+            let file_start = lines.len();
+            file_starts.push(file_start);
+            hunk_starts.extend(file.hunk_starts.iter().map(|start| file_start + start));
+            lines.extend(file.text.lines().map(String::from));
+        }
+
+        Self {
+            lines,
+            file_starts,
+            hunk_starts,
+        }
+    }
+}
+
+/// The first offset in `starts` after `line`.
+fn next_start(starts: &[usize], line: usize) -> Option<usize> {
+    starts.iter().copied().find(|start| *start > line)
+}
+
+/// The last offset in `starts` before `line`.
+fn prev_start(starts: &[usize], line: usize) -> Option<usize> {
+    starts.iter().copied().rev().find(|start| *start < line)
+}
+
+/// The index of the region in `starts` that contains `line`.
+fn region_index(starts: &[usize], line: usize) -> usize {
+    starts
+        .iter()
+        .rposition(|start| *start <= line)
+        .unwrap_or_default()
+}
+
+#[derive(Debug, PartialEq)]
+enum Key {
+    Quit,
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    Top,
+    Bottom,
+    NextHunk,
+    PrevHunk,
+    NextFile,
+    PrevFile,
+    ToggleDisplay,
+    MoreContext,
+    LessContext,
+}
+
+fn parse_key(bytes: &[u8]) -> Option<Key> {
+    let key = match bytes {
+        b"q" | b"Q" | b"\x03" => Key::Quit,
+        b"k" | b"\x1b[A" | b"\x1bOA" => Key::Up,
+        b"j" | b"\x1b[B" | b"\x1bOB" | b"\r" | b"\n" => Key::Down,
+        b"b" | b"\x1b[5~" => Key::PageUp,
+        b" " | b"f" | b"\x1b[6~" => Key::PageDown,
+        b"g" | b"<" | b"\x1b[H" | b"\x1b[1~" => Key::Top,
+        b"G" | b">" | b"\x1b[F" | b"\x1b[4~" => Key::Bottom,
+        b"n" => Key::NextHunk,
+        b"p" | b"N" => Key::PrevHunk,
+        b"]" | b"}" => Key::NextFile,
+        b"[" | b"{" => Key::PrevFile,
+        b"t" => Key::ToggleDisplay,
+        b"+" | b"=" => Key::MoreContext,
+        b"-" => Key::LessContext,
+        _ => return None,
+    };
+    Some(key)
+}
+
+#[cfg(unix)]
+mod terminal {
+    use std::{
+        fs::File,
+        io::{self, Read, Write},
+        os::unix::io::AsRawFd,
+    };
+
+    /// The alternate screen, with the cursor hidden and line wrapping
+    /// disabled. The normal screen is restored when this is dropped,
+    /// including when we panic.
+    pub struct AlternateScreen;
+
+    impl AlternateScreen {
+        pub fn enter(out: &mut impl Write) -> io::Result<Self> {
+            write!(out, "\x1b[?1049h\x1b[?25l\x1b[?7l")?;
+            out.flush()?;
+            Ok(Self)
+        }
+    }
+
+    impl Drop for AlternateScreen {
+        fn drop(&mut self) {
+            let mut out = io::stdout();
+            let _ = write!(out, "\x1b[?7h\x1b[?25h\x1b[?1049l");
+            let _ = out.flush();
+        }
+    }
+
+    /// The controlling terminal in non-canonical mode, so we can read
+    /// individual key presses. The original settings are restored
+    /// when this is dropped.
+    pub struct RawTerminal {
+        tty: File,
+        original: libc::termios,
+    }
+
+    impl RawTerminal {
+        pub fn new() -> io::Result<Self> {
+            // Read from the terminal rather than stdin, as stdin may
+            // be one of the files being diffed.
+            let tty = File::open("/dev/tty")?;
+            let fd = tty.as_raw_fd();
+
+            unsafe {
+                let mut original: libc::termios = std::mem::zeroed();
+                if libc::tcgetattr(fd, &mut original) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+
+                let mut raw = original;
+                raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG);
+                // Return from read() after 100ms without input, so we
+                // notice when the terminal is resized.
+                raw.c_cc[libc::VMIN] = 0;
+                raw.c_cc[libc::VTIME] = 1;
+                if libc::tcsetattr(fd, libc::TCSANOW, &raw) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+
+                Ok(Self { tty, original })
+            }
+        }
+
+        /// Read the bytes of the next key press, or an empty vec if
+        /// there was no input before the timeout.
+        pub fn read_key(&mut self) -> io::Result<Vec<u8>> {
+            let mut buf = [0; 16];
+            let num_bytes = self.tty.read(&mut buf)?;
+            Ok(buf[..num_bytes].to_vec())
+        }
+    }
+
+    impl Drop for RawTerminal {
+        fn drop(&mut self) {
+            unsafe {
+                libc::tcsetattr(self.tty.as_raw_fd(), libc::TCSANOW, &self.original);
+            }
+        }
+    }
+}
+
+/// The state of the pager between key presses.
+struct Pager<F> {
+    options: DisplayOptions,
+    num_files: usize,
+    render_file: F,
+    document: Document,
+    /// The index of the first line shown.
+    top: usize,
+}
+
+impl<F> Pager<F>
+where
+    F: Fn(&DisplayOptions, usize) -> RenderedFile,
+{
+    fn new(mut options: DisplayOptions, num_files: usize, render_file: F) -> Self {
+        if !matches!(
+            options.display_mode,
+            DisplayMode::Inline | DisplayMode::SideBySide | DisplayMode::SideBySideShowBoth
+        ) {
+            options.display_mode = DisplayMode::SideBySide;
+        }
+
+        let mut pager = Self {
+            options,
+            num_files,
+            render_file,
+            document: Document::new(&[]),
+            top: 0,
+        };
+        pager.render();
+        pager
+    }
+
+    fn render(&mut self) {
+        let files: Vec<RenderedFile> = (0..self.num_files)
+            .map(|i| (self.render_file)(&self.options, i))
+            .collect();
+        self.document = Document::new(&files);
+    }
+
+    /// Render again after changing the options, staying on the same
+    /// hunk of the same file where possible.
+    fn rerender(&mut self) {
+        let file_i = region_index(&self.document.file_starts, self.top);
+        let file_start = self.document.file_starts.get(file_i).copied();
+        let hunk_offset = file_start.map(|file_start| {
+            self.document
+                .hunk_starts
+                .iter()
+                .filter(|start| **start >= file_start && **start <= self.top)
+                .count()
+        });
+
+        self.render();
+
+        self.top = match self.document.file_starts.get(file_i) {
+            Some(file_start) => {
+                let file_end = self
+                    .document
+                    .file_starts
+                    .get(file_i + 1)
+                    .copied()
+                    .unwrap_or(self.document.lines.len());
+                let file_hunks: Vec<usize> = self
+                    .document
+                    .hunk_starts
+                    .iter()
+                    .copied()
+                    .filter(|start| start >= file_start && *start < file_end)
+                    .collect();
+
+                match hunk_offset {
+                    Some(offset) if offset > 0 => file_hunks
+                        .get(offset - 1)
+                        .or_else(|| file_hunks.last())
+                        .copied()
+                        .unwrap_or(*file_start),
+                    _ => *file_start,
+                }
+            }
+            None => 0,
+        };
+    }
+
+    fn max_top(&self, page_height: usize) -> usize {
+        self.document.lines.len().saturating_sub(page_height)
+    }
+
+    /// Update the state for `key`. Returns false if we should exit.
+    fn handle_key(&mut self, key: Key, page_height: usize) -> bool {
+        let document = &self.document;
+        match key {
+            Key::Quit => return false,
+            Key::Up => self.top = self.top.saturating_sub(1),
+            Key::Down => self.top += 1,
+            Key::PageUp => self.top = self.top.saturating_sub(page_height),
+            Key::PageDown => self.top += page_height,
+            Key::Top => self.top = 0,
+            Key::Bottom => self.top = self.max_top(page_height),
+            Key::NextHunk => {
+                if let Some(start) = next_start(&document.hunk_starts, self.top) {
+                    self.top = start;
+                }
+            }
+            Key::PrevHunk => {
+                if let Some(start) = prev_start(&document.hunk_starts, self.top) {
+                    self.top = start;
+                }
+            }
+            Key::NextFile => {
+                if let Some(start) = next_start(&document.file_starts, self.top) {
+                    self.top = start;
+                }
+            }
+            Key::PrevFile => {
+                if let Some(start) = prev_start(&document.file_starts, self.top) {
+                    self.top = start;
+                }
+            }
+            Key::ToggleDisplay => {
+                self.options.display_mode = match self.options.display_mode {
+                    DisplayMode::Inline => DisplayMode::SideBySide,
+                    _ => DisplayMode::Inline,
+                };
+                self.rerender();
+            }
+            Key::MoreContext => {
+                self.options.num_context_lines += 1;
+                self.rerender();
+            }
+            Key::LessContext => {
+                if self.options.num_context_lines > 0 {
+                    self.options.num_context_lines -= 1;
+                    self.rerender();
+                }
+            }
+        }
+
+        // Allow scrolling to the start of the last file or hunk, even
+        // if it's less than a page.
+        let last_start = self
+            .document
+            .hunk_starts
+            .last()
+            .copied()
+            .unwrap_or_default();
+        self.top = self
+            .top
+            .min(self.max_top(page_height).max(last_start))
+            .min(self.document.lines.len().saturating_sub(1));
+        true
+    }
+
+    fn status_line(&self, width: usize) -> String {
+        let document = &self.document;
+        let mode = match self.options.display_mode {
+            DisplayMode::Inline => "inline",
+            _ => "side-by-side",
+        };
+        let status = format!(
+            " file {}/{}  hunk {}/{}  {}  context {}  |  {}",
+            region_index(&document.file_starts, self.top) + 1,
+            document.file_starts.len(),
+            region_index(&document.hunk_starts, self.top) + 1,
+            document.hunk_starts.len(),
+            mode,
+            self.options.num_context_lines,
+            HELP
+        );
+
+        let status: String = status.chars().take(width).collect();
+        format!("{:width$}", status, width = width)
+    }
+
+    fn draw(&self, out: &mut impl Write, height: usize, width: usize) -> io::Result<()> {
+        let page_height = height.saturating_sub(1);
+
+        let mut screen = String::from("\x1b[H");
+        for i in self.top..self.top + page_height {
+            if let Some(line) = self.document.lines.get(i) {
+                screen.push_str(line);
+            }
+            screen.push_str("\x1b[0m\x1b[K\r\n");
+        }
+        screen.push_str("\x1b[7m");
+        screen.push_str(&self.status_line(width));
+        screen.push_str("\x1b[0m");
+
+        out.write_all(screen.as_bytes())?;
+        out.flush()
+    }
+}
+
+/// Redraw and handle key presses until the user quits.
+#[cfg(unix)]
+fn event_loop<F>(
+    terminal: &mut terminal::RawTerminal,
+    out: &mut impl Write,
+    pager: &mut Pager<F>,
+) -> io::Result<()>
+where
+    F: Fn(&DisplayOptions, usize) -> RenderedFile,
+{
+    let mut size = None;
+    loop {
+        let (width, height) = match terminal_size::terminal_size() {
+            Some((w, h)) => (w.0 as usize, h.0 as usize),
+            None => (pager.options.display_width, 24),
+        };
+        let page_height = height.saturating_sub(1).max(1);
+
+        if size != Some((width, height)) {
+            // Side-by-side output depends on the width, so render
+            // again if the terminal has been resized.
+            if width != pager.options.display_width {
+                pager.options.display_width = width;
+                pager.rerender();
+            }
+            write!(out, "\x1b[2J")?;
+            pager.draw(out, height, width)?;
+            size = Some((width, height));
+        }
+
+        let bytes = terminal.read_key()?;
+        if let Some(key) = parse_key(&bytes) {
+            if !pager.handle_key(key, page_height) {
+                return Ok(());
+            }
+            pager.draw(out, height, width)?;
+        }
+    }
+}
+
+/// Show the output of `num_files` files in a full-screen pager.
+/// `render_file` returns the terminal output for a file with the
+/// given display options.
+#[cfg(unix)]
+pub fn run<F>(display_options: &DisplayOptions, num_files: usize, render_file: F) -> io::Result<()>
+where
+    F: Fn(&DisplayOptions, usize) -> RenderedFile,
+{
+    let mut terminal = terminal::RawTerminal::new()?;
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+
+    let mut pager = Pager::new(display_options.clone(), num_files, render_file);
+
+    let _screen = terminal::AlternateScreen::enter(&mut out)?;
+    event_loop(&mut terminal, &mut out, &mut pager)
+}
+
+#[cfg(not(unix))]
+pub fn run<F>(
+    _display_options: &DisplayOptions,
+    _num_files: usize,
+    _render_file: F,
+) -> io::Result<()>
+where
+    F: Fn(&DisplayOptions, usize) -> RenderedFile,
+{
+    Err(io::Error::new(
+        io::ErrorKind::Other,
+        "The interactive pager is only supported on Unix",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_document_offsets() {
+        let document = Document::new(&[
+            RenderedFile {
+                text: "a.py --- 1/2 --- Python\n1 x\n\na.py --- 2/2 --- Python\n9 y\n\n".to_owned(),
+                hunk_starts: vec![0, 3],
+            },
+            RenderedFile {
+                text: String::new(),
+                hunk_starts: vec![],
+            },
+            RenderedFile {
+                text: "b.py --- Python\n1 z\n\n".to_owned(),
+                hunk_starts: vec![0],
+            },
+        ]);
+
+        assert_eq!(document.file_starts, vec![0, 6]);
+        assert_eq!(document.hunk_starts, vec![0, 3, 6]);
+        assert_eq!(document.lines.len(), 9);
+    }
+
+    #[test]
+    fn test_next_and_prev_start() {
+        let starts = [0, 3, 6];
+        assert_eq!(next_start(&starts, 3), Some(6));
+        assert_eq!(next_start(&starts, 6), None);
+        assert_eq!(prev_start(&starts, 4), Some(3));
+        assert_eq!(prev_start(&starts, 0), None);
+        assert_eq!(region_index(&starts, 5), 1);
+    }
+
+    #[test]
+    fn test_parse_key() {
+        assert_eq!(parse_key(b"\x1b[B"), Some(Key::Down));
+        assert_eq!(parse_key(b"n"), Some(Key::NextHunk));
+        assert_eq!(parse_key(b"x"), None);
+    }
+}
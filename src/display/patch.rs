@@ -0,0 +1,511 @@
+//! Unified diff output, in the format produced by `diff -u`.
+//!
+//! Hunk boundaries come from difftastic's structural hunks. Any
+//! textual changes between those hunks (e.g. whitespace changes that
+//! aren't syntactically significant) are also included, so applying
+//! the patch always reproduces the new file exactly.
+
+use std::cmp::{max, min};
+use std::fmt::Write;
+use std::io;
+
+use crate::{
+    diff::myers_diff,
+    display::{
+        context::opposite_positions,
+        hunks::{matched_pos_to_hunks, merge_adjacent, Hunk},
+    },
+    lines::MaxLine,
+    options::DisplayOptions,
+    summary::{DiffResult, FileContent},
+};
+
+/// A line of the original file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Line<'a> {
+    text: &'a str,
+    /// True if this is the last line and the file doesn't end with a
+    /// newline.
+    missing_newline: bool,
+}
+
+/// A region of the file that has changed, as half-open ranges of
+/// zero-indexed line numbers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Region {
+    lhs_start: usize,
+    lhs_end: usize,
+    rhs_start: usize,
+    rhs_end: usize,
+}
+
+fn src_lines(src: &str, ends_with_newline: bool) -> Vec<Line<'_>> {
+    if src.is_empty() && !ends_with_newline {
+        return vec![];
+    }
+
+    // Split on \n only, so we preserve any \r characters.
+    let texts: Vec<&str> = src.split('\n').collect();
+    let last_i = texts.len() - 1;
+    texts
+        .into_iter()
+        .enumerate()
+        .map(|(i, text)| Line {
+            text,
+            missing_newline: i == last_i && !ends_with_newline,
+        })
+        .collect()
+}
+
+/// Convert structural hunks into regions.
+///
+/// If a hunk only has lines on one side, we assume it's at the
+/// same offset as the end of the previous region on the other side.
+fn hunk_regions(hunks: &[Hunk], lhs_len: usize, rhs_len: usize) -> Vec<Region> {
+    let mut res: Vec<Region> = vec![];
+
+    for hunk in hunks {
+        let lhs_lines: Vec<usize> = hunk
+            .lines
+            .iter()
+            .filter_map(|(lhs, _)| lhs.map(|ln| ln.as_usize()))
+            .filter(|ln| *ln < lhs_len)
+            .collect();
+        let rhs_lines: Vec<usize> = hunk
+            .lines
+            .iter()
+            .filter_map(|(_, rhs)| rhs.map(|ln| ln.as_usize()))
+            .filter(|ln| *ln < rhs_len)
+            .collect();
+
+        let (prev_lhs_end, prev_rhs_end) = match res.last() {
+            Some(prev) => (prev.lhs_end, prev.rhs_end),
+            None => (0, 0),
+        };
+
+        let (lhs_start, lhs_end, rhs_start, rhs_end) = match (
+            lhs_lines.iter().min(),
+            lhs_lines.iter().max(),
+            rhs_lines.iter().min(),
+            rhs_lines.iter().max(),
+        ) {
+            (Some(lhs_min), Some(lhs_max), Some(rhs_min), Some(rhs_max)) => {
+                (*lhs_min, lhs_max + 1, *rhs_min, rhs_max + 1)
+            }
+            (Some(lhs_min), Some(lhs_max), _, _) => {
+                let rhs_start = (lhs_min + prev_rhs_end).saturating_sub(prev_lhs_end);
+                let rhs_start = min(max(rhs_start, prev_rhs_end), rhs_len);
+                (*lhs_min, lhs_max + 1, rhs_start, rhs_start)
+            }
+            (_, _, Some(rhs_min), Some(rhs_max)) => {
+                let lhs_start = (rhs_min + prev_lhs_end).saturating_sub(prev_rhs_end);
+                let lhs_start = min(max(lhs_start, prev_lhs_end), lhs_len);
+                (lhs_start, lhs_start, *rhs_min, rhs_max + 1)
+            }
+            _ => continue,
+        };
+
+        res.push(Region {
+            lhs_start,
+            lhs_end,
+            rhs_start,
+            rhs_end,
+        });
+    }
+
+    res
+}
+
+/// Merge regions that overlap or touch on either side, so the result
+/// is strictly increasing on both sides.
+fn merge_regions(regions: &[Region]) -> Vec<Region> {
+    let mut regions = regions.to_vec();
+    regions.sort_by_key(|r| (r.lhs_start, r.rhs_start));
+
+    let mut res: Vec<Region> = vec![];
+    for region in regions {
+        let mut region = region;
+        while let Some(prev) = res.last() {
+            if region.lhs_start <= prev.lhs_end || region.rhs_start <= prev.rhs_end {
+                region = Region {
+                    lhs_start: min(prev.lhs_start, region.lhs_start),
+                    lhs_end: max(prev.lhs_end, region.lhs_end),
+                    rhs_start: min(prev.rhs_start, region.rhs_start),
+                    rhs_end: max(prev.rhs_end, region.rhs_end),
+                };
+                res.pop();
+            } else {
+                break;
+            }
+        }
+        res.push(region);
+    }
+
+    res
+}
+
+/// Find textual changes between `lhs` and `rhs`, which are the lines
+/// between two structural regions.
+fn gap_regions(lhs: &[Line], rhs: &[Line], lhs_offset: usize, rhs_offset: usize) -> Vec<Region> {
+    if lhs == rhs {
+        return vec![];
+    }
+
+    let mut res = vec![];
+
+    let mut lhs_i = lhs_offset;
+    let mut rhs_i = rhs_offset;
+    // The start of the current run of changed lines, if any.
+    let mut change_start: Option<(usize, usize)> = None;
+
+    for diff_res in myers_diff::slice(lhs, rhs) {
+        match diff_res {
+            myers_diff::DiffResult::Both(_, _) => {
+                if let Some((lhs_start, rhs_start)) = change_start.take() {
+                    res.push(Region {
+                        lhs_start,
+                        lhs_end: lhs_i,
+                        rhs_start,
+                        rhs_end: rhs_i,
+                    });
+                }
+                lhs_i += 1;
+                rhs_i += 1;
+            }
+            myers_diff::DiffResult::Left(_) => {
+                change_start.get_or_insert((lhs_i, rhs_i));
+                lhs_i += 1;
+            }
+            myers_diff::DiffResult::Right(_) => {
+                change_start.get_or_insert((lhs_i, rhs_i));
+                rhs_i += 1;
+            }
+        }
+    }
+    if let Some((lhs_start, rhs_start)) = change_start {
+        res.push(Region {
+            lhs_start,
+            lhs_end: lhs_i,
+            rhs_start,
+            rhs_end: rhs_i,
+        });
+    }
+
+    res
+}
+
+/// Extend every region with up to `num_context_lines` unchanged lines
+/// before and after.
+///
+/// `regions` must be strictly increasing and the lines between them
+/// must be identical, so we can pad both sides by the same amount.
+fn add_context(regions: &[Region], lhs_len: usize, num_context_lines: usize) -> Vec<Region> {
+    let mut res = vec![];
+    for (i, region) in regions.iter().enumerate() {
+        let prev_lhs_end = if i == 0 { 0 } else { regions[i - 1].lhs_end };
+        let next_lhs_start = match regions.get(i + 1) {
+            Some(next) => next.lhs_start,
+            None => lhs_len,
+        };
+
+        let before = min(num_context_lines, region.lhs_start - prev_lhs_end);
+        let after = min(num_context_lines, next_lhs_start - region.lhs_end);
+        res.push(Region {
+            lhs_start: region.lhs_start - before,
+            lhs_end: region.lhs_end + after,
+            rhs_start: region.rhs_start - before,
+            rhs_end: region.rhs_end + after,
+        });
+    }
+    res
+}
+
+/// All the regions that need to be in the patch: the structural
+/// hunks, and any textual changes outside them.
+fn patch_regions(
+    lhs: &[Line],
+    rhs: &[Line],
+    hunks: &[Hunk],
+    num_context_lines: usize,
+) -> Vec<Region> {
+    let structural = merge_regions(&hunk_regions(hunks, lhs.len(), rhs.len()));
+
+    let mut res = structural.clone();
+    let mut prev_lhs_end = 0;
+    let mut prev_rhs_end = 0;
+    for region in structural.iter().chain(std::iter::once(&Region {
+        lhs_start: lhs.len(),
+        lhs_end: lhs.len(),
+        rhs_start: rhs.len(),
+        rhs_end: rhs.len(),
+    })) {
+        res.extend(gap_regions(
+            &lhs[prev_lhs_end..region.lhs_start],
+            &rhs[prev_rhs_end..region.rhs_start],
+            prev_lhs_end,
+            prev_rhs_end,
+        ));
+        prev_lhs_end = region.lhs_end;
+        prev_rhs_end = region.rhs_end;
+    }
+
+    let regions: Vec<Region> = merge_regions(&res)
+        .into_iter()
+        .filter(|r| lhs[r.lhs_start..r.lhs_end] != rhs[r.rhs_start..r.rhs_end])
+        .collect();
+
+    merge_regions(&add_context(&regions, lhs.len(), num_context_lines))
+}
+
+/// Format the start and length of a hunk range, as used in `@@`
+/// headers. Empty ranges refer to the line before them.
+fn format_range(start: usize, end: usize) -> String {
+    let len = end - start;
+    if len == 0 {
+        format!("{},0", start)
+    } else {
+        format!("{},{}", start + 1, len)
+    }
+}
+
+fn push_line(out: &mut String, prefix: char, line: &Line) {
+    out.push(prefix);
+    out.push_str(line.text);
+    out.push('\n');
+    if line.missing_newline {
+        out.push_str("\\ No newline at end of file\n");
+    }
+}
+
+fn patch_path(prefix: &str, path: &str, is_missing: bool) -> String {
+    if is_missing || path == "/dev/null" {
+        "/dev/null".to_owned()
+    } else {
+        format!("{}/{}", prefix, path)
+    }
+}
+
+/// Return a unified diff between `lhs_src` and `rhs_src`, the text
+/// content of `summary`, or an empty string if they're identical.
+fn format_patch(
+    summary: &DiffResult,
+    lhs_src: &str,
+    rhs_src: &str,
+    num_context_lines: usize,
+) -> String {
+    let opposite_to_lhs = opposite_positions(&summary.lhs_positions);
+    let opposite_to_rhs = opposite_positions(&summary.rhs_positions);
+
+    let hunks = matched_pos_to_hunks(&summary.lhs_positions, &summary.rhs_positions);
+    let hunks = merge_adjacent(
+        &hunks,
+        &opposite_to_lhs,
+        &opposite_to_rhs,
+        lhs_src.max_line(),
+        rhs_src.max_line(),
+        num_context_lines,
+    );
+
+    let lhs = src_lines(lhs_src, summary.lhs_ends_with_newline);
+    let rhs = src_lines(rhs_src, summary.rhs_ends_with_newline);
+
+    let regions = patch_regions(&lhs, &rhs, &hunks, num_context_lines);
+    if regions.is_empty() {
+        return String::new();
+    }
+
+    let mut out = String::new();
+    writeln!(
+        out,
+        "--- {}",
+        patch_path("a", &summary.lhs_display_path, summary.lhs_missing)
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "+++ {}",
+        patch_path("b", &summary.rhs_display_path, summary.rhs_missing)
+    )
+    .unwrap();
+
+    for region in regions {
+        writeln!(
+            out,
+            "@@ -{} +{} @@",
+            format_range(region.lhs_start, region.lhs_end),
+            format_range(region.rhs_start, region.rhs_end)
+        )
+        .unwrap();
+
+        let lhs_lines = &lhs[region.lhs_start..region.lhs_end];
+        let rhs_lines = &rhs[region.rhs_start..region.rhs_end];
+
+        // Within a region, show removals before additions, as
+        // `diff -u` does.
+        let mut removed = vec![];
+        let mut added = vec![];
+        for diff_res in myers_diff::slice(lhs_lines, rhs_lines) {
+            match diff_res {
+                myers_diff::DiffResult::Both(line, _) => {
+                    for line in removed.drain(..) {
+                        push_line(&mut out, '-', line);
+                    }
+                    for line in added.drain(..) {
+                        push_line(&mut out, '+', line);
+                    }
+                    push_line(&mut out, ' ', line);
+                }
+                myers_diff::DiffResult::Left(line) => removed.push(line),
+                myers_diff::DiffResult::Right(line) => added.push(line),
+            }
+        }
+        for line in removed {
+            push_line(&mut out, '-', line);
+        }
+        for line in added {
+            push_line(&mut out, '+', line);
+        }
+    }
+
+    out
+}
+
+/// Print a diff result as a unified diff.
+pub fn print(
+    out: &mut impl io::Write,
+    summary: &DiffResult,
+    display_options: &DisplayOptions,
+) -> io::Result<()> {
+    match (&summary.lhs_src, &summary.rhs_src) {
+        (FileContent::Text(lhs_src), FileContent::Text(rhs_src)) => {
+            // Build the whole patch before printing, so patches from
+            // files diffed in parallel aren't interleaved.
+            write!(
+                out,
+                "{}",
+                format_patch(
+                    summary,
+                    lhs_src,
+                    rhs_src,
+                    display_options.num_context_lines as usize,
+                )
+            )?;
+        }
+        (FileContent::Binary(lhs_bytes), FileContent::Binary(rhs_bytes))
+            if lhs_bytes == rhs_bytes => {}
+        _ => {
+            writeln!(
+                out,
+                "Binary files {} and {} differ",
+                patch_path("a", &summary.lhs_display_path, false),
+                patch_path("b", &summary.rhs_display_path, false)
+            )?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{line_parser, test_helpers};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_format_range() {
+        assert_eq!(format_range(0, 3), "1,3");
+        assert_eq!(format_range(4, 4), "4,0");
+    }
+
+    fn text_result(lhs_src: &str, rhs_src: &str) -> DiffResult {
+        let lhs_ends_with_newline = lhs_src.ends_with('\n');
+        let rhs_ends_with_newline = rhs_src.ends_with('\n');
+        let lhs_src = lhs_src.strip_suffix('\n').unwrap_or(lhs_src);
+        let rhs_src = rhs_src.strip_suffix('\n').unwrap_or(rhs_src);
+
+        DiffResult {
+            lhs_ends_with_newline,
+            rhs_ends_with_newline,
+            ..test_helpers::text_result("foo.txt", lhs_src, rhs_src)
+        }
+    }
+
+    fn patch(summary: &DiffResult, num_context_lines: usize) -> String {
+        match (&summary.lhs_src, &summary.rhs_src) {
+            (FileContent::Text(lhs_src), FileContent::Text(rhs_src)) => {
+                format_patch(summary, lhs_src, rhs_src, num_context_lines)
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_patch_textual_change() {
+        // With no structural hunks, we still include textual changes.
+        let summary = text_result("a\nb\nc\n", "a\nB\nc\n");
+        assert_eq!(
+            patch(&summary, 1),
+            "--- a/foo.txt\n+++ b/foo.txt\n@@ -1,3 +1,3 @@\n a\n-b\n+B\n c\n"
+        );
+    }
+
+    #[test]
+    fn test_patch_structural_hunks() {
+        let lhs_src = "a\nb\nc\nd\ne\nf\ng\nh\ni\nj";
+        let rhs_src = "a\nB\nc\nD\ne\nf\ng\nh\nI\nj";
+        let summary = DiffResult {
+            lhs_positions: line_parser::change_positions(lhs_src, rhs_src),
+            rhs_positions: line_parser::change_positions(rhs_src, lhs_src),
+            ..text_result(&format!("{}\n", lhs_src), &format!("{}\n", rhs_src))
+        };
+
+        // The changes to b and d are close enough to share a hunk,
+        // but the change to i is not.
+        assert_eq!(
+            patch(&summary, 1),
+            concat!(
+                "--- a/foo.txt\n+++ b/foo.txt\n",
+                "@@ -1,5 +1,5 @@\n a\n-b\n+B\n c\n-d\n+D\n e\n",
+                "@@ -8,3 +8,3 @@\n h\n-i\n+I\n j\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_patch_missing_final_newline() {
+        let summary = text_result("a\n", "a");
+        assert_eq!(
+            patch(&summary, 3),
+            "--- a/foo.txt\n+++ b/foo.txt\n@@ -1,1 +1,1 @@\n-a\n+a\n\\ No newline at end of file\n"
+        );
+    }
+
+    #[test]
+    fn test_patch_added_file() {
+        let summary = DiffResult {
+            lhs_missing: true,
+            ..text_result("", "a\nb\n")
+        };
+        assert_eq!(
+            patch(&summary, 3),
+            "--- /dev/null\n+++ b/foo.txt\n@@ -0,0 +1,2 @@\n+a\n+b\n"
+        );
+    }
+
+    #[test]
+    fn test_patch_empty_file() {
+        // An existing empty file isn't /dev/null, otherwise the patch
+        // would try to create it.
+        let summary = text_result("", "a\n");
+        assert_eq!(
+            patch(&summary, 3),
+            "--- a/foo.txt\n+++ b/foo.txt\n@@ -0,0 +1,1 @@\n+a\n"
+        );
+    }
+
+    #[test]
+    fn test_patch_identical() {
+        let summary = text_result("a\nb\n", "a\nb\n");
+        assert_eq!(patch(&summary, 3), "");
+    }
+}
@@ -5,16 +5,17 @@ use rustc_hash::FxHashMap;
 use std::{
     cmp::{max, min},
     collections::HashSet,
+    io::{self, Write},
 };
 
 use crate::{
     constants::Side,
     display::context::all_matched_lines_filled,
-    display::hunks::{matched_lines_indexes_for_hunk, Hunk},
+    display::hunks::{matched_lines_indexes_for_hunk, Hunk, LineCounter},
     display::style::{
         self, apply_colors, apply_line_number_color, color_positions, novel_style, split_and_apply,
-        BackgroundColor,
     },
+    display::theme::Theme,
     lines::{codepoint_len, format_line_num, split_on_newlines, LineNumber},
     options::{DisplayMode, DisplayOptions},
     parse::{
@@ -85,6 +86,7 @@ fn display_single_column(
         1,
         1,
         lang_name,
+        None,
         display_options,
     ));
     header_line.push('\n');
@@ -92,7 +94,7 @@ fn display_single_column(
 
     let mut style = Style::new();
     if display_options.use_color {
-        style = novel_style(Style::new(), side, display_options.background_color);
+        style = novel_style(Style::new(), side, &display_options.theme);
     }
 
     for (i, line) in src_lines.iter().enumerate() {
@@ -252,7 +254,7 @@ pub fn lines_with_novel(
 /// Calculate positions of highlights on both sides. This includes
 /// both syntax highlighting and added/removed content highlighting.
 fn highlight_positions(
-    background: BackgroundColor,
+    theme: &Theme,
     syntax_highlight: bool,
     language: Option<Language>,
     lhs_mps: &[MatchedPos],
@@ -261,8 +263,7 @@ fn highlight_positions(
     FxHashMap<LineNumber, Vec<(SingleLineSpan, Style)>>,
     FxHashMap<LineNumber, Vec<(SingleLineSpan, Style)>>,
 ) {
-    let lhs_positions =
-        color_positions(Side::Left, background, syntax_highlight, language, lhs_mps);
+    let lhs_positions = color_positions(Side::Left, theme, syntax_highlight, language, lhs_mps);
     // Preallocate the hashmap assuming the average line will have 2 items on it.
     let mut lhs_styles: FxHashMap<LineNumber, Vec<(SingleLineSpan, Style)>> = FxHashMap::default();
     for (span, style) in lhs_positions {
@@ -270,8 +271,7 @@ fn highlight_positions(
         styles.push((span, style));
     }
 
-    let rhs_positions =
-        color_positions(Side::Right, background, syntax_highlight, language, rhs_mps);
+    let rhs_positions = color_positions(Side::Right, theme, syntax_highlight, language, rhs_mps);
     let mut rhs_styles: FxHashMap<LineNumber, Vec<(SingleLineSpan, Style)>> = FxHashMap::default();
     for (span, style) in rhs_positions {
         let styles = rhs_styles.entry(span.line).or_insert_with(Vec::new);
@@ -306,7 +306,9 @@ fn highlight_as_novel(
 }
 
 pub fn print(
+    out: &mut impl Write,
     hunks: &[Hunk],
+    hunk_definitions: &[Option<&str>],
     display_options: &DisplayOptions,
     lhs_display_path: &str,
     rhs_display_path: &str,
@@ -316,7 +318,7 @@ pub fn print(
     rhs_src: &str,
     lhs_mps: &[MatchedPos],
     rhs_mps: &[MatchedPos],
-) {
+) -> io::Result<Vec<usize>> {
     let (lhs_colored_lines, rhs_colored_lines) = if display_options.use_color {
         (
             apply_colors(
@@ -324,7 +326,7 @@ pub fn print(
                 Side::Left,
                 display_options.syntax_highlight,
                 language,
-                display_options.background_color,
+                &display_options.theme,
                 lhs_mps,
             ),
             apply_colors(
@@ -332,7 +334,7 @@ pub fn print(
                 Side::Right,
                 display_options.syntax_highlight,
                 language,
-                display_options.background_color,
+                &display_options.theme,
                 rhs_mps,
             ),
         )
@@ -358,10 +360,10 @@ pub fn print(
             Side::Right,
             display_options,
         ) {
-            print!("{}", line);
+            write!(out, "{}", line)?;
         }
-        println!();
-        return;
+        writeln!(out)?;
+        return Ok(vec![0]);
     }
     if rhs_src.is_empty() {
         for line in display_single_column(
@@ -372,16 +374,16 @@ pub fn print(
             Side::Left,
             display_options,
         ) {
-            print!("{}", line);
+            write!(out, "{}", line)?;
         }
-        println!();
-        return;
+        writeln!(out)?;
+        return Ok(vec![0]);
     }
 
     // TODO: this is largely duplicating the `apply_colors` logic.
     let (lhs_highlights, rhs_highlights) = if display_options.use_color {
         highlight_positions(
-            display_options.background_color,
+            &display_options.theme,
             display_options.syntax_highlight,
             language,
             lhs_mps,
@@ -401,8 +403,12 @@ pub fn print(
     let matched_lines = all_matched_lines_filled(lhs_mps, rhs_mps, &lhs_lines, &rhs_lines);
     let mut matched_lines_to_print = &matched_lines[..];
 
+    let mut out = LineCounter::new(out);
+    let mut hunk_starts = vec![];
     for (i, hunk) in hunks.iter().enumerate() {
-        println!(
+        hunk_starts.push(out.num_lines());
+        writeln!(
+            out,
             "{}",
             style::header(
                 lhs_display_path,
@@ -410,9 +416,10 @@ pub fn print(
                 i + 1,
                 hunks.len(),
                 lang_name,
+                hunk_definitions[i],
                 display_options
             )
-        );
+        )?;
 
         let (start_i, end_i) = matched_lines_indexes_for_hunk(
             matched_lines_to_print,
@@ -471,19 +478,20 @@ pub fn print(
                     Some(rhs_line_num) => {
                         let rhs_line = &rhs_colored_lines[rhs_line_num.as_usize()];
                         if same_lines {
-                            print!("{}{}", display_rhs_line_num, rhs_line);
+                            write!(out, "{}{}", display_rhs_line_num, rhs_line)?;
                         } else {
-                            print!(
+                            write!(
+                                out,
                                 "{}{}{}",
                                 display_lhs_line_num, display_rhs_line_num, rhs_line
-                            );
+                            )?;
                         }
                     }
                     None => {
                         // We didn't have any changed RHS lines in the
                         // hunk, but we had some contextual lines that
                         // only occurred on the LHS (e.g. extra newlines).
-                        println!("{}{}", display_lhs_line_num, display_rhs_line_num);
+                        writeln!(out, "{}{}", display_lhs_line_num, display_rhs_line_num)?;
                     }
                 }
             } else if no_rhs_changes && !show_both {
@@ -491,16 +499,17 @@ pub fn print(
                     Some(lhs_line_num) => {
                         let lhs_line = &lhs_colored_lines[lhs_line_num.as_usize()];
                         if same_lines {
-                            print!("{}{}", display_lhs_line_num, lhs_line);
+                            write!(out, "{}{}", display_lhs_line_num, lhs_line)?;
                         } else {
-                            print!(
+                            write!(
+                                out,
                                 "{}{}{}",
                                 display_lhs_line_num, display_rhs_line_num, lhs_line
-                            );
+                            )?;
                         }
                     }
                     None => {
-                        println!("{}{}", display_lhs_line_num, display_rhs_line_num);
+                        writeln!(out, "{}{}", display_lhs_line_num, display_rhs_line_num)?;
                     }
                 }
             } else {
@@ -573,7 +582,11 @@ pub fn print(
                         s
                     };
 
-                    println!("{}{}{}{}{}", lhs_num, lhs_line, SPACER, rhs_num, rhs_line);
+                    writeln!(
+                        out,
+                        "{}{}{}{}{}",
+                        lhs_num, lhs_line, SPACER, rhs_num, rhs_line
+                    )?;
                 }
             }
 
@@ -584,13 +597,17 @@ pub fn print(
                 prev_rhs_line_num = *rhs_line_num;
             }
         }
-        println!();
+        writeln!(out)?;
     }
+    Ok(hunk_starts)
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::syntax::{AtomKind, MatchKind, TokenKind};
+    use crate::{
+        display::style::BackgroundColor,
+        syntax::{AtomKind, MatchKind, TokenKind},
+    };
 
     use super::*;
     use pretty_assertions::assert_eq;
@@ -657,13 +674,16 @@ mod tests {
     fn test_display_single_column() {
         let display_options = DisplayOptions {
             background_color: BackgroundColor::Dark,
+            theme: Theme::default_for(BackgroundColor::Dark),
             use_color: false,
             display_mode: DisplayMode::SideBySide,
             print_unchanged: true,
+            print_formatting_only: true,
             tab_width: 8,
             display_width: 80,
             num_context_lines: 3,
             in_vcs: false,
+            interactive: false,
             syntax_highlight: true,
         };
 
@@ -724,19 +744,25 @@ mod tests {
 
         let display_options = DisplayOptions {
             background_color: BackgroundColor::Dark,
+            theme: Theme::default_for(BackgroundColor::Dark),
             use_color: true,
             display_mode: DisplayMode::SideBySide,
             print_unchanged: true,
+            print_formatting_only: true,
             tab_width: 8,
             display_width: 80,
             num_context_lines: 3,
             syntax_highlight: true,
             in_vcs: true,
+            interactive: false,
         };
 
         // Simple smoke test.
-        print(
+        let mut out = vec![];
+        let hunk_starts = print(
+            &mut out,
             &hunks,
+            &[None],
             &display_options,
             "foo-old.el",
             "foo-new.el",
@@ -746,6 +772,8 @@ mod tests {
             "bar",
             &lhs_mps,
             &rhs_mps,
-        );
+        )
+        .unwrap();
+        assert_eq!(hunk_starts, vec![0]);
     }
 }
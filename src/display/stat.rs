@@ -0,0 +1,241 @@
+//! A per-file count of changes, similar to `git diff --stat`.
+
+use owo_colors::{OwoColorize, Style};
+use rustc_hash::FxHashSet;
+
+use crate::{
+    constants::Side,
+    options::DisplayOptions,
+    parse::syntax::{MatchKind, MatchedPos},
+    summary::{ChangeStatus, DiffResult, FileContent},
+};
+
+/// The maximum width of the bar of `-` and `+` characters.
+const MAX_BAR_WIDTH: usize = 40;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct NovelCounts {
+    lines: usize,
+    tokens: usize,
+}
+
+impl NovelCounts {
+    fn from_positions(positions: &[MatchedPos]) -> Self {
+        let mut lines = FxHashSet::default();
+        let mut tokens = 0;
+        for mp in positions {
+            if !mp.kind.is_novel() {
+                continue;
+            }
+            lines.insert(mp.pos.line);
+            // The unchanged parts of a novel line aren't tokens in
+            // their own right.
+            if !matches!(mp.kind, MatchKind::NovelLinePart { .. }) {
+                tokens += 1;
+            }
+        }
+
+        Self {
+            lines: lines.len(),
+            tokens,
+        }
+    }
+}
+
+struct FileStat {
+    path: String,
+    /// The novel counts for each side, or `None` for binary files.
+    counts: Option<(NovelCounts, NovelCounts)>,
+}
+
+fn file_stat(summary: &DiffResult, in_vcs: bool) -> FileStat {
+    let counts = match (&summary.lhs_src, &summary.rhs_src) {
+        (FileContent::Text(_), FileContent::Text(_)) => Some((
+            NovelCounts::from_positions(&summary.lhs_positions),
+            NovelCounts::from_positions(&summary.rhs_positions),
+        )),
+        _ => None,
+    };
+
+    FileStat {
+        path: summary.display_path(in_vcs),
+        counts,
+    }
+}
+
+/// The number of `-` and `+` characters to show for these line
+/// counts, scaled down so the largest file fits in `max_width`.
+fn bar_widths(
+    lhs_lines: usize,
+    rhs_lines: usize,
+    max_total: usize,
+    max_width: usize,
+) -> (usize, usize) {
+    if max_total <= max_width {
+        return (lhs_lines, rhs_lines);
+    }
+
+    // Round up, so every change is shown with at least one character.
+    let scale = |n: usize| (n * max_width + max_total - 1) / max_total;
+    (scale(lhs_lines), scale(rhs_lines))
+}
+
+fn plural(n: usize, singular: &str) -> String {
+    if n == 1 {
+        format!("{} {}", n, singular)
+    } else {
+        format!("{} {}s", n, singular)
+    }
+}
+
+fn apply_side_color(s: &str, side: Side, display_options: &DisplayOptions) -> String {
+    if display_options.use_color {
+        let style = display_options.theme.novel_color(side).apply(Style::new());
+        s.style(style).to_string()
+    } else {
+        s.to_owned()
+    }
+}
+
+fn format_stats(stats: &[FileStat], display_options: &DisplayOptions) -> Vec<String> {
+    let path_width = stats
+        .iter()
+        .map(|stat| stat.path.chars().count())
+        .max()
+        .unwrap_or(0);
+
+    let all_counts: Vec<&(NovelCounts, NovelCounts)> = stats
+        .iter()
+        .filter_map(|stat| stat.counts.as_ref())
+        .collect();
+    let number_width = |f: &dyn Fn(&(NovelCounts, NovelCounts)) -> usize| {
+        all_counts
+            .iter()
+            .map(|counts| f(counts).to_string().len())
+            .max()
+            .unwrap_or(1)
+    };
+    let lhs_lines_width = number_width(&|(lhs, _)| lhs.lines);
+    let rhs_lines_width = number_width(&|(_, rhs)| rhs.lines);
+    let lhs_tokens_width = number_width(&|(lhs, _)| lhs.tokens);
+    let rhs_tokens_width = number_width(&|(_, rhs)| rhs.tokens);
+
+    let max_total = all_counts
+        .iter()
+        .map(|(lhs, rhs)| lhs.lines + rhs.lines)
+        .max()
+        .unwrap_or(0);
+    let columns_width = path_width
+        + lhs_lines_width
+        + rhs_lines_width
+        + lhs_tokens_width
+        + rhs_tokens_width
+        + " |  lines -  + |  tokens -  + | ".len()
+        + 1;
+    let max_bar_width = display_options
+        .display_width
+        .saturating_sub(columns_width)
+        .clamp(10, MAX_BAR_WIDTH);
+
+    let mut lines = vec![];
+    let mut total_lhs = NovelCounts::default();
+    let mut total_rhs = NovelCounts::default();
+    for stat in stats {
+        let padding = " ".repeat(path_width - stat.path.chars().count());
+        match stat.counts {
+            Some((lhs, rhs)) => {
+                total_lhs.lines += lhs.lines;
+                total_lhs.tokens += lhs.tokens;
+                total_rhs.lines += rhs.lines;
+                total_rhs.tokens += rhs.tokens;
+
+                let (lhs_bar, rhs_bar) = bar_widths(lhs.lines, rhs.lines, max_total, max_bar_width);
+                lines.push(format!(
+                    " {}{} | lines -{:>lw$} +{:>rw$} | tokens -{:>ltw$} +{:>rtw$} | {}{}",
+                    stat.path,
+                    padding,
+                    lhs.lines,
+                    rhs.lines,
+                    lhs.tokens,
+                    rhs.tokens,
+                    apply_side_color(&"-".repeat(lhs_bar), Side::Left, display_options),
+                    apply_side_color(&"+".repeat(rhs_bar), Side::Right, display_options),
+                    lw = lhs_lines_width,
+                    rw = rhs_lines_width,
+                    ltw = lhs_tokens_width,
+                    rtw = rhs_tokens_width,
+                ));
+            }
+            None => lines.push(format!(" {}{} | Bin", stat.path, padding)),
+        }
+    }
+
+    lines.push(format!(
+        " {} changed, {} removed ({}), {} added ({})",
+        plural(stats.len(), "file"),
+        plural(total_lhs.lines, "line"),
+        plural(total_lhs.tokens, "token"),
+        plural(total_rhs.lines, "line"),
+        plural(total_rhs.tokens, "token"),
+    ));
+    lines
+}
+
+pub fn print(summaries: &[DiffResult], display_options: &DisplayOptions) {
+    let stats: Vec<FileStat> = summaries
+        .iter()
+        .filter(|summary| {
+            display_options.print_unchanged || summary.change_status() != ChangeStatus::Unchanged
+        })
+        .map(|summary| file_stat(summary, display_options.in_vcs))
+        .collect();
+
+    for line in format_stats(&stats, display_options) {
+        println!("{}", line);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        parse::syntax::{AtomKind, TokenKind},
+        positions::SingleLineSpan,
+    };
+    use pretty_assertions::assert_eq;
+
+    fn novel_on_line(line: u32) -> MatchedPos {
+        MatchedPos {
+            kind: MatchKind::Novel {
+                highlight: TokenKind::Atom(AtomKind::Normal),
+            },
+            pos: SingleLineSpan {
+                line: line.into(),
+                start_col: 0,
+                end_col: 1,
+            },
+        }
+    }
+
+    #[test]
+    fn test_novel_counts() {
+        let positions = vec![novel_on_line(0), novel_on_line(0), novel_on_line(3)];
+        assert_eq!(
+            NovelCounts::from_positions(&positions),
+            NovelCounts {
+                lines: 2,
+                tokens: 3
+            }
+        );
+    }
+
+    #[test]
+    fn test_bar_widths_fit() {
+        assert_eq!(bar_widths(2, 3, 10, 40), (2, 3));
+    }
+
+    #[test]
+    fn test_bar_widths_scaled() {
+        assert_eq!(bar_widths(1, 99, 100, 10), (1, 10));
+    }
+}
@@ -2,6 +2,7 @@
 
 use crate::{
     constants::Side,
+    display::theme::Theme,
     lines::{byte_len, split_on_newlines, LineNumber},
     options::DisplayOptions,
     parse::{
@@ -257,23 +258,19 @@ fn style_lines(lines: &[&str], styles: &[(SingleLineSpan, Style)]) -> Vec<String
     res
 }
 
-pub fn novel_style(style: Style, side: Side, background: BackgroundColor) -> Style {
-    if background.is_dark() {
-        match side {
-            Side::Left => style.bright_red(),
-            Side::Right => style.bright_green(),
-        }
-    } else {
-        match side {
-            Side::Left => style.red(),
-            Side::Right => style.green(),
-        }
-    }
+pub fn novel_style(style: Style, side: Side, theme: &Theme) -> Style {
+    theme.novel_color(side).apply(style)
+}
+
+/// Moved content is shown in the same colour on both sides, so it's
+/// distinct from additions and removals.
+pub fn moved_style(style: Style, theme: &Theme) -> Style {
+    theme.moved.apply(style)
 }
 
 pub fn color_positions(
     side: Side,
-    background: BackgroundColor,
+    theme: &Theme,
     syntax_highlight: bool,
     language: Option<Language>,
     positions: &[MatchedPos],
@@ -284,50 +281,17 @@ pub fn color_positions(
         match pos.kind {
             MatchKind::UnchangedToken { highlight, .. } => {
                 if syntax_highlight {
-                    if let TokenKind::Atom(atom_kind) = highlight {
-                        match atom_kind {
-                            AtomKind::String => {
-                                style = if background.is_dark() {
-                                    style.bright_magenta()
-                                } else {
-                                    style.magenta()
-                                };
-                            }
-                            AtomKind::Comment => {
-                                style = style.italic();
-                                style = if background.is_dark() {
-                                    style.bright_blue()
-                                } else {
-                                    style.blue()
-                                };
-                            }
-                            AtomKind::Keyword | AtomKind::Type => {
-                                style = style.bold();
-                            }
-                            AtomKind::TreeSitterError => style = style.purple(),
-                            AtomKind::Normal => {}
-                        }
-                    }
+                    style = theme.unchanged.get(highlight).apply(style);
                 }
             }
             MatchKind::Novel { highlight, .. } => {
-                style = novel_style(style, side, background);
-                if syntax_highlight
-                    && matches!(
-                        highlight,
-                        TokenKind::Delimiter
-                            | TokenKind::Atom(AtomKind::Keyword)
-                            | TokenKind::Atom(AtomKind::Type)
-                    )
-                {
-                    style = style.bold();
-                }
-                if matches!(highlight, TokenKind::Atom(AtomKind::Comment)) {
-                    style = style.italic();
+                style = novel_style(style, side, theme);
+                if syntax_highlight || matches!(highlight, TokenKind::Atom(AtomKind::Comment)) {
+                    style = theme.novel.get(highlight).apply(style);
                 }
             }
             MatchKind::NovelWord { highlight } => {
-                style = novel_style(style, side, background).bold();
+                style = novel_style(style, side, theme).bold();
 
                 // Underline novel words inside comments in code, but
                 // don't apply it to every single line in plaintext.
@@ -336,13 +300,19 @@ pub fn color_positions(
                 }
 
                 if syntax_highlight && matches!(highlight, TokenKind::Atom(AtomKind::Comment)) {
-                    style = style.italic();
+                    style = theme.novel.comment.apply(style);
+                }
+            }
+            MatchKind::Moved { highlight, .. } => {
+                style = moved_style(style, theme);
+                if syntax_highlight || matches!(highlight, TokenKind::Atom(AtomKind::Comment)) {
+                    style = theme.novel.get(highlight).apply(style);
                 }
             }
             MatchKind::NovelLinePart { highlight, .. } => {
-                style = novel_style(style, side, background);
+                style = novel_style(style, side, theme);
                 if syntax_highlight && matches!(highlight, TokenKind::Atom(AtomKind::Comment)) {
-                    style = style.italic();
+                    style = theme.novel.comment.apply(style);
                 }
             }
         };
@@ -356,23 +326,17 @@ pub fn apply_colors(
     side: Side,
     syntax_highlight: bool,
     language: Option<Language>,
-    background: BackgroundColor,
+    theme: &Theme,
     positions: &[MatchedPos],
 ) -> Vec<String> {
-    let styles = color_positions(side, background, syntax_highlight, language, positions);
+    let styles = color_positions(side, theme, syntax_highlight, language, positions);
     let lines = split_on_newlines(s);
     style_lines(&lines, &styles)
 }
 
-fn apply_header_color(s: &str, use_color: bool, background: BackgroundColor) -> String {
+fn apply_header_color(s: &str, use_color: bool, theme: &Theme) -> String {
     if use_color {
-        if background.is_dark() {
-            s.bright_yellow().to_string()
-        } else {
-            s.yellow().to_string()
-        }
-        .bold()
-        .to_string()
+        s.style(theme.header.apply(Style::new())).to_string()
     } else {
         s.to_string()
     }
@@ -385,20 +349,22 @@ pub(crate) fn apply_line_number_color(
     display_options: &DisplayOptions,
 ) -> String {
     if display_options.use_color {
-        let mut style = Style::new();
+        let theme = &display_options.theme;
 
         // The goal here is to choose a style for line numbers that is
         // visually distinct from content.
-        if is_novel {
+        let style = if is_novel {
             // For changed lines, show the line number as red/green
             // and bold. This works well for syntactic diffs, where
             // most content is not bold.
-            style = novel_style(style, side, display_options.background_color).bold();
+            theme
+                .novel_line_number
+                .apply(novel_style(Style::new(), side, theme))
         } else {
             // For unchanged lines, dim the line numbers so it's
             // clearly separate from the content.
-            style = style.dimmed()
-        }
+            theme.line_number.apply(Style::new())
+        };
 
         s.style(style).to_string()
     } else {
@@ -412,6 +378,7 @@ pub fn header(
     hunk_num: usize,
     hunk_total: usize,
     language_name: &str,
+    definition: Option<&str>,
     display_options: &DisplayOptions,
 ) -> String {
     let divider = if hunk_total == 1 {
@@ -419,16 +386,21 @@ pub fn header(
     } else {
         format!("{}/{} --- ", hunk_num, hunk_total)
     };
+    // Show the enclosing definition, like git's hunk headers.
+    let language_name = match definition {
+        Some(definition) => format!("{} --- {}", language_name, definition),
+        None => language_name.to_owned(),
+    };
 
     let rhs_path_pretty = apply_header_color(
         rhs_display_path,
         display_options.use_color,
-        display_options.background_color,
+        &display_options.theme,
     );
     let lhs_path_pretty = apply_header_color(
         lhs_display_path,
         display_options.use_color,
-        display_options.background_color,
+        &display_options.theme,
     );
     if hunk_num == 1 && lhs_display_path != rhs_display_path && display_options.in_vcs {
         let renamed = format!("Renamed {} to {}", lhs_path_pretty, rhs_path_pretty);
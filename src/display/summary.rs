@@ -0,0 +1,99 @@
+//! A summary of which definitions changed in each file, rather than
+//! the individual lines.
+
+use std::io::{self, Write};
+
+use owo_colors::{OwoColorize, Style};
+
+use crate::{
+    constants::Side,
+    display::style,
+    options::DisplayOptions,
+    summary::{ChangeStatus, DefinitionChange, DefinitionChangeKind, DiffResult, FileContent},
+};
+
+fn format_change(change: &DefinitionChange, display_options: &DisplayOptions) -> String {
+    let (verb, side) = match change.kind {
+        DefinitionChangeKind::Added => ("added", Some(Side::Right)),
+        DefinitionChangeKind::Removed => ("removed", Some(Side::Left)),
+        DefinitionChangeKind::Modified => ("modified", None),
+    };
+
+    let description = format!("{} {}", verb, change.name);
+    let description = match side {
+        Some(side) if display_options.use_color => {
+            let style = display_options.theme.novel_color(side).apply(Style::new());
+            description.style(style).to_string()
+        }
+        _ => description,
+    };
+
+    format!("{}{}", "  ".repeat(change.depth + 1), description)
+}
+
+pub fn print(
+    out: &mut impl Write,
+    summary: &DiffResult,
+    display_options: &DisplayOptions,
+) -> io::Result<()> {
+    let status = summary.change_status();
+    if status != ChangeStatus::Changed && !display_options.print_unchanged {
+        return Ok(());
+    }
+
+    let lang_name = match (&summary.lhs_src, &summary.rhs_src) {
+        (FileContent::Text(_), FileContent::Text(_)) => {
+            summary.language.clone().unwrap_or_else(|| "Text".into())
+        }
+        _ => "binary".into(),
+    };
+    writeln!(
+        out,
+        "{}",
+        style::header(
+            &summary.lhs_display_path,
+            &summary.rhs_display_path,
+            1,
+            1,
+            &lang_name,
+            None,
+            display_options
+        )
+    )?;
+
+    match status {
+        ChangeStatus::Unchanged => writeln!(out, "No changes.")?,
+        ChangeStatus::FormattingOnly => writeln!(out, "No syntactic changes.")?,
+        ChangeStatus::Changed if summary.definition_changes.is_empty() => {
+            writeln!(out, "No definitions changed.")?;
+        }
+        ChangeStatus::Changed => {
+            for change in &summary.definition_changes {
+                writeln!(out, "{}", format_change(change, display_options))?;
+            }
+        }
+    }
+    writeln!(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{options::DisplayMode, test_helpers::display_options};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_format_change_nested() {
+        let display_options = display_options(DisplayMode::Summary);
+
+        let change = DefinitionChange {
+            kind: DefinitionChangeKind::Added,
+            name: "def foo".into(),
+            depth: 1,
+        };
+        assert_eq!(
+            format_change(&change, &display_options),
+            "    added def foo"
+        );
+    }
+}
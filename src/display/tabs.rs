@@ -0,0 +1,179 @@
+//! Expanding tab characters for display.
+//!
+//! We parse and diff the original source, so tabs are preserved in
+//! string literals and in languages where they're significant (such
+//! as Makefiles). Tabs are only expanded to spaces when displaying,
+//! which shifts the columns of any spans after a tab on the same line.
+
+use std::borrow::Cow;
+
+use crate::{
+    lines::split_on_newlines,
+    parse::syntax::{MatchKind, MatchedPos},
+    positions::SingleLineSpan,
+};
+
+/// The byte offsets of the tabs on each line of a source file.
+struct TabOffsets {
+    tab_width: usize,
+    offsets_by_line: Vec<Vec<u32>>,
+}
+
+impl TabOffsets {
+    fn new(src: &str, tab_width: usize) -> Self {
+        let offsets_by_line = split_on_newlines(src)
+            .iter()
+            .map(|line| {
+                line.match_indices('\t')
+                    .map(|(offset, _)| offset as u32)
+                    .collect()
+            })
+            .collect();
+
+        Self {
+            tab_width,
+            offsets_by_line,
+        }
+    }
+
+    /// The column in the expanded line that corresponds to `col` in
+    /// the original line.
+    fn expand_col(&self, line: usize, col: u32) -> u32 {
+        let tabs_before = match self.offsets_by_line.get(line) {
+            Some(offsets) => offsets.iter().take_while(|offset| **offset < col).count(),
+            None => 0,
+        };
+        col + (tabs_before * self.tab_width.saturating_sub(1)) as u32
+    }
+
+    fn expand_span(&self, span: &SingleLineSpan) -> SingleLineSpan {
+        SingleLineSpan {
+            line: span.line,
+            start_col: self.expand_col(span.line.as_usize(), span.start_col),
+            end_col: self.expand_col(span.line.as_usize(), span.end_col),
+        }
+    }
+
+    fn expand_spans(&self, spans: &[SingleLineSpan]) -> Vec<SingleLineSpan> {
+        spans.iter().map(|span| self.expand_span(span)).collect()
+    }
+}
+
+fn expand_positions(
+    positions: &[MatchedPos],
+    tabs: &TabOffsets,
+    opposite_tabs: &TabOffsets,
+) -> Vec<MatchedPos> {
+    positions
+        .iter()
+        .map(|mp| {
+            let kind = match &mp.kind {
+                MatchKind::UnchangedToken {
+                    highlight,
+                    self_pos,
+                    opposite_pos,
+                } => MatchKind::UnchangedToken {
+                    highlight: *highlight,
+                    self_pos: tabs.expand_spans(self_pos),
+                    opposite_pos: opposite_tabs.expand_spans(opposite_pos),
+                },
+                MatchKind::NovelLinePart {
+                    highlight,
+                    self_pos,
+                    opposite_pos,
+                } => MatchKind::NovelLinePart {
+                    highlight: *highlight,
+                    self_pos: tabs.expand_span(self_pos),
+                    opposite_pos: opposite_tabs.expand_spans(opposite_pos),
+                },
+                MatchKind::Moved {
+                    highlight,
+                    opposite_pos,
+                } => MatchKind::Moved {
+                    highlight: *highlight,
+                    opposite_pos: opposite_tabs.expand_spans(opposite_pos),
+                },
+                kind @ (MatchKind::Novel { .. } | MatchKind::NovelWord { .. }) => kind.clone(),
+            };
+
+            MatchedPos {
+                kind,
+                pos: tabs.expand_span(&mp.pos),
+            }
+        })
+        .collect()
+}
+
+/// Replace the tabs in both sources with `tab_width` spaces, and
+/// adjust the positions so they refer to the expanded text.
+pub fn expand_tabs<'a>(
+    lhs_src: &'a str,
+    rhs_src: &'a str,
+    lhs_positions: &'a [MatchedPos],
+    rhs_positions: &'a [MatchedPos],
+    tab_width: usize,
+) -> (
+    Cow<'a, str>,
+    Cow<'a, str>,
+    Cow<'a, [MatchedPos]>,
+    Cow<'a, [MatchedPos]>,
+) {
+    if !lhs_src.contains('\t') && !rhs_src.contains('\t') {
+        return (
+            Cow::Borrowed(lhs_src),
+            Cow::Borrowed(rhs_src),
+            Cow::Borrowed(lhs_positions),
+            Cow::Borrowed(rhs_positions),
+        );
+    }
+
+    let lhs_tabs = TabOffsets::new(lhs_src, tab_width);
+    let rhs_tabs = TabOffsets::new(rhs_src, tab_width);
+    let spaces = " ".repeat(tab_width);
+
+    (
+        Cow::Owned(lhs_src.replace('\t', &spaces)),
+        Cow::Owned(rhs_src.replace('\t', &spaces)),
+        Cow::Owned(expand_positions(lhs_positions, &lhs_tabs, &rhs_tabs)),
+        Cow::Owned(expand_positions(rhs_positions, &rhs_tabs, &lhs_tabs)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::syntax::{AtomKind, TokenKind};
+    use pretty_assertions::assert_eq;
+
+    fn novel(line: u32, start_col: u32, end_col: u32) -> MatchedPos {
+        MatchedPos {
+            kind: MatchKind::Novel {
+                highlight: TokenKind::Atom(AtomKind::Normal),
+            },
+            pos: SingleLineSpan {
+                line: line.into(),
+                start_col,
+                end_col,
+            },
+        }
+    }
+
+    #[test]
+    fn test_expand_tabs_adjusts_columns() {
+        let lhs_positions = vec![novel(0, 1, 4), novel(1, 0, 3)];
+        let (lhs_src, _, lhs_positions, _) =
+            expand_tabs("\tfoo\nbar\t", "", &lhs_positions, &[], 4);
+
+        assert_eq!(lhs_src, "    foo\nbar    ");
+        assert_eq!(lhs_positions.to_vec(), vec![novel(0, 4, 7), novel(1, 0, 3)]);
+    }
+
+    #[test]
+    fn test_expand_tabs_without_tabs() {
+        let positions = vec![novel(0, 0, 3)];
+        let (lhs_src, _, lhs_positions, _) = expand_tabs("foo", "", &positions, &[], 4);
+
+        assert!(matches!(lhs_src, Cow::Borrowed(_)));
+        assert!(matches!(lhs_positions, Cow::Borrowed(_)));
+    }
+}
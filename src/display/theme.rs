@@ -0,0 +1,413 @@
+//! Colour themes for terminal output.
+//!
+//! A theme file is a TOML document that overrides some of the styles
+//! of the default theme for the current background:
+//!
+//! ```toml
+//! novel-lhs = { fg = 208 }
+//! novel-rhs = { fg = "#0087ff" }
+//! header = { fg = "bright-white", bold = true }
+//!
+//! [unchanged]
+//! comment = { fg = "bright-black", italic = true }
+//! ```
+//!
+//! Colours can be ANSI colour names (optionally prefixed with
+//! `bright-`), 256-colour palette indexes, or `#rrggbb` truecolor
+//! values.
+
+use std::{fs, path::Path};
+
+use owo_colors::{AnsiColors, DynColors, Style, XtermColors};
+use serde::{de::Error, Deserialize, Deserializer};
+
+use crate::{
+    constants::Side,
+    display::style::BackgroundColor,
+    exit_codes::EXIT_ERROR,
+    parse::syntax::{AtomKind, TokenKind},
+};
+
+/// The names of the built-in themes.
+pub const BUILTIN_THEMES: &[&str] = &["default", "colorblind"];
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ThemeStyle {
+    #[serde(default, deserialize_with = "deserialize_color")]
+    pub fg: Option<DynColors>,
+    #[serde(default)]
+    pub bold: bool,
+    #[serde(default)]
+    pub italic: bool,
+    #[serde(default)]
+    pub underline: bool,
+    #[serde(default)]
+    pub dimmed: bool,
+}
+
+impl ThemeStyle {
+    fn fg(color: AnsiColors) -> Self {
+        Self {
+            fg: Some(DynColors::Ansi(color)),
+            ..Self::default()
+        }
+    }
+
+    fn xterm(index: u8) -> Self {
+        Self {
+            fg: Some(DynColors::Xterm(XtermColors::from(index))),
+            ..Self::default()
+        }
+    }
+
+    fn bold() -> Self {
+        Self {
+            bold: true,
+            ..Self::default()
+        }
+    }
+
+    fn italic(self) -> Self {
+        Self {
+            italic: true,
+            ..self
+        }
+    }
+
+    /// Add this style's colour and attributes to `style`.
+    pub fn apply(&self, mut style: Style) -> Style {
+        if let Some(fg) = self.fg {
+            style = style.color(fg);
+        }
+        if self.bold {
+            style = style.bold();
+        }
+        if self.italic {
+            style = style.italic();
+        }
+        if self.underline {
+            style = style.underline();
+        }
+        if self.dimmed {
+            style = style.dimmed();
+        }
+        style
+    }
+}
+
+/// Styles for each kind of token.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TokenStyles {
+    pub normal: ThemeStyle,
+    pub string: ThemeStyle,
+    pub comment: ThemeStyle,
+    pub keyword: ThemeStyle,
+    pub type_: ThemeStyle,
+    pub delimiter: ThemeStyle,
+    pub error: ThemeStyle,
+}
+
+impl TokenStyles {
+    pub fn get(&self, kind: TokenKind) -> &ThemeStyle {
+        match kind {
+            TokenKind::Delimiter => &self.delimiter,
+            TokenKind::Atom(AtomKind::Normal) => &self.normal,
+            TokenKind::Atom(AtomKind::String) => &self.string,
+            TokenKind::Atom(AtomKind::Comment) => &self.comment,
+            TokenKind::Atom(AtomKind::Keyword) => &self.keyword,
+            TokenKind::Atom(AtomKind::Type) => &self.type_,
+            TokenKind::Atom(AtomKind::TreeSitterError) => &self.error,
+        }
+    }
+
+    fn get_mut(&mut self, name: &str) -> Option<&mut ThemeStyle> {
+        match name {
+            "normal" => Some(&mut self.normal),
+            "string" => Some(&mut self.string),
+            "comment" => Some(&mut self.comment),
+            "keyword" => Some(&mut self.keyword),
+            "type" => Some(&mut self.type_),
+            "delimiter" => Some(&mut self.delimiter),
+            "error" => Some(&mut self.error),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Theme {
+    /// The colour of removed content.
+    pub novel_lhs: ThemeStyle,
+    /// The colour of added content.
+    pub novel_rhs: ThemeStyle,
+    pub moved: ThemeStyle,
+    pub header: ThemeStyle,
+    /// Line numbers of lines without changes.
+    pub line_number: ThemeStyle,
+    /// Line numbers of lines with changes, applied on top of the
+    /// novel colour.
+    pub novel_line_number: ThemeStyle,
+    /// Syntax highlighting for unchanged tokens.
+    pub unchanged: TokenStyles,
+    /// Styles for novel tokens, applied on top of the novel colour.
+    pub novel: TokenStyles,
+}
+
+impl Theme {
+    /// The default red/green theme.
+    pub fn default_for(background: BackgroundColor) -> Self {
+        let (red, green, cyan, yellow, magenta, blue) = if background.is_dark() {
+            (
+                AnsiColors::BrightRed,
+                AnsiColors::BrightGreen,
+                AnsiColors::BrightCyan,
+                AnsiColors::BrightYellow,
+                AnsiColors::BrightMagenta,
+                AnsiColors::BrightBlue,
+            )
+        } else {
+            (
+                AnsiColors::Red,
+                AnsiColors::Green,
+                AnsiColors::Cyan,
+                AnsiColors::Yellow,
+                AnsiColors::Magenta,
+                AnsiColors::Blue,
+            )
+        };
+
+        Self {
+            novel_lhs: ThemeStyle::fg(red),
+            novel_rhs: ThemeStyle::fg(green),
+            moved: ThemeStyle::fg(cyan),
+            header: ThemeStyle {
+                bold: true,
+                ..ThemeStyle::fg(yellow)
+            },
+            line_number: ThemeStyle {
+                dimmed: true,
+                ..ThemeStyle::default()
+            },
+            novel_line_number: ThemeStyle::bold(),
+            unchanged: TokenStyles {
+                string: ThemeStyle::fg(magenta),
+                comment: ThemeStyle::fg(blue).italic(),
+                keyword: ThemeStyle::bold(),
+                type_: ThemeStyle::bold(),
+                error: ThemeStyle::fg(AnsiColors::Magenta),
+                ..TokenStyles::default()
+            },
+            novel: TokenStyles {
+                comment: ThemeStyle::default().italic(),
+                keyword: ThemeStyle::bold(),
+                type_: ThemeStyle::bold(),
+                delimiter: ThemeStyle::bold(),
+                ..TokenStyles::default()
+            },
+        }
+    }
+
+    /// A theme that shows removals in orange and additions in blue,
+    /// which are distinguishable with most forms of colour
+    /// blindness.
+    pub fn colorblind(background: BackgroundColor) -> Self {
+        let (orange, blue, purple, grey) = if background.is_dark() {
+            (208, 39, 141, 245)
+        } else {
+            (166, 25, 91, 242)
+        };
+
+        Self {
+            novel_lhs: ThemeStyle::xterm(orange),
+            novel_rhs: ThemeStyle::xterm(blue),
+            moved: ThemeStyle::xterm(purple),
+            unchanged: TokenStyles {
+                comment: ThemeStyle::xterm(grey).italic(),
+                ..Self::default_for(background).unchanged
+            },
+            ..Self::default_for(background)
+        }
+    }
+
+    /// The style for novel content on `side`.
+    pub fn novel_color(&self, side: Side) -> &ThemeStyle {
+        match side {
+            Side::Left => &self.novel_lhs,
+            Side::Right => &self.novel_rhs,
+        }
+    }
+
+    /// Override styles in this theme with the styles in `src`, a
+    /// theme file.
+    fn apply_overrides(&mut self, src: &str) -> Result<(), String> {
+        let table: toml::value::Table = toml::from_str(src).map_err(|e| e.to_string())?;
+
+        for (name, value) in table {
+            match name.as_str() {
+                "unchanged" | "novel" => {
+                    let token_styles = if name == "unchanged" {
+                        &mut self.unchanged
+                    } else {
+                        &mut self.novel
+                    };
+                    let kinds: toml::value::Table = value
+                        .try_into()
+                        .map_err(|e| format!("Invalid [{}]: {}", name, e))?;
+                    for (kind, value) in kinds {
+                        let style = token_styles
+                            .get_mut(&kind)
+                            .ok_or_else(|| format!("Unknown token kind: {}.{}", name, kind))?;
+                        *style = value
+                            .try_into()
+                            .map_err(|e| format!("Invalid {}.{}: {}", name, kind, e))?;
+                    }
+                }
+                _ => {
+                    let style = match name.as_str() {
+                        "novel-lhs" => &mut self.novel_lhs,
+                        "novel-rhs" => &mut self.novel_rhs,
+                        "moved" => &mut self.moved,
+                        "header" => &mut self.header,
+                        "line-number" => &mut self.line_number,
+                        "novel-line-number" => &mut self.novel_line_number,
+                        _ => return Err(format!("Unknown style: {}", name)),
+                    };
+                    *style = value
+                        .try_into()
+                        .map_err(|e| format!("Invalid {}: {}", name, e))?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Parse a colour name, such as `red`, `bright-red` or `#ff0000`.
+fn parse_color(name: &str) -> Option<DynColors> {
+    if let Some(hex) = name.strip_prefix('#') {
+        if hex.len() != 6 {
+            return None;
+        }
+        let component = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+        return Some(DynColors::Rgb(component(0)?, component(2)?, component(4)?));
+    }
+
+    let color = match name {
+        "black" => AnsiColors::Black,
+        "red" => AnsiColors::Red,
+        "green" => AnsiColors::Green,
+        "yellow" => AnsiColors::Yellow,
+        "blue" => AnsiColors::Blue,
+        "magenta" => AnsiColors::Magenta,
+        "cyan" => AnsiColors::Cyan,
+        "white" => AnsiColors::White,
+        "bright-black" => AnsiColors::BrightBlack,
+        "bright-red" => AnsiColors::BrightRed,
+        "bright-green" => AnsiColors::BrightGreen,
+        "bright-yellow" => AnsiColors::BrightYellow,
+        "bright-blue" => AnsiColors::BrightBlue,
+        "bright-magenta" => AnsiColors::BrightMagenta,
+        "bright-cyan" => AnsiColors::BrightCyan,
+        "bright-white" => AnsiColors::BrightWhite,
+        _ => return None,
+    };
+    Some(DynColors::Ansi(color))
+}
+
+fn deserialize_color<'de, D>(deserializer: D) -> Result<Option<DynColors>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum ColorValue {
+        Palette(u8),
+        Name(String),
+    }
+
+    match ColorValue::deserialize(deserializer)? {
+        ColorValue::Palette(index) => Ok(Some(DynColors::Xterm(XtermColors::from(index)))),
+        ColorValue::Name(name) => parse_color(&name)
+            .map(Some)
+            .ok_or_else(|| D::Error::custom(format!("Invalid color: {}", name))),
+    }
+}
+
+/// Load the theme called `name`, which is either a built-in theme or
+/// the path to a theme file. Terminate the process if the theme can't
+/// be loaded.
+pub fn load_or_die(name: &str, background: BackgroundColor) -> Theme {
+    match name {
+        "default" => return Theme::default_for(background),
+        "colorblind" => return Theme::colorblind(background),
+        _ => {}
+    }
+
+    let path = Path::new(name);
+    let src = match fs::read_to_string(path) {
+        Ok(src) => src,
+        Err(e) => {
+            eprintln!(
+                "error: Could not read theme {}: {}\nBuilt-in themes are: {}",
+                path.display(),
+                e,
+                BUILTIN_THEMES.join(", ")
+            );
+            std::process::exit(EXIT_ERROR);
+        }
+    };
+
+    let mut theme = Theme::default_for(background);
+    if let Err(e) = theme.apply_overrides(&src) {
+        eprintln!("error: Invalid theme {}: {}", path.display(), e);
+        std::process::exit(EXIT_ERROR);
+    }
+    theme
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_parse_color() {
+        assert_eq!(parse_color("red"), Some(DynColors::Ansi(AnsiColors::Red)));
+        assert_eq!(
+            parse_color("bright-blue"),
+            Some(DynColors::Ansi(AnsiColors::BrightBlue))
+        );
+        assert_eq!(parse_color("#0087ff"), Some(DynColors::Rgb(0, 0x87, 0xff)));
+        assert_eq!(parse_color("#08f"), None);
+        assert_eq!(parse_color("reddish"), None);
+    }
+
+    #[test]
+    fn test_apply_overrides() {
+        let mut theme = Theme::default_for(BackgroundColor::Dark);
+        theme
+            .apply_overrides(
+                "novel-lhs = { fg = 208 }\n\n[unchanged]\ncomment = { fg = \"#808080\", italic = true }\n",
+            )
+            .unwrap();
+
+        let mut expected = Theme::default_for(BackgroundColor::Dark);
+        expected.novel_lhs = ThemeStyle::xterm(208);
+        expected.unchanged.comment = ThemeStyle {
+            fg: Some(DynColors::Rgb(0x80, 0x80, 0x80)),
+            italic: true,
+            ..ThemeStyle::default()
+        };
+        assert_eq!(theme, expected);
+    }
+
+    #[test]
+    fn test_apply_overrides_unknown_style() {
+        let mut theme = Theme::default_for(BackgroundColor::Dark);
+        assert!(theme.apply_overrides("novel = 1\n").is_err());
+        assert!(theme.apply_overrides("[novel]\nnumber = {}\n").is_err());
+        assert!(theme.apply_overrides("headr = {}\n").is_err());
+    }
+}
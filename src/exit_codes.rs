@@ -0,0 +1,6 @@
+//! Process exit codes.
+
+/// The exit code for errors, such as invalid arguments or files that
+/// can't be read. This matches clap, and is distinct from the codes
+/// `--exit-code` uses to describe changes.
+pub const EXIT_ERROR: i32 = 2;
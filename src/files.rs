@@ -2,15 +2,19 @@
 
 use std::io::Read;
 use std::{
+    cmp::{min, Ordering},
     fs,
+    hash::{Hash, Hasher},
     io::ErrorKind::*,
+    mem,
     path::{Path, PathBuf},
 };
 
-use rustc_hash::FxHashSet;
+use rustc_hash::{FxHashMap, FxHashSet, FxHasher};
+use strsim::normalized_levenshtein;
 use walkdir::WalkDir;
 
-use crate::options::FileArgument;
+use crate::{exit_codes::EXIT_ERROR, options::FileArgument};
 
 pub fn read_files_or_die(
     lhs_path: &FileArgument,
@@ -39,7 +43,7 @@ pub fn read_files_or_die(
             if let Err(e) = rhs_res {
                 eprint_read_error(rhs_path, &e);
             }
-            std::process::exit(1);
+            std::process::exit(EXIT_ERROR);
         }
     }
 }
@@ -96,7 +100,7 @@ pub fn read_or_die(path: &Path) -> Vec<u8> {
         Ok(src) => src,
         Err(e) => {
             eprint_read_error(&FileArgument::NamedPath(path.to_path_buf()), &e);
-            std::process::exit(1);
+            std::process::exit(EXIT_ERROR);
         }
     }
 }
@@ -193,14 +197,11 @@ fn relative_file_paths_in_dir(dir: &Path) -> Vec<PathBuf> {
         .collect()
 }
 
-/// Walk `lhs_dir` and `rhs_dir`, and return relative paths of files
-/// that occur in at least one directory.
+/// Return relative paths of files that occur in at least one of
+/// `lhs_paths` and `rhs_paths`.
 ///
 /// Attempts to preserve the ordering of files in both directories.
-pub fn relative_paths_in_either(lhs_dir: &Path, rhs_dir: &Path) -> Vec<PathBuf> {
-    let lhs_paths = relative_file_paths_in_dir(lhs_dir);
-    let rhs_paths = relative_file_paths_in_dir(rhs_dir);
-
+fn relative_paths_in_either(lhs_paths: &[PathBuf], rhs_paths: &[PathBuf]) -> Vec<PathBuf> {
     let mut seen = FxHashSet::default();
     let mut res: Vec<PathBuf> = vec![];
 
@@ -240,12 +241,219 @@ pub fn relative_paths_in_either(lhs_dir: &Path, rhs_dir: &Path) -> Vec<PathBuf>
         }
     }
 
-    res.extend(lhs_paths.into_iter().skip(i));
-    res.extend(rhs_paths.into_iter().skip(j));
+    res.extend(lhs_paths.iter().skip(i).cloned());
+    res.extend(rhs_paths.iter().skip(j).cloned());
 
     res
 }
 
+/// The minimum similarity for us to consider two files with
+/// different names to be a rename. This is the same default as git.
+const RENAME_SIMILARITY_THRESHOLD: f64 = 0.5;
+
+/// If there are more than this many possible pairs of added and
+/// removed files, only detect renames where the content is
+/// identical. Comparing the content of every pair is slow.
+const RENAME_PAIR_LIMIT: usize = 100_000;
+
+fn content_hash(bytes: &[u8]) -> u64 {
+    let mut hasher = FxHasher::default();
+    bytes.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Count the occurrences of each line in `bytes`, by hash.
+fn line_counts(bytes: &[u8]) -> FxHashMap<u64, usize> {
+    let mut counts = FxHashMap::default();
+    for line in bytes.split(|b| *b == b'\n') {
+        *counts.entry(content_hash(line)).or_insert(0) += 1;
+    }
+    counts
+}
+
+/// How similar are the lines in these two files? Returns a value
+/// between 0.0 (no lines in common) and 1.0 (the same lines).
+fn line_similarity(lhs: &FxHashMap<u64, usize>, rhs: &FxHashMap<u64, usize>) -> f64 {
+    let lhs_total: usize = lhs.values().sum();
+    let rhs_total: usize = rhs.values().sum();
+    if lhs_total + rhs_total == 0 {
+        return 0.0;
+    }
+
+    let common: usize = lhs
+        .iter()
+        .map(|(hash, lhs_count)| min(*lhs_count, rhs.get(hash).copied().unwrap_or(0)))
+        .sum();
+
+    (2 * common) as f64 / (lhs_total + rhs_total) as f64
+}
+
+fn name_similarity(lhs_path: &Path, rhs_path: &Path) -> f64 {
+    normalized_levenshtein(&lhs_path.to_string_lossy(), &rhs_path.to_string_lossy())
+}
+
+/// Given the relative paths that only exist in `lhs_dir` and the
+/// relative paths that only exist in `rhs_dir`, find files that
+/// have probably been renamed.
+///
+/// Files are paired if they have identical content, or failing that
+/// if enough lines are the same. Ties are broken by preferring files
+/// with similar names. Empty files, binary files and files larger
+/// than `byte_limit` are never paired.
+///
+/// Returns the paired paths along with their content.
+fn find_renames(
+    lhs_dir: &Path,
+    rhs_dir: &Path,
+    lhs_only: &[PathBuf],
+    rhs_only: &[PathBuf],
+    byte_limit: usize,
+) -> Vec<(PathBuf, PathBuf, FileContents)> {
+    let read_candidates = |dir: &Path, paths: &[PathBuf]| -> Vec<(PathBuf, Vec<u8>)> {
+        paths
+            .iter()
+            .filter(|path| match fs::metadata(dir.join(path)) {
+                Ok(metadata) => metadata.len() <= byte_limit as u64,
+                Err(_) => false,
+            })
+            .filter_map(|path| match fs::read(dir.join(path)) {
+                Ok(bytes)
+                    if !bytes.is_empty()
+                        && !matches!(guess_content(&bytes), ProbableFileKind::Binary) =>
+                {
+                    Some((path.clone(), bytes))
+                }
+                _ => None,
+            })
+            .collect()
+    };
+    let mut lhs_files = read_candidates(lhs_dir, lhs_only);
+    let mut rhs_files = read_candidates(rhs_dir, rhs_only);
+
+    // Score every pair: exact content matches are 1.0, otherwise
+    // use the proportion of lines in common.
+    let mut candidates: Vec<(f64, f64, usize, usize)> = vec![];
+    if lhs_files.len() * rhs_files.len() <= RENAME_PAIR_LIMIT {
+        let lhs_lines: Vec<_> = lhs_files.iter().map(|(_, b)| line_counts(b)).collect();
+        let rhs_lines: Vec<_> = rhs_files.iter().map(|(_, b)| line_counts(b)).collect();
+
+        for (i, (lhs_path, lhs_bytes)) in lhs_files.iter().enumerate() {
+            for (j, (rhs_path, rhs_bytes)) in rhs_files.iter().enumerate() {
+                let similarity = if lhs_bytes == rhs_bytes {
+                    1.0
+                } else {
+                    line_similarity(&lhs_lines[i], &rhs_lines[j])
+                };
+
+                if similarity >= RENAME_SIMILARITY_THRESHOLD {
+                    candidates.push((similarity, name_similarity(lhs_path, rhs_path), i, j));
+                }
+            }
+        }
+    } else {
+        let mut lhs_by_hash: FxHashMap<u64, Vec<usize>> = FxHashMap::default();
+        for (i, (_, lhs_bytes)) in lhs_files.iter().enumerate() {
+            lhs_by_hash
+                .entry(content_hash(lhs_bytes))
+                .or_default()
+                .push(i);
+        }
+
+        for (j, (rhs_path, rhs_bytes)) in rhs_files.iter().enumerate() {
+            for i in lhs_by_hash.get(&content_hash(rhs_bytes)).unwrap_or(&vec![]) {
+                let (lhs_path, lhs_bytes) = &lhs_files[*i];
+                if lhs_bytes == rhs_bytes {
+                    candidates.push((1.0, name_similarity(lhs_path, rhs_path), *i, j));
+                }
+            }
+        }
+    }
+
+    // Greedily take the best pairs first.
+    candidates.sort_by(|a, b| {
+        b.0.partial_cmp(&a.0)
+            .unwrap_or(Ordering::Equal)
+            .then(b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal))
+    });
+
+    let mut lhs_used = FxHashSet::default();
+    let mut rhs_used = FxHashSet::default();
+    let mut res = vec![];
+    for (_, _, i, j) in candidates {
+        if lhs_used.contains(&i) || rhs_used.contains(&j) {
+            continue;
+        }
+        lhs_used.insert(i);
+        rhs_used.insert(j);
+
+        let (lhs_path, lhs_bytes) = mem::take(&mut lhs_files[i]);
+        let (rhs_path, rhs_bytes) = mem::take(&mut rhs_files[j]);
+        res.push((lhs_path, rhs_path, (lhs_bytes, rhs_bytes)));
+    }
+
+    res
+}
+
+/// The content of a pair of files that we have already read.
+pub type FileContents = (Vec<u8>, Vec<u8>);
+
+/// Walk `lhs_dir` and `rhs_dir`, and return pairs of relative paths
+/// to compare.
+///
+/// Files with the same relative path are compared with each
+/// other. Files that only occur in one directory are compared with
+/// a similar file that only occurs in the other directory, as
+/// they've probably been renamed. Any remaining files are compared
+/// with their (missing) counterpart.
+///
+/// Renamed files have already been read, so their content is
+/// returned too.
+pub fn relative_path_pairs(
+    lhs_dir: &Path,
+    rhs_dir: &Path,
+    byte_limit: usize,
+) -> Vec<(PathBuf, PathBuf, Option<FileContents>)> {
+    let lhs_paths = relative_file_paths_in_dir(lhs_dir);
+    let rhs_paths = relative_file_paths_in_dir(rhs_dir);
+
+    let lhs_set: FxHashSet<PathBuf> = lhs_paths.iter().cloned().collect();
+    let rhs_set: FxHashSet<PathBuf> = rhs_paths.iter().cloned().collect();
+
+    let lhs_only: Vec<PathBuf> = lhs_paths
+        .iter()
+        .filter(|p| !rhs_set.contains(*p))
+        .cloned()
+        .collect();
+    let rhs_only: Vec<PathBuf> = rhs_paths
+        .iter()
+        .filter(|p| !lhs_set.contains(*p))
+        .cloned()
+        .collect();
+
+    let mut renames: FxHashMap<PathBuf, (PathBuf, FileContents)> =
+        if lhs_only.is_empty() || rhs_only.is_empty() {
+            FxHashMap::default()
+        } else {
+            find_renames(lhs_dir, rhs_dir, &lhs_only, &rhs_only, byte_limit)
+                .into_iter()
+                .map(|(lhs_path, rhs_path, contents)| (lhs_path, (rhs_path, contents)))
+                .collect()
+        };
+    let rename_targets: FxHashSet<PathBuf> = renames
+        .values()
+        .map(|(rhs_path, _)| rhs_path.clone())
+        .collect();
+
+    relative_paths_in_either(&lhs_paths, &rhs_paths)
+        .into_iter()
+        .filter(|path| !rename_targets.contains(path) || lhs_set.contains(path))
+        .map(|path| match renames.remove(&path) {
+            Some((new_path, contents)) => (path, new_path, Some(contents)),
+            None => (path.clone(), path, None),
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -266,4 +474,138 @@ mod tests {
             ProbableFileKind::Binary
         ));
     }
+
+    #[test]
+    fn test_line_similarity() {
+        let lhs = line_counts(b"a\nb\nc\nd");
+        let rhs = line_counts(b"a\nb\nc\nx");
+        assert_eq!(line_similarity(&lhs, &rhs), 0.75);
+    }
+
+    #[test]
+    fn test_line_similarity_disjoint() {
+        let lhs = line_counts(b"a\nb");
+        let rhs = line_counts(b"c\nd");
+        assert_eq!(line_similarity(&lhs, &rhs), 0.0);
+    }
+
+    /// Create a fresh temporary directory named `name` containing
+    /// `files`, which are pairs of relative paths and contents.
+    fn test_dir(name: &str, files: &[(&str, &[u8])]) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("difftastic-test-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        for (path, content) in files {
+            fs::write(dir.join(path), content).unwrap();
+        }
+        dir
+    }
+
+    /// Find renames between `lhs_files` and `rhs_files`, returning
+    /// the pairs of paths.
+    fn renames(
+        name: &str,
+        lhs_files: &[(&str, &[u8])],
+        rhs_files: &[(&str, &[u8])],
+        byte_limit: usize,
+    ) -> Vec<(PathBuf, PathBuf)> {
+        let lhs_dir = test_dir(&format!("{}-lhs", name), lhs_files);
+        let rhs_dir = test_dir(&format!("{}-rhs", name), rhs_files);
+        let lhs_only: Vec<PathBuf> = lhs_files.iter().map(|(p, _)| PathBuf::from(p)).collect();
+        let rhs_only: Vec<PathBuf> = rhs_files.iter().map(|(p, _)| PathBuf::from(p)).collect();
+
+        let res = find_renames(&lhs_dir, &rhs_dir, &lhs_only, &rhs_only, byte_limit)
+            .into_iter()
+            .map(|(lhs_path, rhs_path, _)| (lhs_path, rhs_path))
+            .collect();
+
+        fs::remove_dir_all(&lhs_dir).unwrap();
+        fs::remove_dir_all(&rhs_dir).unwrap();
+        res
+    }
+
+    fn path_pair(lhs_path: &str, rhs_path: &str) -> (PathBuf, PathBuf) {
+        (PathBuf::from(lhs_path), PathBuf::from(rhs_path))
+    }
+
+    #[test]
+    fn test_find_renames_prefers_exact_match() {
+        let res = renames(
+            "exact",
+            &[("a.txt", b"a\nb\nc\nd")],
+            &[("a2.txt", b"a\nb\nc\nx"), ("b.txt", b"a\nb\nc\nd")],
+            1000,
+        );
+        assert_eq!(res, vec![path_pair("a.txt", "b.txt")]);
+    }
+
+    #[test]
+    fn test_find_renames_prefers_similar_name() {
+        let res = renames(
+            "name",
+            &[("foo.txt", b"a\nb")],
+            &[("bar.txt", b"a\nb"), ("foo2.txt", b"a\nb")],
+            1000,
+        );
+        assert_eq!(res, vec![path_pair("foo.txt", "foo2.txt")]);
+    }
+
+    #[test]
+    fn test_find_renames_threshold() {
+        let res = renames(
+            "threshold",
+            &[("a.txt", b"a\nb\nc\nd"), ("b.txt", b"e\nf\ng\nh")],
+            &[("c.txt", b"a\nb\nx\ny"), ("d.txt", b"e\nx\ny\nz")],
+            1000,
+        );
+        assert_eq!(res, vec![path_pair("a.txt", "c.txt")]);
+    }
+
+    #[test]
+    fn test_find_renames_skips_unsuitable_files() {
+        let res = renames(
+            "unsuitable",
+            &[
+                ("empty.txt", b""),
+                ("binary.dat", &[0; 100]),
+                ("large.txt", b"a\nb\nc\nd\ne\nf"),
+            ],
+            &[
+                ("empty2.txt", b""),
+                ("binary2.dat", &[0; 100]),
+                ("large2.txt", b"a\nb\nc\nd\ne\nf"),
+            ],
+            10,
+        );
+        assert_eq!(res, vec![]);
+    }
+
+    #[test]
+    fn test_relative_path_pairs_with_rename() {
+        let lhs_dir = test_dir("pairs-lhs", &[("old.txt", b"a\nb"), ("same.txt", b"x")]);
+        let rhs_dir = test_dir("pairs-rhs", &[("new.txt", b"a\nb"), ("same.txt", b"y")]);
+
+        let res = relative_path_pairs(&lhs_dir, &rhs_dir, 1000);
+        fs::remove_dir_all(&lhs_dir).unwrap();
+        fs::remove_dir_all(&rhs_dir).unwrap();
+
+        // The rename target isn't also compared as an added file.
+        let paths: Vec<_> = res
+            .iter()
+            .map(|(lhs_path, rhs_path, _)| (lhs_path.clone(), rhs_path.clone()))
+            .collect();
+        assert_eq!(
+            paths,
+            vec![
+                path_pair("old.txt", "new.txt"),
+                path_pair("same.txt", "same.txt")
+            ]
+        );
+
+        // Renamed files are returned with the content we've read.
+        assert_eq!(res[0].2, Some((b"a\nb".to_vec(), b"a\nb".to_vec())));
+        assert_eq!(res[1].2, None);
+    }
 }
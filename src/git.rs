@@ -0,0 +1,268 @@
+//! Reading files directly from a local git repository.
+//!
+//! This shells out to the `git` binary, so we see exactly the same
+//! objects that `git diff` would, including any rename detection
+//! configured for the repository.
+
+use std::{
+    ffi::OsString,
+    io::{BufRead, BufReader, Read, Write},
+    path::PathBuf,
+    process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+};
+
+/// A version of a file in a git revision.
+#[derive(Debug, PartialEq)]
+pub struct Blob {
+    /// The path of the file, relative to the repository root.
+    pub path: PathBuf,
+    /// The object ID of the file contents.
+    pub oid: String,
+}
+
+/// A file that differs between two git revisions.
+#[derive(Debug, PartialEq)]
+pub struct ChangedFile {
+    /// The file in the LHS revision. `None` if the file was added.
+    pub lhs: Option<Blob>,
+    /// The file in the RHS revision. `None` if the file was deleted.
+    pub rhs: Option<Blob>,
+}
+
+/// Run git with `args`, returning its stdout.
+fn run_git(args: &[OsString]) -> Result<Vec<u8>, String> {
+    let output = Command::new("git")
+        .args(args)
+        .output()
+        .map_err(|e| format!("Could not run git: {}", e))?;
+
+    if output.status.success() {
+        Ok(output.stdout)
+    } else {
+        Err(String::from_utf8_lossy(&output.stderr)
+            .trim_end()
+            .to_owned())
+    }
+}
+
+/// Convert a path printed by git to a `PathBuf`, preserving paths
+/// that aren't valid UTF-8 where the platform allows it.
+#[cfg(unix)]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    use std::os::unix::ffi::OsStrExt;
+    PathBuf::from(std::ffi::OsStr::from_bytes(bytes))
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+}
+
+/// Is `oid` the all-zeros object ID, which git uses for the missing
+/// side of an added or deleted file?
+fn is_null_oid(oid: &str) -> bool {
+    oid.bytes().all(|b| b == b'0')
+}
+
+/// Parse the output of `git diff --raw -z`.
+///
+/// Each entry is `:<lhs mode> <rhs mode> <lhs oid> <rhs oid> <status>`
+/// followed by the path, or both paths for renames and copies.
+fn parse_raw_diff(output: &[u8]) -> Result<Vec<ChangedFile>, String> {
+    let mut fields = output
+        .split(|b| *b == b'\0')
+        .filter(|field| !field.is_empty());
+
+    let mut changed_files = vec![];
+    while let Some(header) = fields.next() {
+        let header = String::from_utf8_lossy(header);
+        let parts: Vec<&str> = header.trim_start_matches(':').split(' ').collect();
+        let (lhs_oid, rhs_oid, status) = match parts[..] {
+            [_, _, lhs_oid, rhs_oid, status] => (lhs_oid, rhs_oid, status),
+            _ => return Err(format!("Unexpected output from git diff: {:?}", header)),
+        };
+
+        let lhs_path = fields.next().map(path_from_bytes);
+        // Renames and copies are followed by both the old and new
+        // paths.
+        let rhs_path = if status.starts_with('R') || status.starts_with('C') {
+            fields.next().map(path_from_bytes)
+        } else {
+            lhs_path.clone()
+        };
+
+        let blob = |path: Option<PathBuf>, oid: &str| match path {
+            Some(path) if !is_null_oid(oid) => Some(Blob {
+                path,
+                oid: oid.to_owned(),
+            }),
+            _ => None,
+        };
+        let changed_file = ChangedFile {
+            lhs: blob(lhs_path, lhs_oid),
+            rhs: blob(rhs_path, rhs_oid),
+        };
+
+        if changed_file.lhs.is_some() || changed_file.rhs.is_some() {
+            changed_files.push(changed_file);
+        }
+    }
+
+    Ok(changed_files)
+}
+
+/// Return all the files that differ between `lhs_rev` and `rhs_rev`,
+/// limited to `paths` if any are given.
+pub fn changed_files(
+    lhs_rev: &str,
+    rhs_rev: &str,
+    paths: &[OsString],
+) -> Result<Vec<ChangedFile>, String> {
+    let mut args: Vec<OsString> = [
+        "diff",
+        "--raw",
+        "-z",
+        "--no-abbrev",
+        "-M",
+        "--no-ext-diff",
+        "--ignore-submodules",
+        lhs_rev,
+        rhs_rev,
+        "--",
+    ]
+    .iter()
+    .map(OsString::from)
+    .collect();
+    args.extend(paths.iter().cloned());
+
+    let output = run_git(&args)
+        .map_err(|e| format!("Could not compare {} and {}: {}", lhs_rev, rhs_rev, e))?;
+    parse_raw_diff(&output)
+}
+
+/// Reads blobs from a single long-running `git cat-file --batch`
+/// process, rather than starting git for every file.
+pub struct BlobReader {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl BlobReader {
+    pub fn new() -> Result<Self, String> {
+        let mut child = Command::new("git")
+            .args(["cat-file", "--batch"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("Could not run git: {}", e))?;
+
+        let stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = BufReader::new(child.stdout.take().expect("stdout is piped"));
+        Ok(Self {
+            child,
+            stdin,
+            stdout,
+        })
+    }
+
+    /// Read the contents of `blob`.
+    pub fn read(&mut self, blob: &Blob) -> Result<Vec<u8>, String> {
+        let error = |e: std::io::Error| format!("Could not read {}: {}", blob.path.display(), e);
+
+        writeln!(self.stdin, "{}", blob.oid).map_err(error)?;
+        self.stdin.flush().map_err(error)?;
+
+        // The response is `<oid> <type> <size>` on its own line,
+        // followed by the contents and a newline.
+        let mut header = String::new();
+        self.stdout.read_line(&mut header).map_err(error)?;
+        let size = match header.split_whitespace().collect::<Vec<_>>()[..] {
+            [_, "blob", size] => size.parse::<usize>().ok(),
+            _ => None,
+        };
+        let size = size.ok_or_else(|| {
+            format!(
+                "Could not read {}: git returned {:?}",
+                blob.path.display(),
+                header.trim_end()
+            )
+        })?;
+
+        let mut contents = vec![0; size + 1];
+        self.stdout.read_exact(&mut contents).map_err(error)?;
+        contents.pop();
+        Ok(contents)
+    }
+}
+
+impl Drop for BlobReader {
+    fn drop(&mut self) {
+        // Don't leave a git process running after we're done.
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    const LHS_OID: &str = "1111111111111111111111111111111111111111";
+    const RHS_OID: &str = "2222222222222222222222222222222222222222";
+    const NULL_OID: &str = "0000000000000000000000000000000000000000";
+
+    fn blob(path: &str, oid: &str) -> Option<Blob> {
+        Some(Blob {
+            path: path.into(),
+            oid: oid.to_owned(),
+        })
+    }
+
+    #[test]
+    fn test_parse_raw_diff() {
+        let output = format!(
+            concat!(
+                ":100644 100644 {lhs} {rhs} M\0src/main.rs\0",
+                ":000000 100644 {null} {rhs} A\0new.rs\0",
+                ":100644 000000 {lhs} {null} D\0old.rs\0",
+                ":100644 100644 {lhs} {rhs} R087\0before.rs\0after.rs\0",
+            ),
+            lhs = LHS_OID,
+            rhs = RHS_OID,
+            null = NULL_OID
+        );
+        assert_eq!(
+            parse_raw_diff(output.as_bytes()).unwrap(),
+            vec![
+                ChangedFile {
+                    lhs: blob("src/main.rs", LHS_OID),
+                    rhs: blob("src/main.rs", RHS_OID),
+                },
+                ChangedFile {
+                    lhs: None,
+                    rhs: blob("new.rs", RHS_OID),
+                },
+                ChangedFile {
+                    lhs: blob("old.rs", LHS_OID),
+                    rhs: None,
+                },
+                ChangedFile {
+                    lhs: blob("before.rs", LHS_OID),
+                    rhs: blob("after.rs", RHS_OID),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_raw_diff_empty() {
+        assert_eq!(parse_raw_diff(b"").unwrap(), vec![]);
+    }
+
+    #[test]
+    fn test_parse_raw_diff_invalid() {
+        assert!(parse_raw_diff(b"garbage\0path\0").is_err());
+    }
+}
@@ -1,10 +1,14 @@
 //! A fallback "parser" for plain text.
 
-use std::hash::Hash;
+use std::{
+    cmp::{max, min},
+    hash::Hash,
+};
 
 use lazy_static::lazy_static;
 use regex::Regex;
 use rustc_hash::FxHashMap;
+use strsim::normalized_levenshtein;
 
 use crate::{
     diff::myers_diff,
@@ -164,10 +168,163 @@ fn line_len_in_bytes(line: &str) -> usize {
     }
 }
 
+/// Changed blocks with more than this many pairs of lines aren't
+/// aligned, as aligning is quadratic. Their lines are shown as novel.
+const MAX_ALIGNED_LINE_PAIRS: usize = 10_000;
+
+/// Comparing two lines is quadratic in their length, so changed
+/// blocks aren't aligned if the product of their total lengths
+/// exceeds this, e.g. when a long minified line has changed.
+const MAX_ALIGNED_BYTES_PRODUCT: usize = 10_000_000;
+
+/// How similar two changed lines must be, as a percentage, before we
+/// show which words changed between them.
+const MIN_LINE_SIMILARITY_PCT: u32 = 50;
+
+fn line_similarity_pct(line: &str, opposite_line: &str) -> u32 {
+    let line = line.trim();
+    let opposite_line = opposite_line.trim();
+
+    // The edit distance is at least the difference in length, so
+    // lines with very different lengths can't be similar. Skip the
+    // expensive comparison for them.
+    let len = line.chars().count();
+    let opposite_len = opposite_line.chars().count();
+    let (shorter, longer) = (min(len, opposite_len), max(len, opposite_len));
+    if shorter * 100 < longer * MIN_LINE_SIMILARITY_PCT as usize {
+        return 0;
+    }
+
+    (normalized_levenshtein(line, opposite_line) * 100.0).round() as u32
+}
+
+/// Pair up the lines in a changed block that are similar, so we can
+/// show which words changed. Pairs are in order, and have the
+/// highest total similarity possible.
+fn align_lines(lhs_lines: &[&str], rhs_lines: &[&str]) -> Vec<(usize, usize)> {
+    if lhs_lines.len() * rhs_lines.len() > MAX_ALIGNED_LINE_PAIRS {
+        return vec![];
+    }
+
+    let lhs_bytes: usize = lhs_lines.iter().map(|line| line.len()).sum();
+    let rhs_bytes: usize = rhs_lines.iter().map(|line| line.len()).sum();
+    if lhs_bytes.saturating_mul(rhs_bytes) > MAX_ALIGNED_BYTES_PRODUCT {
+        return vec![];
+    }
+
+    let similarities: Vec<Vec<u32>> = lhs_lines
+        .iter()
+        .map(|lhs_line| {
+            rhs_lines
+                .iter()
+                .map(|rhs_line| line_similarity_pct(lhs_line, rhs_line))
+                .collect()
+        })
+        .collect();
+
+    // best[i][j] is the highest total similarity when aligning
+    // lhs_lines[i..] with rhs_lines[j..].
+    let mut best = vec![vec![0; rhs_lines.len() + 1]; lhs_lines.len() + 1];
+    for i in (0..lhs_lines.len()).rev() {
+        for j in (0..rhs_lines.len()).rev() {
+            let mut score = max(best[i + 1][j], best[i][j + 1]);
+            if similarities[i][j] >= MIN_LINE_SIMILARITY_PCT {
+                score = max(score, best[i + 1][j + 1] + similarities[i][j]);
+            }
+            best[i][j] = score;
+        }
+    }
+
+    let mut pairs = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < lhs_lines.len() && j < rhs_lines.len() {
+        if similarities[i][j] >= MIN_LINE_SIMILARITY_PCT
+            && best[i][j] == best[i + 1][j + 1] + similarities[i][j]
+        {
+            pairs.push((i, j));
+            i += 1;
+            j += 1;
+        } else if best[i][j] == best[i + 1][j] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    pairs
+}
+
+/// Mark all of `line`, which starts at `offset`, as novel.
+fn novel_line(nlp: &NewlinePositions, offset: usize, line: &str) -> Option<MatchedPos> {
+    let len = line_len_in_bytes(line);
+    if len == 0 {
+        return None;
+    }
+
+    Some(MatchedPos {
+        kind: MatchKind::Novel {
+            highlight: TokenKind::Atom(AtomKind::Normal),
+        },
+        pos: nlp.from_offsets(offset, offset + len)[0],
+    })
+}
+
+/// Diff the words of two similar lines, so we only highlight the
+/// words that changed.
+fn word_positions(
+    lhs_line: &str,
+    rhs_line: &str,
+    lhs_offset: usize,
+    rhs_offset: usize,
+    lhs_nlp: &NewlinePositions,
+    rhs_nlp: &NewlinePositions,
+) -> Vec<MatchedPos> {
+    let lhs_line = &lhs_line[..line_len_in_bytes(lhs_line)];
+    let rhs_line = &rhs_line[..line_len_in_bytes(rhs_line)];
+
+    let mut lhs_offset = lhs_offset;
+    let mut rhs_offset = rhs_offset;
+
+    let mut res = vec![];
+    for diff_res in myers_diff::slice(&split_words(lhs_line), &split_words(rhs_line)) {
+        match diff_res {
+            myers_diff::DiffResult::Left(lhs_word) => {
+                let lhs_pos = lhs_nlp.from_offsets(lhs_offset, lhs_offset + lhs_word.len());
+                res.push(MatchedPos {
+                    kind: MatchKind::NovelWord {
+                        highlight: TokenKind::Atom(AtomKind::Normal),
+                    },
+                    pos: lhs_pos[0],
+                });
+
+                lhs_offset += lhs_word.len();
+            }
+            myers_diff::DiffResult::Both(lhs_word, rhs_word) => {
+                let lhs_pos = lhs_nlp.from_offsets(lhs_offset, lhs_offset + lhs_word.len());
+                let rhs_pos = rhs_nlp.from_offsets(rhs_offset, rhs_offset + rhs_word.len());
+
+                res.push(MatchedPos {
+                    kind: MatchKind::NovelLinePart {
+                        highlight: TokenKind::Atom(AtomKind::Normal),
+                        self_pos: lhs_pos[0],
+                        opposite_pos: rhs_pos,
+                    },
+                    pos: lhs_pos[0],
+                });
+
+                lhs_offset += lhs_word.len();
+                rhs_offset += rhs_word.len();
+            }
+            myers_diff::DiffResult::Right(rhs_word) => {
+                rhs_offset += rhs_word.len();
+            }
+        }
+    }
+
+    res
+}
+
 // TODO: Prefer src/opposite_src nomenclature as this function is called from both sides.
 pub fn change_positions(lhs_src: &str, rhs_src: &str) -> Vec<MatchedPos> {
-    // TODO: If either side is "", don't split each line by words
-    // pointlessly. This is common for file additions/removals.
     let lhs_nlp = NewlinePositions::from(lhs_src);
     let rhs_nlp = NewlinePositions::from(rhs_src);
 
@@ -198,50 +355,36 @@ pub fn change_positions(lhs_src: &str, rhs_src: &str) -> Vec<MatchedPos> {
                 }
             }
             TextChangeKind::Novel => {
-                let lhs_part = lhs_lines.join("");
-                let rhs_part = rhs_lines.join("");
-
-                for diff_res in myers_diff::slice(&split_words(&lhs_part), &split_words(&rhs_part))
-                {
-                    match diff_res {
-                        myers_diff::DiffResult::Left(lhs_word) => {
-                            if *lhs_word != "\n" {
-                                let lhs_pos =
-                                    lhs_nlp.from_offsets(lhs_offset, lhs_offset + lhs_word.len());
-                                res.push(MatchedPos {
-                                    kind: MatchKind::NovelWord {
-                                        highlight: TokenKind::Atom(AtomKind::Normal),
-                                    },
-                                    pos: lhs_pos[0],
-                                });
-                            }
-
-                            lhs_offset += lhs_word.len();
-                        }
-                        myers_diff::DiffResult::Both(lhs_word, rhs_word) => {
-                            if *lhs_word != "\n" {
-                                let lhs_pos =
-                                    lhs_nlp.from_offsets(lhs_offset, lhs_offset + lhs_word.len());
-                                let rhs_pos =
-                                    rhs_nlp.from_offsets(rhs_offset, rhs_offset + rhs_word.len());
-
-                                res.push(MatchedPos {
-                                    kind: MatchKind::NovelLinePart {
-                                        highlight: TokenKind::Atom(AtomKind::Normal),
-                                        self_pos: lhs_pos[0],
-                                        opposite_pos: rhs_pos,
-                                    },
-                                    pos: lhs_pos[0],
-                                });
-                            }
-
-                            lhs_offset += lhs_word.len();
-                            rhs_offset += rhs_word.len();
-                        }
-                        myers_diff::DiffResult::Right(rhs_word) => {
-                            rhs_offset += rhs_word.len();
-                        }
+                // Lines that aren't paired with a similar line on the
+                // other side are entirely novel.
+                let mut lhs_i = 0;
+                let mut rhs_i = 0;
+                let pairs = align_lines(&lhs_lines, &rhs_lines);
+                let end = (lhs_lines.len(), rhs_lines.len());
+
+                for (pair_lhs_i, pair_rhs_i) in pairs.into_iter().chain(std::iter::once(end)) {
+                    for lhs_line in &lhs_lines[lhs_i..pair_lhs_i] {
+                        res.extend(novel_line(&lhs_nlp, lhs_offset, lhs_line));
+                        lhs_offset += lhs_line.len();
+                    }
+                    for rhs_line in &rhs_lines[rhs_i..pair_rhs_i] {
+                        rhs_offset += rhs_line.len();
+                    }
+
+                    if (pair_lhs_i, pair_rhs_i) == end {
+                        break;
                     }
+
+                    let lhs_line = lhs_lines[pair_lhs_i];
+                    let rhs_line = rhs_lines[pair_rhs_i];
+                    res.extend(word_positions(
+                        lhs_line, rhs_line, lhs_offset, rhs_offset, &lhs_nlp, &rhs_nlp,
+                    ));
+                    lhs_offset += lhs_line.len();
+                    rhs_offset += rhs_line.len();
+
+                    lhs_i = pair_lhs_i + 1;
+                    rhs_i = pair_rhs_i + 1;
                 }
             }
         }
@@ -313,6 +456,45 @@ mod tests {
         assert!(positions[0].kind.is_novel());
     }
 
+    #[test]
+    fn test_align_lines() {
+        let lhs_lines = vec!["foo = 1\n", "unrelated\n", "bar = 2\n"];
+        let rhs_lines = vec!["foo = 10\n", "bar = 20\n"];
+
+        assert_eq!(align_lines(&lhs_lines, &rhs_lines), vec![(0, 0), (2, 1)]);
+    }
+
+    #[test]
+    fn test_align_lines_long_line() {
+        let lhs_line = "x".repeat(1_000_000);
+        let rhs_line = format!("{}y", lhs_line);
+
+        assert_eq!(align_lines(&[&lhs_line], &[&rhs_line]), vec![]);
+
+        let positions = change_positions(&lhs_line, &rhs_line);
+        assert_eq!(positions.len(), 1);
+        assert!(positions[0].kind.is_novel());
+    }
+
+    #[test]
+    fn test_positions_changed_word() {
+        let positions = change_positions("foo = 1\nbar\n", "foo = 2\nbar\n");
+
+        let novel_words: Vec<_> = positions
+            .iter()
+            .filter(|mp| matches!(mp.kind, MatchKind::NovelWord { .. }))
+            .map(|mp| mp.pos)
+            .collect();
+        assert_eq!(
+            novel_words,
+            vec![SingleLineSpan {
+                line: 0.into(),
+                start_col: 6,
+                end_col: 7
+            }]
+        );
+    }
+
     #[test]
     fn test_positions_novel_lhs() {
         let positions = change_positions("foo", "");
@@ -19,33 +19,38 @@
 // comments in the if/else.
 #![allow(clippy::bool_to_int_with_if)]
 
+mod config;
 mod constants;
 mod diff;
 mod display;
+mod exit_codes;
 mod files;
+mod git;
 mod line_parser;
 mod lines;
+mod merge;
 mod options;
 mod parse;
 mod positions;
 mod summary;
+#[cfg(test)]
+mod test_helpers;
 
 #[macro_use]
 extern crate log;
 
-use crate::diff::{dijkstra, unchanged};
+use crate::diff::{dijkstra, fallback, moves, unchanged};
 use crate::display::hunks::{matched_pos_to_hunks, merge_adjacent};
 use crate::parse::guess_language::LANG_EXTENSIONS;
 use crate::parse::syntax;
 use diff::changes::ChangeMap;
-use diff::dijkstra::ExceededGraphLimit;
+use diff::dijkstra::{ExceededLimit, SearchLimits};
 use display::context::opposite_positions;
-use files::{
-    guess_content, read_files_or_die, read_or_die, relative_paths_in_either, ProbableFileKind,
-};
+use exit_codes::EXIT_ERROR;
+use files::{guess_content, read_files_or_die, read_or_die, relative_path_pairs, ProbableFileKind};
 use log::info;
 use mimalloc::MiMalloc;
-use parse::guess_language::{guess, language_name};
+use parse::guess_language::{from_overrides, guess, language_name};
 
 /// The global allocator used by difftastic.
 ///
@@ -55,11 +60,19 @@ use parse::guess_language::{guess, language_name};
 static GLOBAL: MiMalloc = MiMalloc;
 
 use diff::sliders::fix_all_sliders;
-use options::{DisplayMode, DisplayOptions, FileArgument, Mode, DEFAULT_TAB_WIDTH};
+use options::{DiffOptions, DisplayMode, DisplayOptions, FileArgument, Mode};
 use owo_colors::OwoColorize;
 use rayon::prelude::*;
-use std::{env, path::Path};
-use summary::{DiffResult, FileContent};
+use rustc_hash::FxHashMap;
+use std::{
+    env,
+    ffi::OsString,
+    io::{self, Write},
+    mem,
+    path::Path,
+    time::Instant,
+};
+use summary::{ChangeStatus, DiffResult, FileContent};
 use syntax::init_next_prev;
 use typed_arena::Arena;
 
@@ -96,8 +109,6 @@ fn main() {
             let path = Path::new(&path);
             let bytes = read_or_die(path);
             let src = String::from_utf8_lossy(&bytes).to_string();
-            // TODO: Load display options rather than hard-coding.
-            let src = replace_tabs(&src, DEFAULT_TAB_WIDTH);
 
             let language = language_override.or_else(|| guess(path, &src));
             match language {
@@ -118,8 +129,6 @@ fn main() {
             let path = Path::new(&path);
             let bytes = read_or_die(path);
             let src = String::from_utf8_lossy(&bytes).to_string();
-            // TODO: Load display options rather than hard-coding.
-            let src = replace_tabs(&src, DEFAULT_TAB_WIDTH);
 
             let language = language_override.or_else(|| guess(path, &src));
             match language {
@@ -151,13 +160,30 @@ fn main() {
                 }
                 println!();
             }
+
+            for plugin in parse::plugins::all() {
+                let mut name = plugin.name.clone();
+                if use_color {
+                    name = name.bold().to_string();
+                }
+                print!("{} (plugin)", name);
+
+                let mut extensions = plugin.extensions.clone();
+                extensions.sort_unstable();
+
+                for extension in extensions {
+                    print!(" .{}", extension);
+                }
+                println!();
+            }
         }
         Mode::Diff {
-            graph_limit,
-            byte_limit,
+            diff_options,
             display_options,
             missing_as_empty,
             language_override,
+            set_exit_code,
+            check_only,
             lhs_path,
             rhs_path,
             lhs_display_path,
@@ -175,22 +201,22 @@ fn main() {
                 );
             }
 
-            match (&lhs_path, &rhs_path) {
+            let change_status = match (&lhs_path, &rhs_path) {
                 (
                     options::FileArgument::NamedPath(lhs_path),
                     options::FileArgument::NamedPath(rhs_path),
                 ) if lhs_path.is_dir() && rhs_path.is_dir() => {
-                    diff_directories(
-                        lhs_path,
-                        rhs_path,
-                        &display_options,
-                        graph_limit,
-                        byte_limit,
-                        language_override,
-                    )
-                    .for_each(|diff_result| {
-                        print_diff_result(&display_options, &diff_result);
-                    });
+                    // Relative paths only differ when we've paired a
+                    // renamed file, so report renames in the header,
+                    // as we do inside a VCS.
+                    let display_options = DisplayOptions {
+                        in_vcs: true,
+                        ..display_options
+                    };
+
+                    let diff_results =
+                        diff_directories(lhs_path, rhs_path, &diff_options, language_override);
+                    print_diff_results(&display_options, check_only, diff_results)
                 }
                 _ => {
                     let diff_result = diff_file(
@@ -198,27 +224,128 @@ fn main() {
                         &rhs_display_path,
                         &lhs_path,
                         &rhs_path,
-                        &display_options,
                         missing_as_empty,
-                        graph_limit,
-                        byte_limit,
+                        &diff_options,
                         language_override,
                     );
-                    print_diff_result(&display_options, &diff_result);
+
+                    print_diff_results(&display_options, check_only, rayon::iter::once(diff_result))
                 }
+            };
+
+            if set_exit_code {
+                std::process::exit(change_status.exit_code());
             }
         }
-    };
-}
+        Mode::Merge {
+            diff_options,
+            language_override,
+            base_path,
+            lhs_path,
+            rhs_path,
+            display_path,
+            output_path,
+        } => {
+            let read_text_or_die = |path: &Path| match guess_content(&read_or_die(path)) {
+                ProbableFileKind::Text(src) => src,
+                ProbableFileKind::Binary => {
+                    eprintln!("Can't merge binary file: {}", path.display());
+                    std::process::exit(EXIT_ERROR);
+                }
+            };
+            let base_src = read_text_or_die(&base_path);
+            let lhs_src = read_text_or_die(&lhs_path);
+            let rhs_src = read_text_or_die(&rhs_path);
 
-/// Return a copy of `str` with all the tab characters replaced by
-/// `tab_width` strings.
-///
-/// TODO: This break parsers that require tabs, such as Makefile
-/// parsing. We shouldn't do this transform until after parsing.
-fn replace_tabs(src: &str, tab_width: usize) -> String {
-    let tab_as_spaces = " ".repeat(tab_width);
-    src.replace('\t', &tab_as_spaces)
+            let guess_path = match &display_path {
+                Some(display_path) => Path::new(display_path),
+                None => &lhs_path,
+            };
+            let language = language_override
+                .or_else(|| from_overrides(guess_path, &diff_options.language_overrides))
+                .or_else(|| guess(guess_path, &lhs_src));
+
+            // Merge drivers are given temporary files, so their
+            // paths aren't useful as conflict labels.
+            let (lhs_label, rhs_label) = match &display_path {
+                Some(_) => ("ours".to_owned(), "theirs".to_owned()),
+                None => (
+                    lhs_path.display().to_string(),
+                    rhs_path.display().to_string(),
+                ),
+            };
+            let merge_result = merge::merge(
+                &base_src,
+                &lhs_src,
+                &rhs_src,
+                &lhs_label,
+                &rhs_label,
+                language,
+                &diff_options,
+            );
+
+            match output_path {
+                Some(output_path) => {
+                    if let Err(e) = std::fs::write(&output_path, &merge_result.text) {
+                        eprintln!("Could not write {}: {}", output_path.display(), e);
+                        std::process::exit(EXIT_ERROR);
+                    }
+                }
+                None => print!("{}", merge_result.text),
+            }
+
+            // Like git merge-file, exit with a non-zero status if
+            // there are conflicts, as merge drivers must.
+            if merge_result.num_conflicts > 0 {
+                eprintln!(
+                    "{} conflict{} found.",
+                    merge_result.num_conflicts,
+                    if merge_result.num_conflicts == 1 {
+                        ""
+                    } else {
+                        "s"
+                    }
+                );
+                std::process::exit(1);
+            }
+        }
+        Mode::DiffFromGit {
+            diff_options,
+            display_options,
+            language_override,
+            set_exit_code,
+            check_only,
+            lhs_rev,
+            rhs_rev,
+            paths,
+        } => {
+            let changed_files = match read_git_files(&lhs_rev, &rhs_rev, &paths) {
+                Ok(changed_files) => changed_files,
+                Err(e) => {
+                    eprintln!("error: {}", e);
+                    std::process::exit(EXIT_ERROR);
+                }
+            };
+
+            let diff_results =
+                changed_files
+                    .into_par_iter()
+                    .map(|(changed_file, lhs_bytes, rhs_bytes)| {
+                        diff_git_file(
+                            &changed_file,
+                            &lhs_bytes,
+                            &rhs_bytes,
+                            &diff_options,
+                            language_override,
+                        )
+                    });
+            let change_status = print_diff_results(&display_options, check_only, diff_results);
+
+            if set_exit_code {
+                std::process::exit(change_status.exit_code());
+            }
+        }
+    };
 }
 
 /// Print a diff between two files.
@@ -227,23 +354,91 @@ fn diff_file(
     rhs_display_path: &str,
     lhs_path: &FileArgument,
     rhs_path: &FileArgument,
-    display_options: &DisplayOptions,
     missing_as_empty: bool,
-    graph_limit: usize,
-    byte_limit: usize,
+    diff_options: &DiffOptions,
     language_override: Option<parse::guess_language::Language>,
 ) -> DiffResult {
     let (lhs_bytes, rhs_bytes) = read_files_or_die(lhs_path, rhs_path, missing_as_empty);
+
+    // Files that don't exist are diffed as empty, but we still need
+    // to distinguish them from files that exist and are empty.
+    let dev_null_if_missing = |path: &FileArgument| match path {
+        FileArgument::NamedPath(path) if !path.exists() => FileArgument::DevNull,
+        FileArgument::NamedPath(path) => FileArgument::NamedPath(path.clone()),
+        FileArgument::Stdin => FileArgument::Stdin,
+        FileArgument::DevNull => FileArgument::DevNull,
+    };
+
     diff_file_content(
         lhs_display_path,
         rhs_display_path,
-        lhs_path,
-        rhs_path,
+        &dev_null_if_missing(lhs_path),
+        &dev_null_if_missing(rhs_path),
         &lhs_bytes,
         &rhs_bytes,
-        display_options.tab_width,
-        graph_limit,
-        byte_limit,
+        diff_options,
+        language_override,
+    )
+}
+
+/// Find the files that differ between two git revisions, and read
+/// both versions of each file from the repository.
+fn read_git_files(
+    lhs_rev: &str,
+    rhs_rev: &str,
+    paths: &[OsString],
+) -> Result<Vec<(git::ChangedFile, Vec<u8>, Vec<u8>)>, String> {
+    let changed_files = git::changed_files(lhs_rev, rhs_rev, paths)?;
+
+    let mut blob_reader = git::BlobReader::new()?;
+    let mut read_blob = |blob: &Option<git::Blob>| match blob {
+        Some(blob) => blob_reader.read(blob),
+        None => Ok(vec![]),
+    };
+
+    let mut files = vec![];
+    for changed_file in changed_files {
+        let lhs_bytes = read_blob(&changed_file.lhs)?;
+        let rhs_bytes = read_blob(&changed_file.rhs)?;
+        files.push((changed_file, lhs_bytes, rhs_bytes));
+    }
+    Ok(files)
+}
+
+/// Diff a file that changed between two git revisions.
+fn diff_git_file(
+    changed_file: &git::ChangedFile,
+    lhs_bytes: &[u8],
+    rhs_bytes: &[u8],
+    diff_options: &DiffOptions,
+    language_override: Option<parse::guess_language::Language>,
+) -> DiffResult {
+    let file_arg = |blob: &Option<git::Blob>| match blob {
+        Some(blob) => FileArgument::NamedPath(blob.path.clone()),
+        None => FileArgument::DevNull,
+    };
+    let lhs_path = file_arg(&changed_file.lhs);
+    let rhs_path = file_arg(&changed_file.rhs);
+
+    // Like git, show the same path on both sides when a file is
+    // added or removed.
+    let display_path = |blob: &git::Blob| blob.path.display().to_string();
+    let lhs_display_path = changed_file.lhs.as_ref().map(display_path);
+    let rhs_display_path = changed_file.rhs.as_ref().map(display_path);
+    let (lhs_display_path, rhs_display_path) = match (lhs_display_path, rhs_display_path) {
+        (Some(lhs), Some(rhs)) => (lhs, rhs),
+        (Some(path), None) | (None, Some(path)) => (path.clone(), path),
+        (None, None) => unreachable!("git should always report at least one path"),
+    };
+
+    diff_file_content(
+        &lhs_display_path,
+        &rhs_display_path,
+        &lhs_path,
+        &rhs_path,
+        lhs_bytes,
+        rhs_bytes,
+        diff_options,
         language_override,
     )
 }
@@ -251,15 +446,16 @@ fn diff_file(
 fn diff_file_content(
     lhs_display_path: &str,
     rhs_display_path: &str,
-    _lhs_path: &FileArgument,
+    lhs_path: &FileArgument,
     rhs_path: &FileArgument,
     lhs_bytes: &[u8],
     rhs_bytes: &[u8],
-    tab_width: usize,
-    graph_limit: usize,
-    byte_limit: usize,
+    diff_options: &DiffOptions,
     language_override: Option<parse::guess_language::Language>,
 ) -> DiffResult {
+    let lhs_missing = *lhs_path == FileArgument::DevNull;
+    let rhs_missing = *rhs_path == FileArgument::DevNull;
+
     let (mut lhs_src, mut rhs_src) = match (guess_content(lhs_bytes), guess_content(rhs_bytes)) {
         (ProbableFileKind::Binary, _) | (_, ProbableFileKind::Binary) => {
             return DiffResult {
@@ -269,24 +465,29 @@ fn diff_file_content(
                 detected_language: None,
                 lhs_src: FileContent::Binary(lhs_bytes.to_vec()),
                 rhs_src: FileContent::Binary(rhs_bytes.to_vec()),
+                lhs_ends_with_newline: false,
+                rhs_ends_with_newline: false,
+                lhs_missing,
+                rhs_missing,
                 lhs_positions: vec![],
                 rhs_positions: vec![],
+                lhs_definitions: FxHashMap::default(),
+                rhs_definitions: FxHashMap::default(),
+                definition_changes: vec![],
             };
         }
         (ProbableFileKind::Text(lhs_src), ProbableFileKind::Text(rhs_src)) => (lhs_src, rhs_src),
     };
 
-    // TODO: don't replace tab characters inside string literals.
-    lhs_src = replace_tabs(&lhs_src, tab_width);
-    rhs_src = replace_tabs(&rhs_src, tab_width);
-
     // Ignore the trailing newline, if present.
     // TODO: highlight if this has changes (#144).
     // TODO: factor out a string cleaning function.
-    if lhs_src.ends_with('\n') {
+    let lhs_ends_with_newline = lhs_src.ends_with('\n');
+    if lhs_ends_with_newline {
         lhs_src.pop();
     }
-    if rhs_src.ends_with('\n') {
+    let rhs_ends_with_newline = rhs_src.ends_with('\n');
+    if rhs_ends_with_newline {
         rhs_src.pop();
     }
 
@@ -296,7 +497,9 @@ fn diff_file_content(
         FileArgument::DevNull => (&lhs_src, Path::new(&lhs_display_path)),
     };
 
-    let language = language_override.or_else(|| guess(guess_path, guess_src));
+    let language = language_override
+        .or_else(|| from_overrides(guess_path, &diff_options.language_overrides))
+        .or_else(|| guess(guess_path, guess_src));
     let lang_config = language.map(tsp::from_language);
 
     if lhs_bytes == rhs_bytes {
@@ -309,13 +512,26 @@ fn diff_file_content(
             detected_language: language,
             lhs_src: FileContent::Text("".into()),
             rhs_src: FileContent::Text("".into()),
+            lhs_ends_with_newline,
+            rhs_ends_with_newline,
+            lhs_missing,
+            rhs_missing,
             lhs_positions: vec![],
             rhs_positions: vec![],
+            lhs_definitions: FxHashMap::default(),
+            rhs_definitions: FxHashMap::default(),
+            definition_changes: vec![],
         };
     }
 
+    let mut lhs_definitions = FxHashMap::default();
+    let mut rhs_definitions = FxHashMap::default();
+    let mut definition_changes = vec![];
+
     let (lang_name, lhs_positions, rhs_positions) = match lang_config {
-        _ if lhs_bytes.len() > byte_limit || rhs_bytes.len() > byte_limit => {
+        _ if lhs_bytes.len() > diff_options.byte_limit
+            || rhs_bytes.len() > diff_options.byte_limit =>
+        {
             let lhs_positions = line_parser::change_positions(&lhs_src, &rhs_src);
             let rhs_positions = line_parser::change_positions(&rhs_src, &lhs_src);
             (
@@ -325,62 +541,110 @@ fn diff_file_content(
             )
         }
         Some(ts_lang) => {
+            let deadline = diff_options.time_limit.map(|limit| Instant::now() + limit);
             let arena = Arena::new();
-            let lhs = tsp::parse(&arena, &lhs_src, &ts_lang);
-            let rhs = tsp::parse(&arena, &rhs_src, &ts_lang);
+            let parsed = tsp::parse_with_deadline(&arena, &lhs_src, &ts_lang, deadline).zip(
+                tsp::parse_with_deadline(&arena, &rhs_src, &ts_lang, deadline),
+            );
+
+            match parsed {
+                None => text_positions_exceeding(&lhs_src, &rhs_src, ExceededLimit::Time),
+                Some((lhs, rhs)) => {
+                    let (lhs, rhs) = if diff_options.ignore_comments {
+                        (
+                            syntax::without_comments(&arena, &lhs),
+                            syntax::without_comments(&arena, &rhs),
+                        )
+                    } else {
+                        (lhs, rhs)
+                    };
 
-            init_all_info(&lhs, &rhs);
+                    // The syntax nodes count towards the memory
+                    // limit, and the graph search can use the rest.
+                    let syntax_bytes = arena.len() * mem::size_of::<syntax::Syntax>();
+                    let memory_limit = diff_options
+                        .memory_limit
+                        .map(|limit| limit.saturating_sub(syntax_bytes));
 
-            let mut change_map = ChangeMap::default();
-            let possibly_changed = if env::var("DFT_DBG_KEEP_UNCHANGED").is_ok() {
-                vec![(lhs.clone(), rhs.clone())]
-            } else {
-                unchanged::mark_unchanged(&lhs, &rhs, &mut change_map)
-            };
+                    if memory_limit == Some(0) {
+                        text_positions_exceeding(&lhs_src, &rhs_src, ExceededLimit::Memory)
+                    } else {
+                        let limits = SearchLimits {
+                            graph_limit: diff_options.graph_limit,
+                            deadline,
+                            memory_limit,
+                        };
+                        init_all_info(&lhs, &rhs);
+
+                        let mut change_map = ChangeMap::default();
+                        let possibly_changed = if env::var("DFT_DBG_KEEP_UNCHANGED").is_ok() {
+                            vec![(lhs.clone(), rhs.clone())]
+                        } else {
+                            unchanged::mark_unchanged(&lhs, &rhs, &mut change_map)
+                        };
+
+                        let mut exceeded_graph_limit = false;
+                        let mut exceeded_limit = None;
 
-            let mut exceeded_graph_limit = false;
-
-            for (lhs_section_nodes, rhs_section_nodes) in possibly_changed {
-                init_next_prev(&lhs_section_nodes);
-                init_next_prev(&rhs_section_nodes);
-
-                match mark_syntax(
-                    lhs_section_nodes.get(0).copied(),
-                    rhs_section_nodes.get(0).copied(),
-                    &mut change_map,
-                    graph_limit,
-                ) {
-                    Ok(()) => {}
-                    Err(ExceededGraphLimit {}) => {
-                        exceeded_graph_limit = true;
-                        break;
+                        for (lhs_section_nodes, rhs_section_nodes) in possibly_changed {
+                            init_next_prev(&lhs_section_nodes);
+                            init_next_prev(&rhs_section_nodes);
+
+                            match mark_syntax(
+                                lhs_section_nodes.get(0).copied(),
+                                rhs_section_nodes.get(0).copied(),
+                                &mut change_map,
+                                &limits,
+                            ) {
+                                Ok(()) => {}
+                                Err(ExceededLimit::Graph) => {
+                                    // Only this section is too big for the graph
+                                    // search, so use a cheaper tree diff here and
+                                    // keep the precise results for other sections.
+                                    exceeded_graph_limit = true;
+                                    fallback::mark_syntax(
+                                        &lhs_section_nodes,
+                                        &rhs_section_nodes,
+                                        &mut change_map,
+                                    );
+                                }
+                                Err(limit) => {
+                                    exceeded_limit = Some(limit);
+                                    break;
+                                }
+                            }
+                        }
+
+                        if let Some(limit) = exceeded_limit {
+                            text_positions_exceeding(&lhs_src, &rhs_src, limit)
+                        } else {
+                            // TODO: Make this .expect() unnecessary.
+                            let language = language
+                                .expect("If we had a ts_lang, we must have guessed the language");
+                            unchanged::mark_reordered(&lhs, &rhs, &mut change_map);
+                            fix_all_sliders(language, &lhs, &mut change_map);
+                            fix_all_sliders(language, &rhs, &mut change_map);
+                            moves::mark_moved(&lhs, &rhs, &mut change_map);
+
+                            let lhs_positions = syntax::change_positions(&lhs, &change_map);
+                            let rhs_positions = syntax::change_positions(&rhs, &change_map);
+                            lhs_definitions =
+                                syntax::changed_line_definitions(&lhs, &change_map, language);
+                            rhs_definitions =
+                                syntax::changed_line_definitions(&rhs, &change_map, language);
+                            definition_changes =
+                                syntax::definition_changes(&lhs, &rhs, &change_map, language);
+
+                            let lang_name = if exceeded_graph_limit {
+                                format!("{} (exceeded DFT_GRAPH_LIMIT)", language_name(language))
+                            } else {
+                                language_name(language).into()
+                            };
+                            (Some(lang_name), lhs_positions, rhs_positions)
+                        }
                     }
                 }
             }
-
-            if exceeded_graph_limit {
-                let lhs_positions = line_parser::change_positions(&lhs_src, &rhs_src);
-                let rhs_positions = line_parser::change_positions(&rhs_src, &lhs_src);
-                (
-                    Some("Text (exceeded DFT_GRAPH_LIMIT)".into()),
-                    lhs_positions,
-                    rhs_positions,
-                )
-            } else {
-                // TODO: Make this .expect() unnecessary.
-                let language =
-                    language.expect("If we had a ts_lang, we must have guessed the language");
-                fix_all_sliders(language, &lhs, &mut change_map);
-                fix_all_sliders(language, &rhs, &mut change_map);
-
-                let lhs_positions = syntax::change_positions(&lhs, &change_map);
-                let rhs_positions = syntax::change_positions(&rhs, &change_map);
-                (
-                    Some(language_name(language).into()),
-                    lhs_positions,
-                    rhs_positions,
-                )
-            }
         }
         None => {
             let lhs_positions = line_parser::change_positions(&lhs_src, &rhs_src);
@@ -396,11 +660,39 @@ fn diff_file_content(
         detected_language: language,
         lhs_src: FileContent::Text(lhs_src),
         rhs_src: FileContent::Text(rhs_src),
+        lhs_ends_with_newline,
+        rhs_ends_with_newline,
+        lhs_missing,
+        rhs_missing,
         lhs_positions,
         rhs_positions,
+        lhs_definitions,
+        rhs_definitions,
+        definition_changes,
     }
 }
 
+/// Diff `lhs_src` and `rhs_src` as text, because diffing them
+/// structurally exceeded `limit`.
+fn text_positions_exceeding(
+    lhs_src: &str,
+    rhs_src: &str,
+    limit: ExceededLimit,
+) -> (
+    Option<String>,
+    Vec<syntax::MatchedPos>,
+    Vec<syntax::MatchedPos>,
+) {
+    let lhs_positions = line_parser::change_positions(lhs_src, rhs_src);
+    let rhs_positions = line_parser::change_positions(rhs_src, lhs_src);
+    let lang_name = match limit {
+        ExceededLimit::Graph => "Text (exceeded DFT_GRAPH_LIMIT)",
+        ExceededLimit::Time => "Text (exceeded time limit)",
+        ExceededLimit::Memory => "Text (exceeded memory limit)",
+    };
+    (Some(lang_name.into()), lhs_positions, rhs_positions)
+}
+
 /// Given two directories that contain the files, compare them
 /// pairwise. Returns an iterator, so we can print results
 /// incrementally.
@@ -410,39 +702,163 @@ fn diff_file_content(
 fn diff_directories<'a>(
     lhs_dir: &'a Path,
     rhs_dir: &'a Path,
-    display_options: &DisplayOptions,
-    graph_limit: usize,
-    byte_limit: usize,
+    diff_options: &DiffOptions,
     language_override: Option<parse::guess_language::Language>,
 ) -> impl ParallelIterator<Item = DiffResult> + 'a {
-    let display_options = display_options.clone();
+    let diff_options = diff_options.clone();
 
     // We greedily list all files in the directory, and then diff them
     // in parallel. This is assuming that diffing is slower than
     // enumerating files, so it benefits more from parallelism.
-    let paths = relative_paths_in_either(lhs_dir, rhs_dir);
-
-    paths.into_par_iter().map(move |rel_path| {
-        info!("Relative path is {:?} inside {:?}", rel_path, lhs_dir);
-
-        let lhs_path = Path::new(lhs_dir).join(&rel_path);
-        let rhs_path = Path::new(rhs_dir).join(&rel_path);
-
-        diff_file(
-            &rel_path.to_string_lossy(),
-            &rel_path.to_string_lossy(),
-            &FileArgument::NamedPath(lhs_path),
-            &FileArgument::NamedPath(rhs_path),
-            &display_options,
-            true,
-            graph_limit,
-            byte_limit,
-            language_override,
-        )
-    })
+    let path_pairs = relative_path_pairs(lhs_dir, rhs_dir, diff_options.byte_limit);
+
+    path_pairs
+        .into_par_iter()
+        .map(move |(lhs_rel_path, rhs_rel_path, contents)| {
+            info!(
+                "Relative paths are {:?} inside {:?} and {:?} inside {:?}",
+                lhs_rel_path, lhs_dir, rhs_rel_path, rhs_dir
+            );
+
+            let lhs_path = FileArgument::NamedPath(Path::new(lhs_dir).join(&lhs_rel_path));
+            let rhs_path = FileArgument::NamedPath(Path::new(rhs_dir).join(&rhs_rel_path));
+
+            match contents {
+                // Renamed files were already read when finding renames.
+                Some((lhs_bytes, rhs_bytes)) => diff_file_content(
+                    &lhs_rel_path.to_string_lossy(),
+                    &rhs_rel_path.to_string_lossy(),
+                    &lhs_path,
+                    &rhs_path,
+                    &lhs_bytes,
+                    &rhs_bytes,
+                    &diff_options,
+                    language_override,
+                ),
+                None => diff_file(
+                    &lhs_rel_path.to_string_lossy(),
+                    &rhs_rel_path.to_string_lossy(),
+                    &lhs_path,
+                    &rhs_path,
+                    true,
+                    &diff_options,
+                    language_override,
+                ),
+            }
+        })
+}
+
+/// Should `diff_result` be displayed at all? Files with only
+/// formatting changes are omitted with `--skip-formatting-only`.
+fn is_displayed(display_options: &DisplayOptions, diff_result: &DiffResult) -> bool {
+    display_options.print_formatting_only
+        || diff_result.change_status() != ChangeStatus::FormattingOnly
+}
+
+/// Print all of `diff_results`, and return the most significant
+/// change status among them.
+fn print_diff_results(
+    display_options: &DisplayOptions,
+    check_only: bool,
+    diff_results: impl ParallelIterator<Item = DiffResult>,
+) -> ChangeStatus {
+    if check_only {
+        diff_results
+            .map(|diff_result| diff_result.change_status())
+            .max()
+            .unwrap_or(ChangeStatus::Unchanged)
+    } else if display_options.display_mode.is_single_document() {
+        // We need all the results before we can print the document.
+        let (displayed, hidden): (Vec<_>, Vec<_>) =
+            diff_results.partition(|diff_result| is_displayed(display_options, diff_result));
+        print_diff_document(display_options, &displayed);
+
+        displayed
+            .iter()
+            .chain(hidden.iter())
+            .map(|diff_result| diff_result.change_status())
+            .max()
+            .unwrap_or(ChangeStatus::Unchanged)
+    } else if display_options.interactive {
+        // The pager needs all the results so users can navigate
+        // between files.
+        let (displayed, hidden): (Vec<_>, Vec<_>) =
+            diff_results.partition(|diff_result| is_displayed(display_options, diff_result));
+        let pager_result = display::pager::run(display_options, displayed.len(), |options, i| {
+            let mut out = vec![];
+            let hunk_starts = write_diff_result(&mut out, options, &displayed[i])
+                .expect("Writing to a Vec should never fail");
+            display::pager::RenderedFile {
+                text: String::from_utf8_lossy(&out).into_owned(),
+                hunk_starts,
+            }
+        });
+        if let Err(e) = pager_result {
+            eprintln!("error: Could not run the interactive pager: {}", e);
+            std::process::exit(EXIT_ERROR);
+        }
+
+        displayed
+            .iter()
+            .chain(hidden.iter())
+            .map(|diff_result| diff_result.change_status())
+            .max()
+            .unwrap_or(ChangeStatus::Unchanged)
+    } else {
+        diff_results
+            .map(|diff_result| {
+                if is_displayed(display_options, &diff_result) {
+                    print_diff_result(display_options, &diff_result);
+                }
+                diff_result.change_status()
+            })
+            .max()
+            .unwrap_or(ChangeStatus::Unchanged)
+    }
+}
+
+/// Print all the results as a single document, for display modes
+/// that don't print files individually.
+fn print_diff_document(display_options: &DisplayOptions, summaries: &[DiffResult]) {
+    match display_options.display_mode {
+        DisplayMode::Json => display::json::print(summaries, display_options),
+        DisplayMode::Html => display::html::print(summaries, display_options),
+        DisplayMode::Stat => display::stat::print(summaries, display_options),
+        DisplayMode::ChangeList => display::change_list::print(summaries, display_options),
+        DisplayMode::Inline
+        | DisplayMode::SideBySide
+        | DisplayMode::SideBySideShowBoth
+        | DisplayMode::Patch
+        | DisplayMode::Summary => {
+            unreachable!("Only called for single document display modes")
+        }
+    }
 }
 
 fn print_diff_result(display_options: &DisplayOptions, summary: &DiffResult) {
+    // Lock stdout for the whole file, so output from files diffed in
+    // parallel isn't interleaved.
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    write_diff_result(&mut out, display_options, summary).expect("failed printing to stdout");
+}
+
+/// Write the terminal output for `summary`. Returns the line offset
+/// of each hunk header in the output.
+fn write_diff_result(
+    out: &mut impl Write,
+    display_options: &DisplayOptions,
+    summary: &DiffResult,
+) -> io::Result<Vec<usize>> {
+    if let DisplayMode::Patch = display_options.display_mode {
+        display::patch::print(out, summary, display_options)?;
+        return Ok(vec![]);
+    }
+    if let DisplayMode::Summary = display_options.display_mode {
+        display::summary::print(out, summary, display_options)?;
+        return Ok(vec![]);
+    }
+
     match (&summary.lhs_src, &summary.rhs_src) {
         (FileContent::Text(lhs_src), FileContent::Text(rhs_src)) => {
             let opposite_to_lhs = opposite_positions(&summary.lhs_positions);
@@ -461,7 +877,8 @@ fn print_diff_result(display_options: &DisplayOptions, summary: &DiffResult) {
             let lang_name = summary.language.clone().unwrap_or_else(|| "Text".into());
             if hunks.is_empty() {
                 if display_options.print_unchanged {
-                    println!(
+                    writeln!(
+                        out,
                         "{}",
                         display::style::header(
                             &summary.lhs_display_path,
@@ -469,55 +886,80 @@ fn print_diff_result(display_options: &DisplayOptions, summary: &DiffResult) {
                             1,
                             1,
                             &lang_name,
+                            None,
                             display_options
                         )
-                    );
+                    )?;
                     if lang_name == "Text" || summary.lhs_src == summary.rhs_src {
                         // TODO: there are other Text names now, so
                         // they will hit the second case incorrectly.
-                        println!("No changes.\n");
+                        writeln!(out, "No changes.\n")?;
                     } else {
-                        println!("No syntactic changes.\n");
+                        writeln!(out, "No syntactic changes.\n")?;
                     }
                 }
-                return;
+                return Ok(vec![]);
             }
 
-            match display_options.display_mode {
-                DisplayMode::Inline => {
-                    display::inline::print(
-                        lhs_src,
-                        rhs_src,
-                        display_options,
-                        &summary.lhs_positions,
-                        &summary.rhs_positions,
-                        &hunks,
-                        &summary.lhs_display_path,
-                        &summary.rhs_display_path,
-                        &lang_name,
-                        summary.detected_language,
-                    );
-                }
+            let hunk_definitions: Vec<Option<&str>> = hunks
+                .iter()
+                .map(|hunk| summary.hunk_definition(hunk))
+                .collect();
+
+            let (lhs_src, rhs_src, lhs_positions, rhs_positions) = display::tabs::expand_tabs(
+                lhs_src,
+                rhs_src,
+                &summary.lhs_positions,
+                &summary.rhs_positions,
+                display_options.tab_width,
+            );
+
+            return match display_options.display_mode {
+                DisplayMode::Inline => display::inline::print(
+                    out,
+                    &lhs_src,
+                    &rhs_src,
+                    display_options,
+                    &lhs_positions,
+                    &rhs_positions,
+                    &hunks,
+                    &hunk_definitions,
+                    &summary.lhs_display_path,
+                    &summary.rhs_display_path,
+                    &lang_name,
+                    summary.detected_language,
+                ),
                 DisplayMode::SideBySide | DisplayMode::SideBySideShowBoth => {
                     display::side_by_side::print(
+                        out,
                         &hunks,
+                        &hunk_definitions,
                         display_options,
                         &summary.lhs_display_path,
                         &summary.rhs_display_path,
                         &lang_name,
                         summary.detected_language,
-                        lhs_src,
-                        rhs_src,
-                        &summary.lhs_positions,
-                        &summary.rhs_positions,
-                    );
+                        &lhs_src,
+                        &rhs_src,
+                        &lhs_positions,
+                        &rhs_positions,
+                    )
                 }
-            }
+                DisplayMode::Json
+                | DisplayMode::Html
+                | DisplayMode::Patch
+                | DisplayMode::Summary
+                | DisplayMode::Stat
+                | DisplayMode::ChangeList => {
+                    unreachable!("Handled before computing terminal output")
+                }
+            };
         }
         (FileContent::Binary(lhs_bytes), FileContent::Binary(rhs_bytes)) => {
             let changed = lhs_bytes != rhs_bytes;
             if display_options.print_unchanged || changed {
-                println!(
+                writeln!(
+                    out,
                     "{}",
                     display::style::header(
                         &summary.lhs_display_path,
@@ -525,19 +967,21 @@ fn print_diff_result(display_options: &DisplayOptions, summary: &DiffResult) {
                         1,
                         1,
                         "binary",
+                        None,
                         display_options
                     )
-                );
+                )?;
                 if changed {
-                    println!("Binary contents changed.");
+                    writeln!(out, "Binary contents changed.")?;
                 } else {
-                    println!("No changes.");
+                    writeln!(out, "No changes.")?;
                 }
             }
         }
         (_, FileContent::Binary(_)) | (FileContent::Binary(_), _) => {
             // We're diffing a binary file against a text file.
-            println!(
+            writeln!(
+                out,
                 "{}",
                 display::style::header(
                     &summary.lhs_display_path,
@@ -545,12 +989,14 @@ fn print_diff_result(display_options: &DisplayOptions, summary: &DiffResult) {
                     1,
                     1,
                     "binary",
+                    None,
                     display_options
                 )
-            );
-            println!("Binary contents changed.");
+            )?;
+            writeln!(out, "Binary contents changed.")?;
         }
     }
+    Ok(vec![])
 }
 
 #[cfg(test)]
@@ -558,7 +1004,7 @@ mod tests {
     use std::ffi::OsStr;
 
     use super::*;
-    use crate::options::{DEFAULT_BYTE_LIMIT, DEFAULT_GRAPH_LIMIT, DEFAULT_TAB_WIDTH};
+    use crate::options::{DEFAULT_BYTE_LIMIT, DEFAULT_GRAPH_LIMIT};
 
     #[test]
     fn test_diff_identical_content() {
@@ -570,9 +1016,14 @@ mod tests {
             &FileArgument::from_path_argument(OsStr::new("foo.el")),
             s.as_bytes(),
             s.as_bytes(),
-            DEFAULT_TAB_WIDTH,
-            DEFAULT_GRAPH_LIMIT,
-            DEFAULT_BYTE_LIMIT,
+            &DiffOptions {
+                graph_limit: DEFAULT_GRAPH_LIMIT,
+                byte_limit: DEFAULT_BYTE_LIMIT,
+                time_limit: None,
+                memory_limit: None,
+                ignore_comments: false,
+                language_overrides: vec![],
+            },
             None,
         );
 
@@ -0,0 +1,723 @@
+//! Three-way merging using structural diffs.
+//!
+//! We diff the base against each side, and treat the tokens that are
+//! unchanged as anchors between the two texts. Wherever the text
+//! between two anchors differs from the base, that side has made an
+//! edit. Edits from both sides are applied to the base, and we only
+//! emit conflict markers when edits from the two sides overlap and
+//! disagree. Overlapping deletions are combined, so deleting or
+//! changing neighbouring nodes doesn't conflict.
+
+use std::time::Instant;
+
+use typed_arena::Arena;
+
+use crate::{
+    constants::Side,
+    diff::{
+        changes::{ChangeKind, ChangeMap},
+        dijkstra::{mark_syntax, SearchLimits},
+        myers_diff,
+        sliders::fix_all_sliders,
+        unchanged::mark_unchanged,
+    },
+    options::DiffOptions,
+    parse::{
+        guess_language::Language,
+        syntax::{init_all_info, init_next_prev, Syntax},
+        tree_sitter_parser as tsp,
+    },
+    positions::SingleLineSpan,
+};
+
+/// The length of the `<<<<<<<`, `=======` and `>>>>>>>` lines, matching
+/// git.
+const CONFLICT_MARKER_SIZE: usize = 7;
+
+/// A region of the base that corresponds to a region of one side, with
+/// identical content.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Anchor {
+    base_start: usize,
+    base_end: usize,
+    side_start: usize,
+    side_end: usize,
+}
+
+/// A change made by one side: replace `base_start..base_end` with
+/// `replacement`.
+#[derive(Debug, Clone, PartialEq)]
+struct Edit<'s> {
+    base_start: usize,
+    base_end: usize,
+    replacement: &'s str,
+}
+
+/// A region of the base along with the edits from both sides that
+/// touch it.
+#[derive(Debug, Clone)]
+struct Group<'s> {
+    start: usize,
+    end: usize,
+    edits: Vec<(Side, Edit<'s>)>,
+}
+
+pub struct MergeResult {
+    pub text: String,
+    pub num_conflicts: usize,
+}
+
+/// The byte offset of the start of every line in `src`.
+fn line_starts(src: &str) -> Vec<usize> {
+    let mut starts = vec![0];
+    for (i, c) in src.char_indices() {
+        if c == '\n' {
+            starts.push(i + 1);
+        }
+    }
+    starts
+}
+
+/// Convert `spans` to a byte range, or `None` if there are no spans.
+fn span_offsets(line_starts: &[usize], spans: &[SingleLineSpan]) -> Option<(usize, usize)> {
+    let first = spans.first()?;
+    let last = spans.last()?;
+    Some((
+        line_starts[first.line.as_usize()] + first.start_col as usize,
+        line_starts[last.line.as_usize()] + last.end_col as usize,
+    ))
+}
+
+fn push_anchor(
+    base_line_starts: &[usize],
+    side_line_starts: &[usize],
+    base_spans: &[SingleLineSpan],
+    side_spans: &[SingleLineSpan],
+    anchors: &mut Vec<Anchor>,
+) {
+    if let (Some((base_start, base_end)), Some((side_start, side_end))) = (
+        span_offsets(base_line_starts, base_spans),
+        span_offsets(side_line_starts, side_spans),
+    ) {
+        if base_start < base_end {
+            anchors.push(Anchor {
+                base_start,
+                base_end,
+                side_start,
+                side_end,
+            });
+        }
+    }
+}
+
+/// Walk the base nodes, and add an anchor for every unchanged token.
+fn collect_anchors<'a>(
+    nodes: &[&'a Syntax<'a>],
+    change_map: &ChangeMap<'a>,
+    base_line_starts: &[usize],
+    side_line_starts: &[usize],
+    anchors: &mut Vec<Anchor>,
+) {
+    for node in nodes {
+        let opposite = match change_map.get(node) {
+            Some(ChangeKind::Unchanged(opposite)) => Some(opposite),
+            _ => None,
+        };
+
+        match (node, opposite) {
+            (
+                Syntax::List {
+                    open_position,
+                    children,
+                    close_position,
+                    ..
+                },
+                opposite,
+            ) => {
+                if let Some(Syntax::List {
+                    open_position: opposite_open_position,
+                    ..
+                }) = opposite
+                {
+                    push_anchor(
+                        base_line_starts,
+                        side_line_starts,
+                        open_position,
+                        opposite_open_position,
+                        anchors,
+                    );
+                }
+
+                collect_anchors(
+                    children,
+                    change_map,
+                    base_line_starts,
+                    side_line_starts,
+                    anchors,
+                );
+
+                if let Some(Syntax::List {
+                    close_position: opposite_close_position,
+                    ..
+                }) = opposite
+                {
+                    push_anchor(
+                        base_line_starts,
+                        side_line_starts,
+                        close_position,
+                        opposite_close_position,
+                        anchors,
+                    );
+                }
+            }
+            (
+                Syntax::Atom { position, .. },
+                Some(Syntax::Atom {
+                    position: opposite_position,
+                    ..
+                }),
+            ) => {
+                push_anchor(
+                    base_line_starts,
+                    side_line_starts,
+                    position,
+                    opposite_position,
+                    anchors,
+                );
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Discard anchors that would cross each other, or whose text isn't
+/// identical (e.g. multiline comments that only differ in
+/// indentation).
+fn clean_anchors(base_src: &str, side_src: &str, anchors: Vec<Anchor>) -> Vec<Anchor> {
+    let mut base_pos = 0;
+    let mut side_pos = 0;
+
+    let mut res = vec![];
+    for anchor in anchors {
+        if anchor.base_start < base_pos || anchor.side_start < side_pos {
+            continue;
+        }
+        if base_src[anchor.base_start..anchor.base_end]
+            != side_src[anchor.side_start..anchor.side_end]
+        {
+            continue;
+        }
+
+        base_pos = anchor.base_end;
+        side_pos = anchor.side_end;
+        res.push(anchor);
+    }
+
+    res
+}
+
+/// Find anchors by diffing the syntax trees of `base_src` and
+/// `side_src`. Returns `None` if we exceeded the graph, time or
+/// memory limit.
+fn tree_anchors(
+    base_src: &str,
+    side_src: &str,
+    language: Language,
+    diff_options: &DiffOptions,
+) -> Option<Vec<Anchor>> {
+    let deadline = diff_options.time_limit.map(|limit| Instant::now() + limit);
+    let ts_lang = tsp::from_language(language);
+    let arena = Arena::new();
+    let base = tsp::parse_with_deadline(&arena, base_src, &ts_lang, deadline)?;
+    let side = tsp::parse_with_deadline(&arena, side_src, &ts_lang, deadline)?;
+
+    let limits = SearchLimits {
+        graph_limit: diff_options.graph_limit,
+        deadline,
+        memory_limit: diff_options.memory_limit,
+    };
+
+    init_all_info(&base, &side);
+
+    let mut change_map = ChangeMap::default();
+    for (base_section_nodes, side_section_nodes) in mark_unchanged(&base, &side, &mut change_map) {
+        init_next_prev(&base_section_nodes);
+        init_next_prev(&side_section_nodes);
+
+        if mark_syntax(
+            base_section_nodes.first().copied(),
+            side_section_nodes.first().copied(),
+            &mut change_map,
+            &limits,
+        )
+        .is_err()
+        {
+            return None;
+        }
+    }
+
+    fix_all_sliders(language, &base, &mut change_map);
+    fix_all_sliders(language, &side, &mut change_map);
+
+    let mut anchors = vec![];
+    collect_anchors(
+        &base,
+        &change_map,
+        &line_starts(base_src),
+        &line_starts(side_src),
+        &mut anchors,
+    );
+    Some(clean_anchors(base_src, side_src, anchors))
+}
+
+/// Find anchors by diffing the lines of `base_src` and `side_src`.
+fn line_anchors(base_src: &str, side_src: &str) -> Vec<Anchor> {
+    let base_lines: Vec<_> = base_src.split_inclusive('\n').collect();
+    let side_lines: Vec<_> = side_src.split_inclusive('\n').collect();
+
+    let mut base_pos = 0;
+    let mut side_pos = 0;
+
+    let mut anchors = vec![];
+    for diff_res in myers_diff::slice(&base_lines, &side_lines) {
+        match diff_res {
+            myers_diff::DiffResult::Left(line) => {
+                base_pos += line.len();
+            }
+            myers_diff::DiffResult::Both(line, _) => {
+                anchors.push(Anchor {
+                    base_start: base_pos,
+                    base_end: base_pos + line.len(),
+                    side_start: side_pos,
+                    side_end: side_pos + line.len(),
+                });
+                base_pos += line.len();
+                side_pos += line.len();
+            }
+            myers_diff::DiffResult::Right(line) => {
+                side_pos += line.len();
+            }
+        }
+    }
+
+    anchors
+}
+
+/// Find the edits that `side_src` made to `base_src`, given the
+/// anchors between them.
+fn find_edits<'s>(base_src: &str, side_src: &'s str, anchors: &[Anchor]) -> Vec<Edit<'s>> {
+    let end_anchor = Anchor {
+        base_start: base_src.len(),
+        base_end: base_src.len(),
+        side_start: side_src.len(),
+        side_end: side_src.len(),
+    };
+
+    let mut base_pos = 0;
+    let mut side_pos = 0;
+
+    let mut edits = vec![];
+    for anchor in anchors.iter().chain(std::iter::once(&end_anchor)) {
+        let base_gap = &base_src[base_pos..anchor.base_start];
+        let side_gap = &side_src[side_pos..anchor.side_start];
+
+        if base_gap != side_gap {
+            // Ignore any common text at the start and end of the
+            // gap, so whitespace doesn't make edits overlap
+            // unnecessarily.
+            let prefix_len: usize = base_gap
+                .chars()
+                .zip(side_gap.chars())
+                .take_while(|(b, s)| b == s)
+                .map(|(b, _)| b.len_utf8())
+                .sum();
+            let base_gap = &base_gap[prefix_len..];
+            let side_gap = &side_gap[prefix_len..];
+
+            let suffix_len: usize = base_gap
+                .chars()
+                .rev()
+                .zip(side_gap.chars().rev())
+                .take_while(|(b, s)| b == s)
+                .map(|(b, _)| b.len_utf8())
+                .sum();
+
+            edits.push(Edit {
+                base_start: base_pos + prefix_len,
+                base_end: anchor.base_start - suffix_len,
+                replacement: &side_gap[..side_gap.len() - suffix_len],
+            });
+        }
+
+        base_pos = anchor.base_end;
+        side_pos = anchor.side_end;
+    }
+
+    edits
+}
+
+/// Apply the edits from `side` in `group` to the base text in the
+/// group's region.
+fn apply_edits(base_src: &str, group: &Group, side: Side) -> String {
+    let mut res = String::new();
+    let mut pos = group.start;
+    for (edit_side, edit) in &group.edits {
+        if *edit_side == side {
+            res.push_str(&base_src[pos..edit.base_start]);
+            res.push_str(edit.replacement);
+            pos = edit.base_end;
+        }
+    }
+    res.push_str(&base_src[pos..group.end]);
+    res
+}
+
+/// Apply the edits from both sides in `group` to the base text in the
+/// group's region, or return `None` if they conflict.
+///
+/// Edits from the two sides conflict if they overlap, unless they
+/// both only delete text. This allows changes to adjacent nodes, such
+/// as deleting two neighbouring list elements, even when the edits
+/// include the whitespace between them.
+fn combine_edits(base_src: &str, group: &Group) -> Option<String> {
+    let has_side = |side| group.edits.iter().any(|(edit_side, _)| *edit_side == side);
+    let left_text = apply_edits(base_src, group, Side::Left);
+    if !has_side(Side::Right) {
+        return Some(left_text);
+    }
+    let right_text = apply_edits(base_src, group, Side::Right);
+    if !has_side(Side::Left) || left_text == right_text {
+        return Some(right_text);
+    }
+
+    let mut res = String::new();
+    let mut pos = group.start;
+    let mut prev_edit: Option<&Edit> = None;
+    for (_, edit) in &group.edits {
+        if let Some(prev_edit) = prev_edit {
+            let is_deletion = |edit: &Edit| edit.replacement.is_empty();
+            let is_insertion = |edit: &Edit| edit.base_start == edit.base_end;
+
+            if edit.base_start < pos {
+                if !is_deletion(edit) || !is_deletion(prev_edit) {
+                    return None;
+                }
+                pos = pos.max(edit.base_end);
+                continue;
+            }
+            // We can't know which order two insertions at the same
+            // position should go in.
+            if is_insertion(edit) && is_insertion(prev_edit) && edit.base_start == pos {
+                return None;
+            }
+        }
+
+        res.push_str(&base_src[pos..edit.base_start]);
+        res.push_str(edit.replacement);
+        pos = edit.base_end;
+        prev_edit = Some(edit);
+    }
+    res.push_str(&base_src[pos..group.end]);
+    Some(res)
+}
+
+/// Group edits that touch the same region of the base. Edits are
+/// grouped if they overlap, or if one is an insertion at the boundary
+/// of the other, as we can't know which order they should go in.
+fn group_edits<'s>(left_edits: Vec<Edit<'s>>, right_edits: Vec<Edit<'s>>) -> Vec<Group<'s>> {
+    let mut edits: Vec<(Side, Edit)> = left_edits
+        .into_iter()
+        .map(|edit| (Side::Left, edit))
+        .chain(right_edits.into_iter().map(|edit| (Side::Right, edit)))
+        .collect();
+    edits.sort_by_key(|(_, edit)| (edit.base_start, edit.base_end));
+
+    let mut groups: Vec<Group> = vec![];
+    for (side, edit) in edits {
+        if let Some(group) = groups.last_mut() {
+            let is_insertion = edit.base_start == edit.base_end;
+            let touches = edit.base_start < group.end
+                || (edit.base_start == group.end && (is_insertion || group.start == group.end));
+            if touches {
+                group.end = group.end.max(edit.base_end);
+                group.edits.push((side, edit));
+                continue;
+            }
+        }
+
+        groups.push(Group {
+            start: edit.base_start,
+            end: edit.base_end,
+            edits: vec![(side, edit)],
+        });
+    }
+
+    groups
+}
+
+/// Expand `group` so it covers whole lines of `base_src`, so conflict
+/// markers can be placed on their own lines.
+fn expand_to_lines(base_src: &str, group: &mut Group) {
+    group.start = base_src[..group.start]
+        .rfind('\n')
+        .map(|i| i + 1)
+        .unwrap_or(0);
+
+    let ends_at_line_start = group.end > group.start && base_src[..group.end].ends_with('\n');
+    if !ends_at_line_start {
+        group.end = base_src[group.end..]
+            .find('\n')
+            .map(|i| group.end + i + 1)
+            .unwrap_or_else(|| base_src.len());
+    }
+}
+
+/// Combine the groups of edits, expanding conflicting groups to whole
+/// lines. Returns the groups along with whether each group is a
+/// conflict.
+fn resolve_groups<'s>(base_src: &str, groups: Vec<Group<'s>>) -> Vec<(Group<'s>, bool)> {
+    let mut resolved: Vec<(Group, bool)> = vec![];
+    for group in groups {
+        if combine_edits(base_src, &group).is_some() {
+            // Conflicts cover whole lines, so this group might be on
+            // the same line as the previous conflict.
+            if let Some((prev_group, true)) = resolved.last_mut() {
+                if group.start < prev_group.end {
+                    prev_group.end = prev_group.end.max(group.end);
+                    prev_group.edits.extend(group.edits);
+                    expand_to_lines(base_src, prev_group);
+                    continue;
+                }
+            }
+
+            resolved.push((group, false));
+            continue;
+        }
+
+        let mut group = group;
+        expand_to_lines(base_src, &mut group);
+
+        // Absorb any earlier groups that are now on the same lines.
+        while let Some((prev_group, _)) = resolved.last() {
+            if prev_group.end <= group.start {
+                break;
+            }
+            let (mut prev_group, _) = resolved.pop().unwrap();
+            prev_group.edits.extend(group.edits);
+            group = Group {
+                start: prev_group.start,
+                end: group.end,
+                edits: prev_group.edits,
+            };
+            expand_to_lines(base_src, &mut group);
+        }
+
+        resolved.push((group, true));
+    }
+
+    resolved
+}
+
+fn push_conflict_section(res: &mut String, marker: char, label: &str, text: &str) {
+    res.push_str(&marker.to_string().repeat(CONFLICT_MARKER_SIZE));
+    if !label.is_empty() {
+        res.push(' ');
+        res.push_str(label);
+    }
+    res.push('\n');
+
+    res.push_str(text);
+    if !text.is_empty() && !text.ends_with('\n') {
+        res.push('\n');
+    }
+}
+
+/// Merge `left_src` and `right_src`, which have both been derived from
+/// `base_src`. If `language` is given, changes are merged
+/// structurally, otherwise they're merged line by line.
+pub fn merge(
+    base_src: &str,
+    left_src: &str,
+    right_src: &str,
+    left_label: &str,
+    right_label: &str,
+    language: Option<Language>,
+    diff_options: &DiffOptions,
+) -> MergeResult {
+    let anchors = |side_src: &str| -> Vec<Anchor> {
+        let within_byte_limit =
+            base_src.len() <= diff_options.byte_limit && side_src.len() <= diff_options.byte_limit;
+
+        language
+            .filter(|_| within_byte_limit)
+            .and_then(|language| tree_anchors(base_src, side_src, language, diff_options))
+            .unwrap_or_else(|| line_anchors(base_src, side_src))
+    };
+
+    let left_edits = find_edits(base_src, left_src, &anchors(left_src));
+    let right_edits = find_edits(base_src, right_src, &anchors(right_src));
+    let groups = resolve_groups(base_src, group_edits(left_edits, right_edits));
+
+    let mut text = String::with_capacity(base_src.len());
+    let mut num_conflicts = 0;
+    let mut pos = 0;
+    for (group, is_conflict) in groups {
+        text.push_str(&base_src[pos..group.start]);
+
+        if is_conflict {
+            num_conflicts += 1;
+
+            let left_text = apply_edits(base_src, &group, Side::Left);
+            let right_text = apply_edits(base_src, &group, Side::Right);
+            push_conflict_section(&mut text, '<', left_label, &left_text);
+            push_conflict_section(&mut text, '=', "", &right_text);
+            push_conflict_section(&mut text, '>', right_label, "");
+        } else {
+            let combined =
+                combine_edits(base_src, &group).expect("Non-conflicting edits can be combined");
+            text.push_str(&combined);
+        }
+
+        pos = group.end;
+    }
+    text.push_str(&base_src[pos..]);
+
+    MergeResult {
+        text,
+        num_conflicts,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options::{DEFAULT_BYTE_LIMIT, DEFAULT_GRAPH_LIMIT};
+    use pretty_assertions::assert_eq;
+
+    fn diff_options() -> DiffOptions {
+        DiffOptions {
+            graph_limit: DEFAULT_GRAPH_LIMIT,
+            byte_limit: DEFAULT_BYTE_LIMIT,
+            time_limit: None,
+            memory_limit: None,
+            ignore_comments: false,
+            language_overrides: vec![],
+        }
+    }
+
+    #[test]
+    fn test_merge_disjoint_changes_on_same_line() {
+        let res = merge(
+            "x = foo(1, 2)\n",
+            "x = foo(10, 2)\n",
+            "x = foo(1, 20)\n",
+            "left",
+            "right",
+            Some(Language::Python),
+            &diff_options(),
+        );
+
+        assert_eq!(res.text, "x = foo(10, 20)\n");
+        assert_eq!(res.num_conflicts, 0);
+    }
+
+    #[test]
+    fn test_merge_identical_changes() {
+        let res = merge(
+            "x = 1\ny = 2\n",
+            "x = 3\ny = 2\n",
+            "x = 3\ny = 2\n",
+            "left",
+            "right",
+            Some(Language::Python),
+            &diff_options(),
+        );
+
+        assert_eq!(res.text, "x = 3\ny = 2\n");
+        assert_eq!(res.num_conflicts, 0);
+    }
+
+    #[test]
+    fn test_merge_conflict() {
+        let res = merge(
+            "x = 1\ny = 2\n",
+            "x = 3\ny = 2\n",
+            "x = 4\ny = 2\n",
+            "left",
+            "right",
+            Some(Language::Python),
+            &diff_options(),
+        );
+
+        assert_eq!(
+            res.text,
+            "<<<<<<< left\nx = 3\n=======\nx = 4\n>>>>>>> right\ny = 2\n"
+        );
+        assert_eq!(res.num_conflicts, 1);
+    }
+
+    #[test]
+    fn test_merge_adjacent_siblings() {
+        // There's no unchanged token between `a` and `b`, and both
+        // deletions include the space between them.
+        let res = merge(
+            "(f a b)\n",
+            "(f b)\n",
+            "(f a)\n",
+            "left",
+            "right",
+            Some(Language::EmacsLisp),
+            &diff_options(),
+        );
+
+        assert_eq!(res.text, "(f )\n");
+        assert_eq!(res.num_conflicts, 0);
+    }
+
+    #[test]
+    fn test_merge_adjacent_sibling_changed_and_deleted() {
+        let res = merge(
+            "x = [a, b]\n",
+            "x = [a2, b]\n",
+            "x = [a]\n",
+            "left",
+            "right",
+            Some(Language::Python),
+            &diff_options(),
+        );
+
+        assert_eq!(res.text, "x = [a2]\n");
+        assert_eq!(res.num_conflicts, 0);
+    }
+
+    #[test]
+    fn test_merge_insertions_at_same_position() {
+        let res = merge(
+            "x = [a]\n",
+            "x = [a, b]\n",
+            "x = [a, c]\n",
+            "left",
+            "right",
+            Some(Language::Python),
+            &diff_options(),
+        );
+
+        assert_eq!(res.num_conflicts, 1);
+    }
+
+    #[test]
+    fn test_merge_lines_without_language() {
+        let res = merge(
+            "a\nb\nc\n",
+            "A\nb\nc\n",
+            "a\nb\nC\n",
+            "left",
+            "right",
+            None,
+            &diff_options(),
+        );
+
+        assert_eq!(res.text, "A\nb\nC\n");
+        assert_eq!(res.num_conflicts, 0);
+    }
+}
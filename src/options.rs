@@ -1,12 +1,23 @@
 //! CLI option parsing.
 
-use std::{borrow::Borrow, env, ffi::OsStr, path::PathBuf};
+use std::{
+    borrow::Borrow,
+    env,
+    ffi::{OsStr, OsString},
+    path::PathBuf,
+    time::Duration,
+};
 
 use atty::Stream;
 use clap::{crate_authors, crate_description, crate_version, Arg, Command};
 use const_format::formatcp;
 
-use crate::{display::style::BackgroundColor, parse::guess_language};
+use crate::{
+    config,
+    display::{style::BackgroundColor, theme},
+    exit_codes::EXIT_ERROR,
+    parse::guess_language,
+};
 
 pub const DEFAULT_BYTE_LIMIT: usize = 1_000_000;
 // Chosen experimentally: this is sufficiently many for all the sample
@@ -27,17 +38,36 @@ pub enum ColorOutput {
 #[derive(Debug, Clone)]
 pub struct DisplayOptions {
     pub background_color: BackgroundColor,
+    pub theme: theme::Theme,
     pub use_color: bool,
     pub display_mode: DisplayMode,
     pub print_unchanged: bool,
+    /// Should we display files whose only changes are formatting?
+    pub print_formatting_only: bool,
     pub tab_width: usize,
     pub display_width: usize,
     pub num_context_lines: u32,
     pub in_vcs: bool,
     pub syntax_highlight: bool,
+    /// Should we show the results in the interactive pager?
+    pub interactive: bool,
 }
 
-fn app() -> clap::Command<'static> {
+#[derive(Debug, Clone)]
+pub struct DiffOptions {
+    pub graph_limit: usize,
+    pub byte_limit: usize,
+    /// Use a text diff if diffing a file takes longer than this.
+    pub time_limit: Option<Duration>,
+    /// Use a text diff if diffing a file needs more bytes than this.
+    pub memory_limit: Option<usize>,
+    pub ignore_comments: bool,
+    /// File extensions that should be parsed as a specific language,
+    /// rather than guessing.
+    pub language_overrides: Vec<(String, guess_language::Language)>,
+}
+
+fn app<'a>() -> clap::Command<'a> {
     Command::new("Difftastic")
         .override_usage(USAGE)
         .version(crate_version!())
@@ -53,6 +83,15 @@ fn app() -> clap::Command<'static> {
             env!("CARGO_BIN_NAME"),
             " old/ new/\n\n",
             "Difftastic can also be invoked with 7 arguments in the format that GIT_EXTERNAL_DIFF expects.\n\n",
+            "To compare two git revisions without configuring git, use --git.\n\n",
+            "$ ",
+            env!("CARGO_BIN_NAME"),
+            " --git HEAD~1 HEAD\n\n",
+            "Difftastic can also merge two files that were derived from a common base, as a git merge driver.\n\n",
+            "$ ",
+            env!("CARGO_BIN_NAME"),
+            " merge BASE LEFT RIGHT [PATH]\n\n",
+            "Defaults for these options can be set in $XDG_CONFIG_HOME/difftastic/config.toml.\n\n",
             "See the full manual at: https://difftastic.wilfred.me.uk/")
         )
         .arg(
@@ -99,7 +138,7 @@ fn app() -> clap::Command<'static> {
                 .long("tab-width")
                 .takes_value(true)
                 .value_name("NUM_SPACES")
-                .long_help("Treat a tab as this many spaces.")
+                .long_help("Display a tab as this many spaces. Tabs are preserved when parsing and diffing.")
                 .env("DFT_TAB_WIDTH")
                 .default_value(formatcp!("{}", DEFAULT_TAB_WIDTH))
                 .validator(|s| s.parse::<usize>())
@@ -107,10 +146,10 @@ fn app() -> clap::Command<'static> {
         )
         .arg(
             Arg::new("display").long("display")
-                .possible_values(["side-by-side", "side-by-side-show-both", "inline", ])
+                .possible_values(["side-by-side", "side-by-side-show-both", "inline", "json", "html", "patch"])
                 .value_name("MODE")
                 .env("DFT_DISPLAY")
-                .help("Display mode for showing results.")
+                .help("Display mode for showing results. json prints a versioned, machine-readable document, html prints a self-contained HTML page, and patch prints a unified diff suitable for git apply.")
         )
         .arg(
             Arg::new("color").long("color")
@@ -126,6 +165,13 @@ fn app() -> clap::Command<'static> {
                 .default_value("dark")
                 .help("Set the background brightness. Difftastic will prefer brighter colours on dark backgrounds.")
         )
+        .arg(
+            Arg::new("theme").long("theme")
+                .value_name("THEME")
+                .env("DFT_THEME")
+                .default_value("default")
+                .help("The colours to use. Either a built-in theme (default, colorblind) or the path to a theme file. The colorblind theme shows removals in orange and additions in blue.")
+        )
         .arg(
             Arg::new("syntax-highlight").long("syntax-highlight")
                 .value_name("on/off")
@@ -134,14 +180,91 @@ fn app() -> clap::Command<'static> {
                 .default_value("on")
                 .help("Enable or disable syntax highlighting.")
         )
+        .arg(
+            Arg::new("ignore-comments").long("ignore-comments")
+                .env("DFT_IGNORE_COMMENTS")
+                .help("Don't consider comments when diffing. Files whose only changes are in comments will report no syntactic changes.")
+        )
+        .arg(
+            Arg::new("no-ignore-comments").long("no-ignore-comments")
+                .overrides_with("ignore-comments")
+                .help("Consider comments when diffing, even if ignore-comments is set in the configuration file.")
+        )
         .arg(
             Arg::new("skip-unchanged").long("skip-unchanged")
                 .help("Don't display anything if a file is unchanged.")
         )
+        .arg(
+            Arg::new("no-skip-unchanged").long("no-skip-unchanged")
+                .overrides_with("skip-unchanged")
+                .help("Display unchanged files, even if skip-unchanged is set in the configuration file.")
+        )
+        .arg(
+            Arg::new("skip-formatting-only").long("skip-formatting-only")
+                .help("Don't display anything if a file only has formatting changes, such as whitespace.")
+        )
+        .arg(
+            Arg::new("no-skip-formatting-only").long("no-skip-formatting-only")
+                .overrides_with("skip-formatting-only")
+                .help("Display files with only formatting changes, even if skip-formatting-only is set in the configuration file.")
+        )
+        .arg(
+            Arg::new("exit-code").long("exit-code")
+                .env("DFT_EXIT_CODE")
+                .help("Exit with 1 if there are syntactic changes, 3 if there are only formatting changes, and 0 if there are no changes.")
+        )
+        .arg(
+            Arg::new("no-exit-code").long("no-exit-code")
+                .overrides_with("exit-code")
+                .help("Exit with 0 when there are changes, even if exit-code is set in the configuration file.")
+        )
+        .arg(
+            Arg::new("interactive").long("interactive")
+                .help("Show the results in a full-screen pager, with keys to jump between hunks and files, switch between inline and side-by-side display, and change the context size. Ignored if stdout is not a terminal.")
+        )
+        .arg(
+            Arg::new("no-interactive").long("no-interactive")
+                .overrides_with("interactive")
+                .help("Don't use the full-screen pager, even if interactive is set in the configuration file.")
+        )
+        .arg(
+            Arg::new("summary").long("summary")
+                .help("For each file, list the definitions (e.g. functions and classes) that were added, removed or modified, rather than showing the changed lines.")
+        )
+        .arg(
+            Arg::new("stat").long("stat")
+                .conflicts_with("summary")
+                .help("Show the number of changed lines and tokens in each file, similar to git diff --stat.")
+        )
+        .arg(
+            Arg::new("list-changes").long("list-changes")
+                .conflicts_with_all(&["summary", "stat"])
+                .help("List the files with syntactic changes, and the files that only have formatting changes, rather than showing the changes.")
+        )
+        .arg(
+            Arg::new("check").long("check")
+                .help("Don't display anything, just set the exit code as with --exit-code.")
+        )
+        .arg(
+            Arg::new("git").long("git")
+                .help("Treat the arguments as two git revisions, optionally followed by paths, and diff them directly from the git repository in the current directory.")
+        )
+        .arg(
+            Arg::new("output").long("output")
+                .takes_value(true)
+                .value_name("PATH")
+                .allow_invalid_utf8(true)
+                .help("When merging, write the merged file to this path rather than stdout.")
+        )
         .arg(
             Arg::new("missing-as-empty").long("missing-as-empty")
                 .help("Treat paths that don't exist as equivalent to an empty file. Only applies when diffing files, not directories.")
         )
+        .arg(
+            Arg::new("no-missing-as-empty").long("no-missing-as-empty")
+                .overrides_with("missing-as-empty")
+                .help("Treat paths that don't exist as an error, even if missing-as-empty is set in the configuration file.")
+        )
         .arg(
             Arg::new("language").long("language")
                 .value_name("EXT")
@@ -173,6 +296,24 @@ fn app() -> clap::Command<'static> {
                 .validator(|s| s.parse::<usize>())
                 .required(false),
         )
+        .arg(
+            Arg::new("time-limit").long("time-limit")
+                .takes_value(true)
+                .value_name("DURATION")
+                .help("Use a text diff if parsing and diffing a file takes longer than this, e.g. 500ms, 2s or 1m.")
+                .env("DFT_TIME_LIMIT")
+                .validator(parse_duration)
+                .required(false),
+        )
+        .arg(
+            Arg::new("memory-limit").long("memory-limit")
+                .takes_value(true)
+                .value_name("SIZE")
+                .help("Use a text diff if parsing and diffing a file would use more memory than this, e.g. 500M or 1G.")
+                .env("DFT_MEMORY_LIMIT")
+                .validator(parse_byte_size)
+                .required(false),
+        )
         .arg(
             Arg::new("paths")
                 .value_name("PATHS")
@@ -188,6 +329,23 @@ pub enum DisplayMode {
     Inline,
     SideBySide,
     SideBySideShowBoth,
+    Json,
+    Html,
+    Patch,
+    Summary,
+    Stat,
+    ChangeList,
+}
+
+impl DisplayMode {
+    /// Does this mode print all the results as a single document,
+    /// rather than printing each file as soon as it's diffed?
+    pub fn is_single_document(self) -> bool {
+        matches!(
+            self,
+            DisplayMode::Json | DisplayMode::Html | DisplayMode::Stat | DisplayMode::ChangeList
+        )
+    }
 }
 
 #[derive(Eq, PartialEq, Debug)]
@@ -227,11 +385,14 @@ impl FileArgument {
 
 pub enum Mode {
     Diff {
-        graph_limit: usize,
-        byte_limit: usize,
+        diff_options: DiffOptions,
         display_options: DisplayOptions,
         missing_as_empty: bool,
         language_override: Option<guess_language::Language>,
+        /// Should the exit code reflect whether we found changes?
+        set_exit_code: bool,
+        /// Should we skip displaying results entirely?
+        check_only: bool,
         /// The path where we can read the LHS file. This is often a
         /// temporary file generated by source control.
         lhs_path: FileArgument,
@@ -245,6 +406,31 @@ pub enum Mode {
         /// The path that we should display for the RHS file.
         rhs_display_path: String,
     },
+    DiffFromGit {
+        diff_options: DiffOptions,
+        display_options: DisplayOptions,
+        language_override: Option<guess_language::Language>,
+        set_exit_code: bool,
+        check_only: bool,
+        /// The revision to read LHS files from.
+        lhs_rev: String,
+        /// The revision to read RHS files from.
+        rhs_rev: String,
+        /// Only diff files under these paths. Diff all files if empty.
+        paths: Vec<OsString>,
+    },
+    Merge {
+        diff_options: DiffOptions,
+        language_override: Option<guess_language::Language>,
+        base_path: PathBuf,
+        lhs_path: PathBuf,
+        rhs_path: PathBuf,
+        /// The path used for language detection. Merge drivers are
+        /// given temporary files, so this may differ from `lhs_path`.
+        display_path: Option<String>,
+        /// Where to write the merged file. Prints to stdout if `None`.
+        output_path: Option<PathBuf>,
+    },
     ListLanguages {
         use_color: bool,
     },
@@ -258,9 +444,166 @@ pub enum Mode {
     },
 }
 
+/// Print the usage and terminate, because we were called with the
+/// wrong number of arguments.
+fn exit_with_usage_error(num_args: usize) -> ! {
+    if num_args > 0 {
+        eprintln!(
+            "error: Difftastic does not support being called with {} argument{}.\n",
+            num_args,
+            if num_args == 1 { "" } else { "s" }
+        );
+    }
+    eprintln!("USAGE:\n\n    {}\n", USAGE);
+    eprintln!("For more information try --help");
+    std::process::exit(EXIT_ERROR);
+}
+
+/// Check that `value` from the configuration file is a value that
+/// the option `name` accepts, terminating the process if not.
+fn check_config_value(app: &Command, name: &str, value: &str) {
+    let possible_values = app
+        .get_arguments()
+        .find(|arg| arg.get_long() == Some(name))
+        .and_then(|arg| arg.get_possible_values());
+
+    if let Some(possible_values) = possible_values {
+        if !possible_values.iter().any(|pv| pv.get_name() == value) {
+            let names: Vec<&str> = possible_values.iter().map(|pv| pv.get_name()).collect();
+            eprintln!(
+                "error: Invalid value {:?} for {} in configuration file, expected one of: {}",
+                value,
+                name,
+                names.join(", ")
+            );
+            std::process::exit(EXIT_ERROR);
+        }
+    }
+}
+
+/// Parse a duration such as `500ms`, `2s` or `1m`. A number without
+/// a unit is in seconds.
+fn parse_duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("Expected a duration such as 2s, got {:?}", s))?;
+    match unit.trim() {
+        "ms" => Ok(Duration::from_millis(number)),
+        "" | "s" => Ok(Duration::from_secs(number)),
+        "m" => number
+            .checked_mul(60)
+            .map(Duration::from_secs)
+            .ok_or_else(|| format!("Duration {:?} is too large", s)),
+        _ => Err(format!(
+            "Unknown unit {:?} in duration, expected ms, s or m",
+            unit
+        )),
+    }
+}
+
+/// Parse a size in bytes such as `512K`, `500M` or `1G`. A number
+/// without a suffix is in bytes.
+fn parse_byte_size(s: &str) -> Result<usize, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (number, suffix) = s.split_at(split);
+
+    let number: usize = number
+        .parse()
+        .map_err(|_| format!("Expected a size such as 1G, got {:?}", s))?;
+    let multiplier: usize = match suffix.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" => 1 << 10,
+        "M" | "MB" => 1 << 20,
+        "G" | "GB" => 1 << 30,
+        _ => {
+            return Err(format!(
+                "Unknown suffix {:?} in size, expected K, M or G",
+                suffix
+            ))
+        }
+    };
+    number
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("Size {:?} is too large", s))
+}
+
+/// Parse the value of an option that was validated by clap. Values
+/// from the configuration file aren't validated, so terminate the
+/// process if the value is invalid.
+fn parse_or_die<T>(
+    matches: &clap::ArgMatches,
+    name: &str,
+    parse: fn(&str) -> Result<T, String>,
+) -> Option<T> {
+    let value = matches.value_of(name)?;
+    match parse(value) {
+        Ok(parsed) => Some(parsed),
+        Err(e) => {
+            eprintln!("error: Invalid value for --{}: {}", name, e);
+            std::process::exit(EXIT_ERROR);
+        }
+    }
+}
+
+/// Parse the per-extension language overrides from the configuration
+/// file, warning about any languages we don't recognise.
+fn parse_language_overrides(config: &config::Config) -> Vec<(String, guess_language::Language)> {
+    let mut overrides = vec![];
+    for (extension, language_extension) in &config.languages {
+        match guess_language::from_extension(OsStr::new(language_extension)) {
+            Some(language) => overrides.push((extension.clone(), language)),
+            None => eprintln!(
+                "No language is associated with extension: {}",
+                language_extension
+            ),
+        }
+    }
+    overrides
+}
+
+/// Whether the flag `name` is enabled. A flag on the command line
+/// takes precedence, then the environment variable `env_var` (if the
+/// flag has one), then the configuration file.
+fn is_flag_enabled(
+    matches: &clap::ArgMatches,
+    name: &str,
+    env_var: Option<&str>,
+    config_value: bool,
+) -> bool {
+    if matches.is_present(format!("no-{}", name)) {
+        false
+    } else if matches.is_present(name) {
+        true
+    } else if env_var.map_or(false, |env_var| env::var_os(env_var).is_some()) {
+        // clap ignores flags whose environment variable is set to a
+        // false value, such as DFT_EXIT_CODE=0.
+        false
+    } else {
+        config_value
+    }
+}
+
 /// Parse CLI arguments passed to the binary.
 pub fn parse_args() -> Mode {
-    let matches = app().get_matches();
+    // Handle --help, --version and invalid arguments before reading
+    // the configuration file, so they work even if it's invalid.
+    app().get_matches();
+    let config = config::load_or_die();
+
+    // Values in the configuration file are defaults, so arguments
+    // and environment variables take precedence.
+    let config_values = config.option_values();
+    let mut app = app();
+    for (name, value) in &config_values {
+        check_config_value(&app, name, value);
+        app = app.mut_arg(*name, |arg| arg.default_value(value));
+    }
+    let matches = app.get_matches();
 
     let language_override = match matches.value_of_os("language") {
         Some(lang_str) => {
@@ -312,55 +655,6 @@ pub fn parse_args() -> Mode {
     let args: Vec<_> = matches.values_of_os("paths").unwrap_or_default().collect();
     info!("CLI arguments: {:?}", args);
 
-    // TODO: document these different ways of calling difftastic.
-    let (lhs_display_path, rhs_display_path, lhs_path, rhs_path, in_vcs) = match &args[..] {
-        [lhs_path, rhs_path] => {
-            let lhs_arg = FileArgument::from_cli_argument(lhs_path);
-            let rhs_arg = FileArgument::from_cli_argument(rhs_path);
-            (
-                lhs_arg.display(),
-                rhs_arg.display(),
-                lhs_arg,
-                rhs_arg,
-                false,
-            )
-        }
-        [display_path, lhs_tmp_file, _lhs_hash, _lhs_mode, rhs_tmp_file, _rhs_hash, _rhs_mode] => {
-            // https://git-scm.com/docs/git#Documentation/git.txt-codeGITEXTERNALDIFFcode
-            (
-                display_path.to_string_lossy().to_string(),
-                display_path.to_string_lossy().to_string(),
-                FileArgument::from_path_argument(lhs_tmp_file),
-                FileArgument::from_path_argument(rhs_tmp_file),
-                true,
-            )
-        }
-        [old_name, lhs_tmp_file, _lhs_hash, _lhs_mode, rhs_tmp_file, _rhs_hash, _rhs_mode, new_name, _similarity] =>
-        {
-            // Rename file.
-            // TODO: where does git document these 9 arguments?
-            (
-                old_name.to_string_lossy().to_string(),
-                new_name.to_string_lossy().to_string(),
-                FileArgument::from_path_argument(lhs_tmp_file),
-                FileArgument::from_path_argument(rhs_tmp_file),
-                true,
-            )
-        }
-        _ => {
-            if !args.is_empty() {
-                eprintln!(
-                    "error: Difftastic does not support being called with {} argument{}.\n",
-                    args.len(),
-                    if args.len() == 1 { "" } else { "s" }
-                );
-            }
-            eprintln!("USAGE:\n\n    {}\n", USAGE);
-            eprintln!("For more information try --help");
-            std::process::exit(1);
-        }
-    };
-
     let display_width = if let Some(arg_width) = matches.value_of("width") {
         arg_width
             .parse::<usize>()
@@ -369,11 +663,20 @@ pub fn parse_args() -> Mode {
         detect_display_width()
     };
 
-    let display_mode = if let Some(display_mode_str) = matches.value_of("display") {
+    let display_mode = if matches.is_present("summary") {
+        DisplayMode::Summary
+    } else if matches.is_present("stat") {
+        DisplayMode::Stat
+    } else if matches.is_present("list-changes") {
+        DisplayMode::ChangeList
+    } else if let Some(display_mode_str) = matches.value_of("display") {
         match display_mode_str.borrow() {
             "side-by-side" => DisplayMode::SideBySide,
             "side-by-side-show-both" => DisplayMode::SideBySideShowBoth,
             "inline" => DisplayMode::Inline,
+            "json" => DisplayMode::Json,
+            "html" => DisplayMode::Html,
+            "patch" => DisplayMode::Patch,
             _ => {
                 unreachable!("clap has already validated display")
             }
@@ -391,6 +694,13 @@ pub fn parse_args() -> Mode {
         _ => unreachable!("clap has already validated the values"),
     };
 
+    let theme = theme::load_or_die(
+        matches
+            .value_of("theme")
+            .expect("Always present as we've given clap a default"),
+        background_color,
+    );
+
     let syntax_highlight = matches.value_of("syntax-highlight") == Some("on");
 
     let graph_limit = matches
@@ -405,6 +715,9 @@ pub fn parse_args() -> Mode {
         .parse::<usize>()
         .expect("Value already validated by clap");
 
+    let time_limit = parse_or_die(&matches, "time-limit", parse_duration);
+    let memory_limit = parse_or_die(&matches, "memory-limit", parse_byte_size);
+
     let tab_width = matches
         .value_of("tab-width")
         .expect("Always present as we've given clap a default")
@@ -417,30 +730,158 @@ pub fn parse_args() -> Mode {
         .parse::<u32>()
         .expect("Value already validated by clap");
 
-    let print_unchanged = !matches.is_present("skip-unchanged");
+    let ignore_comments = is_flag_enabled(
+        &matches,
+        "ignore-comments",
+        Some("DFT_IGNORE_COMMENTS"),
+        config.ignore_comments,
+    );
+
+    let diff_options = DiffOptions {
+        graph_limit,
+        byte_limit,
+        time_limit,
+        memory_limit,
+        ignore_comments,
+        language_overrides: parse_language_overrides(&config),
+    };
+
+    let print_unchanged = !is_flag_enabled(&matches, "skip-unchanged", None, config.skip_unchanged);
+    let print_formatting_only = !is_flag_enabled(
+        &matches,
+        "skip-formatting-only",
+        None,
+        config.skip_formatting_only,
+    );
 
     // TODO: is this necessary now we handle /dev/null as an empty
     // file on all platforms?
-    let missing_as_empty = matches.is_present("missing-as-empty");
+    let missing_as_empty =
+        is_flag_enabled(&matches, "missing-as-empty", None, config.missing_as_empty);
+
+    let interactive = is_flag_enabled(&matches, "interactive", None, config.interactive)
+        && atty::is(Stream::Stdout);
+
+    let check_only = matches.is_present("check");
+    let set_exit_code = check_only
+        || is_flag_enabled(
+            &matches,
+            "exit-code",
+            Some("DFT_EXIT_CODE"),
+            config.exit_code,
+        );
+
+    if let [command, base_path, lhs_path, rhs_path, display_path @ ..] = &args[..] {
+        if *command == "merge" && display_path.len() <= 1 {
+            return Mode::Merge {
+                diff_options,
+                language_override,
+                base_path: PathBuf::from(base_path),
+                lhs_path: PathBuf::from(lhs_path),
+                rhs_path: PathBuf::from(rhs_path),
+                display_path: display_path
+                    .first()
+                    .map(|path| path.to_string_lossy().to_string()),
+                output_path: matches.value_of_os("output").map(PathBuf::from),
+            };
+        }
+    }
+
+    if matches.is_present("git") {
+        let (lhs_rev, rhs_rev, paths) = match &args[..] {
+            [lhs_rev, rhs_rev, paths @ ..] => (
+                lhs_rev.to_string_lossy().to_string(),
+                rhs_rev.to_string_lossy().to_string(),
+                paths.iter().map(|path| path.to_os_string()).collect(),
+            ),
+            _ => exit_with_usage_error(args.len()),
+        };
+
+        return Mode::DiffFromGit {
+            diff_options,
+            display_options: DisplayOptions {
+                background_color,
+                theme,
+                use_color,
+                print_unchanged,
+                print_formatting_only,
+                tab_width,
+                display_mode,
+                display_width,
+                num_context_lines,
+                syntax_highlight,
+                in_vcs: true,
+                interactive,
+            },
+            language_override,
+            set_exit_code,
+            check_only,
+            lhs_rev,
+            rhs_rev,
+            paths,
+        };
+    }
+
+    // TODO: document these different ways of calling difftastic.
+    let (lhs_display_path, rhs_display_path, lhs_path, rhs_path, in_vcs) = match &args[..] {
+        [lhs_path, rhs_path] => {
+            let lhs_arg = FileArgument::from_cli_argument(lhs_path);
+            let rhs_arg = FileArgument::from_cli_argument(rhs_path);
+            (
+                lhs_arg.display(),
+                rhs_arg.display(),
+                lhs_arg,
+                rhs_arg,
+                false,
+            )
+        }
+        [display_path, lhs_tmp_file, _lhs_hash, _lhs_mode, rhs_tmp_file, _rhs_hash, _rhs_mode] => {
+            // https://git-scm.com/docs/git#Documentation/git.txt-codeGITEXTERNALDIFFcode
+            (
+                display_path.to_string_lossy().to_string(),
+                display_path.to_string_lossy().to_string(),
+                FileArgument::from_path_argument(lhs_tmp_file),
+                FileArgument::from_path_argument(rhs_tmp_file),
+                true,
+            )
+        }
+        [old_name, lhs_tmp_file, _lhs_hash, _lhs_mode, rhs_tmp_file, _rhs_hash, _rhs_mode, new_name, _similarity] =>
+        {
+            // Rename file.
+            // TODO: where does git document these 9 arguments?
+            (
+                old_name.to_string_lossy().to_string(),
+                new_name.to_string_lossy().to_string(),
+                FileArgument::from_path_argument(lhs_tmp_file),
+                FileArgument::from_path_argument(rhs_tmp_file),
+                true,
+            )
+        }
+        _ => exit_with_usage_error(args.len()),
+    };
 
     let display_options = DisplayOptions {
         background_color,
+        theme,
         use_color,
         print_unchanged,
+        print_formatting_only,
         tab_width,
         display_mode,
         display_width,
         num_context_lines,
         syntax_highlight,
         in_vcs,
+        interactive,
     };
 
     Mode::Diff {
-        graph_limit,
-        byte_limit,
+        diff_options,
         display_options,
         missing_as_empty,
         language_override,
+        set_exit_code,
+        check_only,
         lhs_path,
         rhs_path,
         lhs_display_path,
@@ -491,6 +932,43 @@ mod tests {
         app().debug_assert();
     }
 
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("500ms"), Ok(Duration::from_millis(500)));
+        assert_eq!(parse_duration("2s"), Ok(Duration::from_secs(2)));
+        assert_eq!(parse_duration("3"), Ok(Duration::from_secs(3)));
+        assert_eq!(parse_duration("1m"), Ok(Duration::from_secs(60)));
+        assert!(parse_duration("2h").is_err());
+        assert!(parse_duration("s").is_err());
+        assert!(parse_duration("999999999999999999m").is_err());
+    }
+
+    #[test]
+    fn test_parse_byte_size() {
+        assert_eq!(parse_byte_size("100"), Ok(100));
+        assert_eq!(parse_byte_size("512K"), Ok(512 * 1024));
+        assert_eq!(parse_byte_size("1G"), Ok(1024 * 1024 * 1024));
+        assert_eq!(parse_byte_size("2mb"), Ok(2 * 1024 * 1024));
+        assert!(parse_byte_size("1T").is_err());
+    }
+
+    #[test]
+    fn test_is_flag_enabled() {
+        let matches = app().get_matches_from(["difft", "a", "b"]);
+        assert!(is_flag_enabled(&matches, "skip-unchanged", None, true));
+        assert!(!is_flag_enabled(&matches, "skip-unchanged", None, false));
+
+        let matches = app().get_matches_from(["difft", "--skip-unchanged", "a", "b"]);
+        assert!(is_flag_enabled(&matches, "skip-unchanged", None, false));
+
+        let matches = app().get_matches_from(["difft", "--no-skip-unchanged", "a", "b"]);
+        assert!(!is_flag_enabled(&matches, "skip-unchanged", None, true));
+
+        let matches =
+            app().get_matches_from(["difft", "--no-skip-unchanged", "--skip-unchanged", "a", "b"]);
+        assert!(is_flag_enabled(&matches, "skip-unchanged", None, false));
+    }
+
     #[test]
     fn test_detect_display_width() {
         // Basic smoke test.
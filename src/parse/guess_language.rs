@@ -14,6 +14,8 @@ use lazy_static::lazy_static;
 use regex::Regex;
 use std::{borrow::Borrow, ffi::OsStr, path::Path};
 
+use crate::parse::plugins;
+
 /// Languages supported by difftastic. Each language here has a
 /// corresponding tree-sitter parser.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -65,6 +67,9 @@ pub enum Language {
     TypeScript,
     Yaml,
     Zig,
+    /// A grammar loaded at runtime, identified by its index in
+    /// `plugins::all()`.
+    Plugin(usize),
 }
 
 /// The language name shown to the user.
@@ -117,6 +122,7 @@ pub fn language_name(language: Language) -> &'static str {
         TypeScript => "TypeScript",
         Yaml => "YAML",
         Zig => "Zig",
+        Plugin(id) => &plugins::all()[id].name,
     }
 }
 
@@ -353,9 +359,30 @@ fn from_name(path: &Path) -> Option<Language> {
     }
 }
 
+/// Look up the extension of `path` in `overrides`, which pairs file
+/// extensions with the language to use for them.
+pub fn from_overrides(path: &Path, overrides: &[(String, Language)]) -> Option<Language> {
+    let extension = path.extension()?.to_string_lossy();
+    overrides
+        .iter()
+        .find(|(override_extension, _)| *override_extension == extension)
+        .map(|(_, language)| *language)
+}
+
 pub fn from_extension(current_extension: &OsStr) -> Option<Language> {
     let current_extension = current_extension.to_string_lossy();
 
+    // Prefer plugins, so users can override the built-in parsers.
+    for (id, plugin) in plugins::all().iter().enumerate() {
+        if plugin
+            .extensions
+            .iter()
+            .any(|ext| *ext == current_extension)
+        {
+            return Some(Plugin(id));
+        }
+    }
+
     for (language, extensions) in LANG_EXTENSIONS {
         for extension in *extensions {
             if &*current_extension == *extension {
@@ -377,6 +404,13 @@ mod tests {
         assert_eq!(guess(path, ""), Some(EmacsLisp));
     }
 
+    #[test]
+    fn test_from_overrides() {
+        let overrides = vec![("h".to_owned(), C)];
+        assert_eq!(from_overrides(Path::new("foo.h"), &overrides), Some(C));
+        assert_eq!(from_overrides(Path::new("foo.c"), &overrides), None);
+    }
+
     #[test]
     fn test_guess_by_whole_name() {
         let path = Path::new("foo/.bashrc");
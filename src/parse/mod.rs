@@ -1,3 +1,4 @@
 pub mod guess_language;
+pub mod plugins;
 pub mod syntax;
 pub mod tree_sitter_parser;
@@ -0,0 +1,258 @@
+//! Tree-sitter grammars loaded at runtime.
+//!
+//! Each plugin is a directory inside the grammar directory containing
+//! a `grammar.json` manifest, a compiled grammar shared library and
+//! optionally a highlights query:
+//!
+//! ```json
+//! {
+//!   "name": "MyDSL",
+//!   "extensions": ["mydsl"],
+//!   "library": "libtree-sitter-mydsl.so",
+//!   "highlights": "highlights.scm",
+//!   "atom_nodes": ["string"],
+//!   "delimiter_tokens": [["(", ")"], ["{", "}"]]
+//! }
+//! ```
+//!
+//! Plugins are loaded once, the first time they're needed. Plugins
+//! that can't be loaded are reported on stderr and ignored.
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use lazy_static::lazy_static;
+use serde::Deserialize;
+use tree_sitter as ts;
+
+use crate::config;
+
+const MANIFEST_FILE_NAME: &str = "grammar.json";
+
+#[derive(Debug, Deserialize, PartialEq)]
+struct Manifest {
+    name: String,
+    extensions: Vec<String>,
+    /// The path to the shared library, relative to the plugin
+    /// directory.
+    library: PathBuf,
+    /// The name of the function in the library that returns the
+    /// grammar. Defaults to `tree_sitter_` followed by the name.
+    symbol: Option<String>,
+    /// The path to the highlights query, relative to the plugin
+    /// directory.
+    highlights: Option<PathBuf>,
+    #[serde(default)]
+    atom_nodes: Vec<String>,
+    #[serde(default)]
+    delimiter_tokens: Vec<(String, String)>,
+}
+
+/// A tree-sitter grammar loaded from a plugin directory.
+pub struct GrammarPlugin {
+    pub name: String,
+    pub extensions: Vec<String>,
+    pub language: PluginLanguage,
+    pub highlights: String,
+    pub atom_nodes: Vec<String>,
+    pub delimiter_tokens: Vec<(String, String)>,
+}
+
+/// A tree-sitter language from a shared library.
+pub struct PluginLanguage(pub ts::Language);
+
+// A tree-sitter language is a pointer to immutable static data in the
+// shared library, which we never unload.
+unsafe impl Send for PluginLanguage {}
+unsafe impl Sync for PluginLanguage {}
+
+lazy_static! {
+    static ref PLUGINS: Vec<GrammarPlugin> = match grammar_dir() {
+        Some(dir) => load_plugins(&dir),
+        None => vec![],
+    };
+}
+
+/// All the plugins that loaded successfully.
+pub fn all() -> &'static [GrammarPlugin] {
+    &PLUGINS
+}
+
+/// The directory to load plugins from: `DFT_GRAMMAR_DIR` if set,
+/// otherwise `grammars` in the user's configuration directory.
+fn grammar_dir() -> Option<PathBuf> {
+    if let Some(dir) = env::var_os("DFT_GRAMMAR_DIR") {
+        return Some(PathBuf::from(dir));
+    }
+    Some(config::config_dir()?.join("grammars"))
+}
+
+fn load_plugins(dir: &Path) -> Vec<GrammarPlugin> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        // It's fine not to have any plugins.
+        Err(_) => return vec![],
+    };
+
+    let mut plugin_dirs: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.join(MANIFEST_FILE_NAME).is_file())
+        .collect();
+    plugin_dirs.sort();
+
+    let mut plugins = vec![];
+    for plugin_dir in plugin_dirs {
+        match load_plugin(&plugin_dir) {
+            Ok(plugin) => plugins.push(plugin),
+            Err(e) => eprintln!(
+                "warning: Could not load grammar plugin {}: {}",
+                plugin_dir.display(),
+                e
+            ),
+        }
+    }
+    plugins
+}
+
+fn parse_manifest(src: &str) -> Result<Manifest, String> {
+    serde_json::from_str(src).map_err(|e| format!("Invalid {}: {}", MANIFEST_FILE_NAME, e))
+}
+
+/// The default name of the function that returns the grammar, based
+/// on the tree-sitter CLI's naming convention.
+fn default_symbol(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("tree_sitter_{}", name)
+}
+
+fn load_plugin(plugin_dir: &Path) -> Result<GrammarPlugin, String> {
+    let manifest_path = plugin_dir.join(MANIFEST_FILE_NAME);
+    let manifest_src = fs::read_to_string(&manifest_path)
+        .map_err(|e| format!("Could not read {}: {}", manifest_path.display(), e))?;
+    let manifest = parse_manifest(&manifest_src)?;
+
+    let symbol = manifest
+        .symbol
+        .clone()
+        .unwrap_or_else(|| default_symbol(&manifest.name));
+    let language = load_language(&plugin_dir.join(&manifest.library), &symbol)?;
+
+    let version = language.version();
+    if !(ts::MIN_COMPATIBLE_LANGUAGE_VERSION..=ts::LANGUAGE_VERSION).contains(&version) {
+        return Err(format!(
+            "Grammar uses tree-sitter ABI version {}, but difftastic supports versions {} to {}",
+            version,
+            ts::MIN_COMPATIBLE_LANGUAGE_VERSION,
+            ts::LANGUAGE_VERSION
+        ));
+    }
+
+    let highlights = match &manifest.highlights {
+        Some(path) => {
+            let path = plugin_dir.join(path);
+            fs::read_to_string(&path)
+                .map_err(|e| format!("Could not read {}: {}", path.display(), e))?
+        }
+        None => String::new(),
+    };
+    // Check the query now, so we can assume it's valid when parsing.
+    ts::Query::new(language, &highlights)
+        .map_err(|e| format!("Invalid highlights query: {:?}", e))?;
+
+    Ok(GrammarPlugin {
+        name: manifest.name,
+        extensions: manifest.extensions,
+        language: PluginLanguage(language),
+        highlights,
+        atom_nodes: manifest.atom_nodes,
+        delimiter_tokens: manifest.delimiter_tokens,
+    })
+}
+
+/// Load the shared library at `library_path`, and call `symbol` to
+/// get the tree-sitter language.
+#[cfg(unix)]
+fn load_language(library_path: &Path, symbol: &str) -> Result<ts::Language, String> {
+    use std::{
+        ffi::{CStr, CString},
+        os::unix::ffi::OsStrExt,
+    };
+
+    let c_path = CString::new(library_path.as_os_str().as_bytes())
+        .map_err(|_| format!("Invalid library path: {}", library_path.display()))?;
+    let c_symbol = CString::new(symbol).map_err(|_| format!("Invalid symbol: {}", symbol))?;
+
+    unsafe {
+        // We never close the library, as tree-sitter languages must
+        // live for the rest of the process.
+        let handle = libc::dlopen(c_path.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL);
+        if handle.is_null() {
+            let err = libc::dlerror();
+            return Err(if err.is_null() {
+                format!("Could not load {}", library_path.display())
+            } else {
+                CStr::from_ptr(err).to_string_lossy().into_owned()
+            });
+        }
+
+        let language_fn = libc::dlsym(handle, c_symbol.as_ptr());
+        if language_fn.is_null() {
+            return Err(format!(
+                "No function {} in {}",
+                symbol,
+                library_path.display()
+            ));
+        }
+
+        let language_fn: unsafe extern "C" fn() -> ts::Language = std::mem::transmute(language_fn);
+        Ok(language_fn())
+    }
+}
+
+#[cfg(not(unix))]
+fn load_language(_library_path: &Path, _symbol: &str) -> Result<ts::Language, String> {
+    Err("Grammar plugins are only supported on Unix".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_parse_manifest_defaults() {
+        let manifest =
+            parse_manifest(r#"{"name": "My DSL", "extensions": ["dsl"], "library": "dsl.so"}"#)
+                .unwrap();
+
+        assert_eq!(
+            manifest,
+            Manifest {
+                name: "My DSL".into(),
+                extensions: vec!["dsl".into()],
+                library: PathBuf::from("dsl.so"),
+                symbol: None,
+                highlights: None,
+                atom_nodes: vec![],
+                delimiter_tokens: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn test_default_symbol() {
+        assert_eq!(default_symbol("My-DSL"), "tree_sitter_my_dsl");
+    }
+}
@@ -2,6 +2,7 @@
 
 #![allow(clippy::mutable_key_type)] // Hash for Syntax doesn't use mutable fields.
 
+use rustc_hash::FxHashMap;
 use std::{cell::Cell, collections::HashMap, env, fmt, hash::Hash, num::NonZeroU32};
 use typed_arena::Arena;
 
@@ -10,7 +11,9 @@ use crate::{
     diff::changes::{ChangeKind::*, ChangeMap},
     diff::myers_diff,
     lines::{LineNumber, NewlinePositions},
+    parse::guess_language::Language,
     positions::SingleLineSpan,
+    summary::{DefinitionChange, DefinitionChangeKind},
 };
 use Syntax::*;
 
@@ -28,7 +31,15 @@ impl<'a> fmt::Debug for ChangeKind<'a> {
                     rhs_node.id()
                 )
             }
+            ReplacedString(lhs_node, rhs_node) => {
+                format!(
+                    "ReplacedString(lhs ID: {}, rhs ID: {})",
+                    lhs_node.id(),
+                    rhs_node.id()
+                )
+            }
             Novel => "Novel".to_owned(),
+            Moved(node) => format!("Moved(ID: {})", node.id()),
         };
         f.write_str(&desc)
     }
@@ -52,7 +63,10 @@ pub struct SyntaxInfo<'a> {
     prev_is_contiguous: Cell<bool>,
     /// The number of nodes that are ancestors of this one.
     num_ancestors: Cell<u32>,
-    pub num_after: Cell<usize>,
+    /// The number of nodes after this one that are siblings of this
+    /// node or of its ancestors. Set relative to the roots passed to
+    /// `init_next_prev`, so it only counts nodes in the same section.
+    num_after: Cell<usize>,
     /// A number that uniquely identifies this syntax node.
     unique_id: Cell<SyntaxId>,
     /// A number that uniquely identifies the content of this syntax
@@ -64,6 +78,10 @@ pub struct SyntaxInfo<'a> {
     /// Is this the only node with this content? Ignores nodes on the
     /// other side.
     content_is_unique: Cell<bool>,
+    /// Can this node be reordered among its siblings without changing
+    /// the meaning of its parent? For example, the members of a JSON
+    /// object.
+    is_unordered: Cell<bool>,
 }
 
 impl<'a> SyntaxInfo<'a> {
@@ -79,6 +97,7 @@ impl<'a> SyntaxInfo<'a> {
             unique_id: Cell::new(NonZeroU32::new(u32::MAX).unwrap()),
             content_id: Cell::new(0),
             content_is_unique: Cell::new(false),
+            is_unordered: Cell::new(false),
         }
     }
 }
@@ -293,10 +312,22 @@ impl<'a> Syntax<'a> {
         self.info().content_is_unique.get()
     }
 
+    pub fn is_unordered(&self) -> bool {
+        self.info().is_unordered.get()
+    }
+
+    pub fn set_unordered(&self) {
+        self.info().is_unordered.set(true);
+    }
+
     pub fn num_ancestors(&self) -> u32 {
         self.info().num_ancestors.get()
     }
 
+    pub fn num_after(&self) -> usize {
+        self.info().num_after.get()
+    }
+
     pub fn dbg_content(&self) -> String {
         match self {
             List {
@@ -341,6 +372,41 @@ impl<'a> Syntax<'a> {
     }
 }
 
+/// Return a copy of `nodes` with all the comment atoms removed.
+///
+/// This must be called before `init_all_info`, as it allocates new
+/// list nodes.
+pub fn without_comments<'a>(
+    arena: &'a Arena<Syntax<'a>>,
+    nodes: &[&'a Syntax<'a>],
+) -> Vec<&'a Syntax<'a>> {
+    nodes
+        .iter()
+        .filter_map(|node| match node {
+            List {
+                open_content,
+                open_position,
+                children,
+                close_content,
+                close_position,
+                ..
+            } => Some(Syntax::new_list(
+                arena,
+                open_content,
+                open_position.clone(),
+                without_comments(arena, children),
+                close_content,
+                close_position.clone(),
+            )),
+            Atom {
+                kind: AtomKind::Comment,
+                ..
+            } => None,
+            Atom { .. } => Some(*node),
+        })
+        .collect()
+}
+
 /// Initialise all the fields in `SyntaxInfo`.
 pub fn init_all_info<'a>(lhs_roots: &[&'a Syntax<'a>], rhs_roots: &[&'a Syntax<'a>]) {
     init_info(lhs_roots, rhs_roots);
@@ -375,9 +441,23 @@ fn set_content_id(nodes: &[&Syntax], existing: &mut HashMap<ContentKey, u32>) {
                 // Recurse first, so children all have their content_id set.
                 set_content_id(children, existing);
 
-                let children_content_ids: Vec<_> =
+                let mut children_content_ids: Vec<_> =
                     children.iter().map(|c| c.info().content_id.get()).collect();
 
+                // Reordering unordered children doesn't change the
+                // content, so sort their IDs in place.
+                let unordered_indexes: Vec<usize> = (0..children.len())
+                    .filter(|i| children[*i].is_unordered())
+                    .collect();
+                let mut unordered_ids: Vec<u32> = unordered_indexes
+                    .iter()
+                    .map(|i| children_content_ids[*i])
+                    .collect();
+                unordered_ids.sort_unstable();
+                for (i, id) in unordered_indexes.into_iter().zip(unordered_ids) {
+                    children_content_ids[i] = id;
+                }
+
                 (
                     Some(open_content.clone()),
                     Some(close_content.clone()),
@@ -429,6 +509,7 @@ pub fn init_next_prev<'a>(roots: &[&'a Syntax<'a>]) {
     set_next_sibling(roots);
     set_prev(roots, None);
     set_prev_is_contiguous(roots);
+    set_num_after(roots, 0);
 }
 
 /// Set all the `SyntaxInfo` values for all the `roots` on a single
@@ -436,7 +517,6 @@ pub fn init_next_prev<'a>(roots: &[&'a Syntax<'a>]) {
 fn init_info_on_side<'a>(roots: &[&'a Syntax<'a>], next_id: &mut SyntaxId) {
     set_parent(roots, None);
     set_num_ancestors(roots, 0);
-    set_num_after(roots, 0);
     set_unique_id(roots, next_id);
 }
 
@@ -612,13 +692,22 @@ pub enum MatchKind {
     NovelWord {
         highlight: TokenKind,
     },
+    /// Novel content that also occurs on the other side, but in a
+    /// different position.
+    Moved {
+        highlight: TokenKind,
+        opposite_pos: Vec<SingleLineSpan>,
+    },
 }
 
 impl MatchKind {
     pub fn is_novel(&self) -> bool {
         matches!(
             self,
-            MatchKind::Novel { .. } | MatchKind::NovelWord { .. } | MatchKind::NovelLinePart { .. }
+            MatchKind::Novel { .. }
+                | MatchKind::NovelWord { .. }
+                | MatchKind::NovelLinePart { .. }
+                | MatchKind::Moved { .. }
         )
     }
 }
@@ -656,11 +745,14 @@ pub fn split_words(s: &str) -> Vec<&str> {
     res
 }
 
-fn split_comment_words(
+/// Diff the words in a comment or string literal against the
+/// replaced comment or string on the other side.
+fn split_atom_words(
     content: &str,
     pos: SingleLineSpan,
     opposite_content: &str,
     opposite_pos: SingleLineSpan,
+    highlight: TokenKind,
 ) -> Vec<MatchedPos> {
     // TODO: merge adjacent single-line comments unless there are
     // blank lines between them.
@@ -679,9 +771,7 @@ fn split_comment_words(
             myers_diff::DiffResult::Left(word) => {
                 // This word is novel to this side.
                 res.push(MatchedPos {
-                    kind: MatchKind::NovelWord {
-                        highlight: TokenKind::Atom(AtomKind::Comment),
-                    },
+                    kind: MatchKind::NovelWord { highlight },
                     pos: content_newlines.from_offsets_relative_to(
                         pos,
                         offset,
@@ -702,7 +792,7 @@ fn split_comment_words(
 
                 res.push(MatchedPos {
                     kind: MatchKind::NovelLinePart {
-                        highlight: TokenKind::Atom(AtomKind::Comment),
+                        highlight,
                         self_pos: word_pos,
                         opposite_pos: opposite_word_pos,
                     },
@@ -721,6 +811,25 @@ fn split_comment_words(
     res
 }
 
+/// The position of the token in `opposite` that corresponds with
+/// this one. For lists, this is either the open or close delimiter.
+fn opposite_token_pos(opposite: &Syntax, is_close: bool) -> Vec<SingleLineSpan> {
+    match opposite {
+        List {
+            open_position,
+            close_position,
+            ..
+        } => {
+            if is_close {
+                close_position.clone()
+            } else {
+                open_position.clone()
+            }
+        }
+        Atom { position, .. } => position.clone(),
+    }
+}
+
 impl MatchedPos {
     fn new(
         ck: ChangeKind,
@@ -729,7 +838,7 @@ impl MatchedPos {
         is_close: bool,
     ) -> Vec<Self> {
         match ck {
-            ReplacedComment(this, opposite) => {
+            ReplacedComment(this, opposite) | ReplacedString(this, opposite) => {
                 let this_content = match this {
                     List { .. } => unreachable!(),
                     Atom { content, .. } => content,
@@ -741,29 +850,17 @@ impl MatchedPos {
                     } => (content, position),
                 };
 
-                split_comment_words(
+                split_atom_words(
                     this_content,
                     // TODO: handle the whole pos here.
                     pos[0],
                     opposite_content,
                     opposite_pos[0],
+                    highlight,
                 )
             }
             Unchanged(opposite) => {
-                let opposite_pos = match opposite {
-                    List {
-                        open_position,
-                        close_position,
-                        ..
-                    } => {
-                        if is_close {
-                            close_position.clone()
-                        } else {
-                            open_position.clone()
-                        }
-                    }
-                    Atom { position, .. } => position.clone(),
-                };
+                let opposite_pos = opposite_token_pos(opposite, is_close);
 
                 let opposite_pos_len = opposite_pos.len();
                 let kind = MatchKind::UnchangedToken {
@@ -798,8 +895,14 @@ impl MatchedPos {
                 }
                 res
             }
-            Novel => {
-                let kind = MatchKind::Novel { highlight };
+            Novel | Moved(_) => {
+                let kind = match ck {
+                    Moved(opposite) => MatchKind::Moved {
+                        highlight,
+                        opposite_pos: opposite_token_pos(opposite, is_close),
+                    },
+                    _ => MatchKind::Novel { highlight },
+                };
                 // Create a MatchedPos for every line that `pos` covers.
                 let mut res = vec![];
                 for line_pos in pos {
@@ -877,6 +980,282 @@ fn change_positions_<'a>(
     }
 }
 
+/// Keywords that introduce a named definition, such as a function or
+/// a class.
+const DEFINITION_KEYWORDS: &[&str] = &[
+    "class",
+    "def",
+    "defmacro",
+    "defn",
+    "defun",
+    "enum",
+    "fn",
+    "fun",
+    "func",
+    "function",
+    "impl",
+    "interface",
+    "module",
+    "namespace",
+    "object",
+    "struct",
+    "sub",
+    "trait",
+];
+
+fn is_identifier(s: &str) -> bool {
+    match s.chars().next() {
+        Some(c) => {
+            (c.is_alphabetic() || c == '_' || c == '$')
+                && s.chars()
+                    .all(|c| c.is_alphanumeric() || c == '_' || c == '$' || c == '-')
+        }
+        None => false,
+    }
+}
+
+/// Lisps don't necessarily highlight definition forms as keywords
+/// (e.g. `defn` in Clojure), so we accept any symbol there.
+fn is_lisp(language: Language) -> bool {
+    matches!(
+        language,
+        Language::Clojure | Language::CommonLisp | Language::EmacsLisp | Language::Janet
+    )
+}
+
+/// Is `node` a modifier that may precede a definition keyword, such
+/// as `pub` or `public static`?
+fn is_modifier(node: &Syntax) -> bool {
+    match node {
+        Atom {
+            content,
+            kind: AtomKind::Keyword,
+            ..
+        } => !DEFINITION_KEYWORDS.contains(&content.as_str()),
+        List { open_content, .. } => open_content.is_empty(),
+        Atom { .. } => false,
+    }
+}
+
+/// If `node` is a definition, return a description such as `fn foo`
+/// or `class Bar`.
+///
+/// A definition is a list whose first child (after any modifiers) is
+/// a definition keyword, followed shortly after by a name.
+fn definition_name(node: &Syntax, language: Language) -> Option<String> {
+    let children = match node {
+        List { children, .. } => children,
+        Atom { .. } => return None,
+    };
+
+    let keyword_i = if is_lisp(language) {
+        0
+    } else {
+        children.iter().position(|child| !is_modifier(child))?
+    };
+    let keyword = match children.get(keyword_i)? {
+        Atom { content, kind, .. }
+            if (*kind == AtomKind::Keyword || is_lisp(language))
+                && DEFINITION_KEYWORDS.contains(&content.as_str()) =>
+        {
+            content
+        }
+        _ => return None,
+    };
+
+    // The name may be after other syntax, such as Go method receivers
+    // or Rust type parameters.
+    children
+        .iter()
+        .skip(keyword_i + 1)
+        .take(3)
+        .find_map(|child| match child {
+            Atom { content, kind, .. }
+                if *kind != AtomKind::String
+                    && *kind != AtomKind::Comment
+                    && is_identifier(content)
+                    && !DEFINITION_KEYWORDS.contains(&content.as_str()) =>
+            {
+                Some(format!("{} {}", keyword, content))
+            }
+            _ => None,
+        })
+}
+
+/// Find the innermost definition that contains `node`.
+fn enclosing_definition(node: &Syntax, language: Language) -> Option<String> {
+    let mut ancestor = node.parent();
+    while let Some(node) = ancestor {
+        if let Some(name) = definition_name(node, language) {
+            return Some(name);
+        }
+        ancestor = node.parent();
+    }
+    None
+}
+
+/// For each line that contains changes, find the innermost definition
+/// containing the first changed node on that line.
+pub fn changed_line_definitions<'a>(
+    nodes: &[&'a Syntax<'a>],
+    change_map: &ChangeMap<'a>,
+    language: Language,
+) -> FxHashMap<LineNumber, String> {
+    let mut first_changes: FxHashMap<LineNumber, Option<String>> = FxHashMap::default();
+    changed_line_definitions_(nodes, change_map, language, &mut first_changes);
+
+    first_changes
+        .into_iter()
+        .filter_map(|(line, definition)| Some((line, definition?)))
+        .collect()
+}
+
+fn changed_line_definitions_<'a>(
+    nodes: &[&'a Syntax<'a>],
+    change_map: &ChangeMap<'a>,
+    language: Language,
+    first_changes: &mut FxHashMap<LineNumber, Option<String>>,
+) {
+    for node in nodes {
+        let is_changed = !matches!(change_map.get(node), Some(Unchanged(_)) | None);
+
+        let (position, children): (_, &[&Syntax]) = match node {
+            List {
+                open_position,
+                children,
+                ..
+            } => (open_position, children),
+            Atom { position, .. } => (position, &[]),
+        };
+
+        if is_changed {
+            if let Some(span) = position.first() {
+                first_changes
+                    .entry(span.line)
+                    .or_insert_with(|| enclosing_definition(node, language));
+            }
+        }
+
+        changed_line_definitions_(children, change_map, language, first_changes);
+    }
+}
+
+/// A definition in a syntax tree, along with the definitions nested
+/// inside it.
+struct DefinitionNode {
+    name: String,
+    /// Does this definition contain any changed nodes?
+    is_changed: bool,
+    children: Vec<DefinitionNode>,
+}
+
+/// Does `node`, or any of its descendants, have changes? Moved nodes
+/// are not considered changes, as their content is identical.
+fn has_changes<'a>(node: &'a Syntax<'a>, change_map: &ChangeMap<'a>) -> bool {
+    if matches!(
+        change_map.get(node),
+        Some(Novel | ReplacedComment(_, _) | ReplacedString(_, _))
+    ) {
+        return true;
+    }
+
+    match node {
+        List { children, .. } => children.iter().any(|child| has_changes(child, change_map)),
+        Atom { .. } => false,
+    }
+}
+
+fn definition_tree<'a>(
+    nodes: &[&'a Syntax<'a>],
+    change_map: &ChangeMap<'a>,
+    language: Language,
+) -> Vec<DefinitionNode> {
+    let mut definitions = vec![];
+    for node in nodes {
+        let children: &[&Syntax] = match node {
+            List { children, .. } => children,
+            Atom { .. } => continue,
+        };
+
+        match definition_name(node, language) {
+            Some(name) => definitions.push(DefinitionNode {
+                name,
+                is_changed: has_changes(node, change_map),
+                children: definition_tree(children, change_map, language),
+            }),
+            None => definitions.extend(definition_tree(children, change_map, language)),
+        }
+    }
+    definitions
+}
+
+/// Find the definitions that were added, removed or modified.
+///
+/// Definitions are matched by name, so a renamed definition is
+/// reported as removed and added.
+pub fn definition_changes<'a>(
+    lhs_nodes: &[&'a Syntax<'a>],
+    rhs_nodes: &[&'a Syntax<'a>],
+    change_map: &ChangeMap<'a>,
+    language: Language,
+) -> Vec<DefinitionChange> {
+    let mut changes = vec![];
+    diff_definitions(
+        definition_tree(lhs_nodes, change_map, language),
+        definition_tree(rhs_nodes, change_map, language),
+        0,
+        &mut changes,
+    );
+    changes
+}
+
+fn diff_definitions(
+    lhs_definitions: Vec<DefinitionNode>,
+    rhs_definitions: Vec<DefinitionNode>,
+    depth: usize,
+    changes: &mut Vec<DefinitionChange>,
+) {
+    let mut lhs_definitions: Vec<Option<DefinitionNode>> =
+        lhs_definitions.into_iter().map(Some).collect();
+
+    for rhs_definition in rhs_definitions {
+        let lhs_i = lhs_definitions.iter().position(
+            |lhs_definition| matches!(lhs_definition, Some(d) if d.name == rhs_definition.name),
+        );
+
+        match lhs_i.and_then(|i| lhs_definitions[i].take()) {
+            Some(lhs_definition) => {
+                if lhs_definition.is_changed || rhs_definition.is_changed {
+                    changes.push(DefinitionChange {
+                        kind: DefinitionChangeKind::Modified,
+                        name: rhs_definition.name,
+                        depth,
+                    });
+                    diff_definitions(
+                        lhs_definition.children,
+                        rhs_definition.children,
+                        depth + 1,
+                        changes,
+                    );
+                }
+            }
+            None => changes.push(DefinitionChange {
+                kind: DefinitionChangeKind::Added,
+                name: rhs_definition.name,
+                depth,
+            }),
+        }
+    }
+
+    for lhs_definition in lhs_definitions.into_iter().flatten() {
+        changes.push(DefinitionChange {
+            kind: DefinitionChangeKind::Removed,
+            name: lhs_definition.name,
+            depth,
+        });
+    }
+}
+
 pub fn zip_pad_shorter<Tx: Clone, Ty: Clone>(
     lhs: &[Tx],
     rhs: &[Ty],
@@ -926,8 +1305,24 @@ pub fn zip_repeat_shorter<Tx: Clone, Ty: Clone>(lhs: &[Tx], rhs: &[Ty]) -> Vec<(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{
+        diff::changes::insert_deep_unchanged,
+        parse::{
+            guess_language,
+            tree_sitter_parser::{from_language, parse},
+        },
+    };
     use pretty_assertions::assert_eq;
 
+    /// Find the first atom in `nodes` whose content is `needle`.
+    fn find_atom<'a>(nodes: &[&'a Syntax<'a>], needle: &str) -> Option<&'a Syntax<'a>> {
+        nodes.iter().find_map(|node| match node {
+            List { children, .. } => find_atom(children, needle),
+            Atom { content, .. } if content == needle => Some(*node),
+            Atom { .. } => None,
+        })
+    }
+
     /// Consider comment atoms as distinct to other atoms even if the
     /// content matches otherwise.
     #[test]
@@ -1020,6 +1415,140 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_changed_line_definitions() {
+        let arena = Arena::new();
+        let config = from_language(guess_language::Language::Python);
+
+        let lhs = parse(
+            &arena,
+            "class A:\n    def foo(x):\n        return 1\n",
+            &config,
+        );
+        let rhs = parse(
+            &arena,
+            "class A:\n    def foo(x):\n        return 2\n",
+            &config,
+        );
+        init_all_info(&lhs, &rhs);
+
+        let mut change_map = ChangeMap::default();
+        for (lhs_node, rhs_node) in lhs.iter().zip(rhs.iter()) {
+            insert_deep_unchanged(rhs_node, lhs_node, &mut change_map);
+        }
+        change_map.insert(find_atom(&rhs, "2").unwrap(), Novel);
+
+        let definitions = changed_line_definitions(&rhs, &change_map, Language::Python);
+        assert_eq!(definitions.get(&2.into()), Some(&"def foo".to_owned()));
+        assert_eq!(definitions.len(), 1);
+    }
+
+    #[test]
+    fn test_definition_changes() {
+        let arena = Arena::new();
+        let config = from_language(guess_language::Language::Python);
+
+        let lhs = parse(
+            &arena,
+            "class A:\n    def foo():\n        return 1\n    def bar():\n        pass\n",
+            &config,
+        );
+        let rhs = parse(
+            &arena,
+            "class A:\n    def foo():\n        return 2\n    def baz():\n        pass\n",
+            &config,
+        );
+        init_all_info(&lhs, &rhs);
+
+        let mut change_map = ChangeMap::default();
+        for (lhs_node, rhs_node) in lhs.iter().zip(rhs.iter()) {
+            insert_deep_unchanged(rhs_node, lhs_node, &mut change_map);
+        }
+        change_map.insert(find_atom(&lhs, "1").unwrap(), Novel);
+        change_map.insert(find_atom(&rhs, "2").unwrap(), Novel);
+        change_map.insert(find_atom(&lhs, "bar").unwrap(), Novel);
+        change_map.insert(find_atom(&rhs, "baz").unwrap(), Novel);
+
+        let change = |kind, name: &str, depth| DefinitionChange {
+            kind,
+            name: name.to_owned(),
+            depth,
+        };
+        assert_eq!(
+            definition_changes(&lhs, &rhs, &change_map, Language::Python),
+            vec![
+                change(DefinitionChangeKind::Modified, "class A", 0),
+                change(DefinitionChangeKind::Modified, "def foo", 1),
+                change(DefinitionChangeKind::Added, "def baz", 1),
+                change(DefinitionChangeKind::Removed, "def bar", 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_definition_name() {
+        fn definition_names(src: &str, language: Language) -> Vec<String> {
+            fn collect(nodes: &[&Syntax], language: Language, names: &mut Vec<String>) {
+                for node in nodes {
+                    names.extend(definition_name(node, language));
+                    if let List { children, .. } = node {
+                        collect(children, language, names);
+                    }
+                }
+            }
+
+            let arena = Arena::new();
+            let nodes = parse(&arena, src, &from_language(language));
+            let mut names = vec![];
+            collect(&nodes, language, &mut names);
+            names
+        }
+
+        assert_eq!(
+            definition_names("public class Foo {}\n", Language::Java),
+            vec!["class Foo".to_owned()]
+        );
+        assert_eq!(
+            definition_names("(defn foo [] 1)\n", Language::Clojure),
+            vec!["defn foo".to_owned()]
+        );
+        assert_eq!(
+            definition_names("module.exports = f(object, y);\n", Language::JavaScript),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn test_without_comments() {
+        let pos = vec![SingleLineSpan {
+            line: 0.into(),
+            start_col: 2,
+            end_col: 3,
+        }];
+
+        let arena = Arena::new();
+        let comment = Syntax::new_atom(&arena, pos.clone(), "// foo", AtomKind::Comment);
+        let atom = Syntax::new_atom(&arena, pos, "foo", AtomKind::Normal);
+        let list = Syntax::new_list(&arena, "(", vec![], vec![comment, atom], ")", vec![]);
+
+        let nodes = without_comments(&arena, &[comment, list]);
+
+        assert_eq!(nodes.len(), 1);
+        match nodes[0] {
+            List { children, .. } => {
+                assert_eq!(children.len(), 1);
+                assert!(matches!(
+                    children[0],
+                    Atom {
+                        kind: AtomKind::Normal,
+                        ..
+                    }
+                ));
+            }
+            Atom { .. } => unreachable!(),
+        }
+    }
+
     #[test]
     fn test_multiline_comment_ignores_leading_whitespace() {
         let pos = vec![SingleLineSpan {
@@ -1053,7 +1582,13 @@ mod tests {
             end_col: 3,
         };
 
-        let res = split_comment_words(content, pos, opposite_content, opposite_pos);
+        let res = split_atom_words(
+            content,
+            pos,
+            opposite_content,
+            opposite_pos,
+            TokenKind::Atom(AtomKind::Comment),
+        );
         assert_eq!(
             res,
             vec![MatchedPos {
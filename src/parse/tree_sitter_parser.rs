@@ -1,6 +1,10 @@
 //! Load and configure parsers written with tree-sitter.
 
-use std::collections::HashSet;
+use std::{
+    cmp::max,
+    collections::{HashMap, HashSet},
+    time::Instant,
+};
 
 use crate::parse::guess_language as guess;
 use tree_sitter as ts;
@@ -8,7 +12,10 @@ use typed_arena::Arena;
 
 use crate::{
     lines::NewlinePositions,
-    parse::syntax::{AtomKind, Syntax},
+    parse::{
+        plugins,
+        syntax::{AtomKind, Syntax},
+    },
 };
 
 /// Configuration for a tree-sitter parser.
@@ -34,11 +41,38 @@ pub struct TreeSitterConfig {
     /// mark which token pairs we consider to be delimiters.
     delimiter_tokens: Vec<(&'static str, &'static str)>,
 
+    /// Children whose order doesn't affect the meaning of their
+    /// parent, such as the members of a JSON object, as pairs of the
+    /// parent node kind and the child node kind.
+    ///
+    /// The diff pairs up children of this kind by content, so
+    /// reordering them isn't reported as a change. The parsed tree
+    /// keeps them in source order.
+    unordered_children: Vec<(&'static str, &'static str)>,
+
+    /// Regions of the file that are written in another language,
+    /// such as `<script>` in HTML.
+    sub_languages: Vec<TreeSitterSubLanguage>,
+
     /// Tree-sitter query used for syntax highlighting this
     /// language.
     highlight_query: ts::Query,
 }
 
+/// A region of a file that should be parsed with a different language.
+pub struct TreeSitterSubLanguage {
+    /// A tree-sitter query that captures the nodes to parse as the
+    /// other language. Every capture is parsed, so the query should
+    /// only capture the contents, e.g. `(script_element (raw_text)
+    /// @contents)`.
+    query: ts::Query,
+
+    /// The configuration used to parse the captured nodes. It's
+    /// built along with the outer configuration, so it's only built
+    /// once per file.
+    config: TreeSitterConfig,
+}
+
 extern "C" {
     fn tree_sitter_bash() -> ts::Language;
     fn tree_sitter_c() -> ts::Language;
@@ -109,6 +143,8 @@ pub fn from_language(language: guess::Language) -> TreeSitterConfig {
                     .into_iter()
                     .collect(),
                 delimiter_tokens: vec![("(", ")"), ("{", "}"), ("[", "]")],
+                sub_languages: vec![],
+                unordered_children: vec![],
                 highlight_query: ts::Query::new(
                     language,
                     include_str!("../../vendor/highlights/bash.scm"),
@@ -122,6 +158,8 @@ pub fn from_language(language: guess::Language) -> TreeSitterConfig {
                 language,
                 atom_nodes: vec!["string_literal", "char_literal"].into_iter().collect(),
                 delimiter_tokens: vec![("(", ")"), ("{", "}"), ("[", "]")],
+                sub_languages: vec![],
+                unordered_children: vec![],
                 highlight_query: ts::Query::new(
                     language,
                     include_str!("../../vendor/highlights/c.scm"),
@@ -137,6 +175,8 @@ pub fn from_language(language: guess::Language) -> TreeSitterConfig {
                 // names are generally the same.
                 atom_nodes: vec!["string_literal", "char_literal"].into_iter().collect(),
                 delimiter_tokens: vec![("(", ")"), ("{", "}"), ("[", "]"), ("<", ">")],
+                sub_languages: vec![],
+                unordered_children: vec![],
                 highlight_query: ts::Query::new(
                     language,
                     concat!(
@@ -155,6 +195,8 @@ pub fn from_language(language: guess::Language) -> TreeSitterConfig {
                 delimiter_tokens: vec![("{", "}"), ("(", ")"), ("[", "]")]
                     .into_iter()
                     .collect(),
+                sub_languages: vec![],
+                unordered_children: vec![],
                 highlight_query: ts::Query::new(
                     language,
                     include_str!("../../vendor/highlights/clojure.scm"),
@@ -168,6 +210,8 @@ pub fn from_language(language: guess::Language) -> TreeSitterConfig {
                 language,
                 atom_nodes: vec!["argument"].into_iter().collect(),
                 delimiter_tokens: vec![("(", ")")].into_iter().collect(),
+                sub_languages: vec![],
+                unordered_children: vec![],
                 highlight_query: ts::Query::new(
                     language,
                     include_str!("../../vendor/highlights/cmake.scm"),
@@ -181,6 +225,8 @@ pub fn from_language(language: guess::Language) -> TreeSitterConfig {
                 language,
                 atom_nodes: vec!["str_lit", "char_lit"].into_iter().collect(),
                 delimiter_tokens: vec![("(", ")")],
+                sub_languages: vec![],
+                unordered_children: vec![],
                 highlight_query: ts::Query::new(language, "").unwrap(),
             }
         }
@@ -196,6 +242,8 @@ pub fn from_language(language: guess::Language) -> TreeSitterConfig {
                 .into_iter()
                 .collect(),
                 delimiter_tokens: vec![("{", "}"), ("(", ")")],
+                sub_languages: vec![],
+                unordered_children: vec![],
                 highlight_query: ts::Query::new(
                     language,
                     include_str!("../../vendor/highlights/c-sharp.scm"),
@@ -209,6 +257,8 @@ pub fn from_language(language: guess::Language) -> TreeSitterConfig {
                 language,
                 atom_nodes: vec!["integer_value", "float_value"].into_iter().collect(),
                 delimiter_tokens: vec![("{", "}"), ("(", ")")],
+                sub_languages: vec![],
+                unordered_children: vec![("block", "declaration")],
                 highlight_query: ts::Query::new(
                     language,
                     include_str!("../../vendor/highlights/css.scm"),
@@ -222,6 +272,8 @@ pub fn from_language(language: guess::Language) -> TreeSitterConfig {
                 language,
                 atom_nodes: vec!["string_literal", "script_tag"].into_iter().collect(),
                 delimiter_tokens: vec![("{", "}"), ("(", ")"), ("[", "]"), ("<", ">")],
+                sub_languages: vec![],
+                unordered_children: vec![],
                 highlight_query: ts::Query::new(
                     language,
                     include_str!("../../vendor/highlights/dart.scm"),
@@ -237,6 +289,8 @@ pub fn from_language(language: guess::Language) -> TreeSitterConfig {
                 delimiter_tokens: vec![("{", "}"), ("(", ")"), ("[", "]")]
                     .into_iter()
                     .collect(),
+                sub_languages: vec![],
+                unordered_children: vec![],
                 highlight_query: ts::Query::new(
                     language,
                     include_str!("../../vendor/highlights/elisp.scm"),
@@ -252,6 +306,8 @@ pub fn from_language(language: guess::Language) -> TreeSitterConfig {
                 delimiter_tokens: vec![("(", ")"), ("{", "}"), ("do", "end")]
                     .into_iter()
                     .collect(),
+                sub_languages: vec![],
+                unordered_children: vec![],
                 highlight_query: ts::Query::new(
                     language,
                     include_str!("../../vendor/highlights/elixir.scm"),
@@ -265,6 +321,8 @@ pub fn from_language(language: guess::Language) -> TreeSitterConfig {
                 language,
                 atom_nodes: vec!["string_constant_expr"].into_iter().collect(),
                 delimiter_tokens: vec![("{", "}"), ("[", "]"), ("(", ")")],
+                sub_languages: vec![],
+                unordered_children: vec![],
                 highlight_query: ts::Query::new(
                     language,
                     include_str!("../../vendor/highlights/elm.scm"),
@@ -278,6 +336,8 @@ pub fn from_language(language: guess::Language) -> TreeSitterConfig {
                 language,
                 atom_nodes: [].into(),
                 delimiter_tokens: vec![("{", "}"), ("(", ")"), ("[", "]"), ("|", "|")],
+                sub_languages: vec![],
+                unordered_children: vec![],
                 highlight_query: ts::Query::new(
                     language,
                     include_str!("../../vendor/highlights/elvish.scm"),
@@ -291,6 +351,8 @@ pub fn from_language(language: guess::Language) -> TreeSitterConfig {
                 language,
                 atom_nodes: ["string"].into(),
                 delimiter_tokens: vec![("(", ")"), ("[", "]"), ("{", "}")],
+                sub_languages: vec![],
+                unordered_children: vec![],
                 highlight_query: ts::Query::new(
                     language,
                     include_str!("../../vendor/highlights/gleam.scm"),
@@ -308,6 +370,8 @@ pub fn from_language(language: guess::Language) -> TreeSitterConfig {
                 delimiter_tokens: vec![("{", "}"), ("[", "]"), ("(", ")")]
                     .into_iter()
                     .collect(),
+                sub_languages: vec![],
+                unordered_children: vec![("import_spec_list", "import_spec")],
                 highlight_query: ts::Query::new(
                     language,
                     include_str!("../../vendor/highlights/go.scm"),
@@ -321,6 +385,8 @@ pub fn from_language(language: guess::Language) -> TreeSitterConfig {
                 language,
                 atom_nodes: vec!["prefixed_string", "heredoc"].into_iter().collect(),
                 delimiter_tokens: vec![("[", "]"), ("(", ")"), ("<", ">"), ("{", "}")],
+                sub_languages: vec![],
+                unordered_children: vec![],
                 highlight_query: ts::Query::new(
                     language,
                     include_str!("../../vendor/highlights/hack.scm"),
@@ -336,6 +402,8 @@ pub fn from_language(language: guess::Language) -> TreeSitterConfig {
                     .into_iter()
                     .collect(),
                 delimiter_tokens: vec![("[", "]"), ("(", ")"), ("{", "}")],
+                sub_languages: vec![],
+                unordered_children: vec![],
                 highlight_query: ts::Query::new(
                     language,
                     include_str!("../../vendor/highlights/hare.scm"),
@@ -349,6 +417,8 @@ pub fn from_language(language: guess::Language) -> TreeSitterConfig {
                 language,
                 atom_nodes: vec![].into_iter().collect(),
                 delimiter_tokens: vec![("[", "]"), ("(", ")")],
+                sub_languages: vec![],
+                unordered_children: vec![],
                 highlight_query: ts::Query::new(
                     language,
                     include_str!("../../vendor/highlights/haskell.scm"),
@@ -369,6 +439,8 @@ pub fn from_language(language: guess::Language) -> TreeSitterConfig {
                     ("%{~", "~}"),
                     ("${", "}"),
                 ],
+                sub_languages: vec![],
+                unordered_children: vec![],
                 highlight_query: ts::Query::new(
                     language,
                     include_str!("../../vendor/highlights/hcl.scm"),
@@ -392,6 +464,19 @@ pub fn from_language(language: guess::Language) -> TreeSitterConfig {
                 delimiter_tokens: vec![("<", ">"), ("<!", ">"), ("<!--", "-->")]
                     .into_iter()
                     .collect(),
+                sub_languages: vec![
+                    TreeSitterSubLanguage {
+                        query: ts::Query::new(language, "(script_element (raw_text) @contents)")
+                            .unwrap(),
+                        config: from_language(guess::Language::JavaScript),
+                    },
+                    TreeSitterSubLanguage {
+                        query: ts::Query::new(language, "(style_element (raw_text) @contents)")
+                            .unwrap(),
+                        config: from_language(guess::Language::Css),
+                    },
+                ],
+                unordered_children: vec![],
                 highlight_query: ts::Query::new(
                     language,
                     include_str!("../../vendor/highlights/html.scm"),
@@ -414,6 +499,8 @@ pub fn from_language(language: guess::Language) -> TreeSitterConfig {
                 ]
                 .into_iter()
                 .collect(),
+                sub_languages: vec![],
+                unordered_children: vec![],
                 highlight_query: ts::Query::new(
                     language,
                     include_str!("../../vendor/highlights/janet_simple.scm"),
@@ -427,6 +514,8 @@ pub fn from_language(language: guess::Language) -> TreeSitterConfig {
                 language,
                 atom_nodes: vec![].into_iter().collect(),
                 delimiter_tokens: vec![("(", ")"), ("{", "}"), ("[", "]")],
+                sub_languages: vec![],
+                unordered_children: vec![],
                 highlight_query: ts::Query::new(
                     language,
                     include_str!("../../vendor/highlights/java.scm"),
@@ -450,6 +539,8 @@ pub fn from_language(language: guess::Language) -> TreeSitterConfig {
                     // > at the same level in JSX.
                     ("<", ">"),
                 ],
+                sub_languages: vec![],
+                unordered_children: vec![],
                 highlight_query: ts::Query::new(
                     language,
                     include_str!("../../vendor/highlights/javascript.scm"),
@@ -463,6 +554,8 @@ pub fn from_language(language: guess::Language) -> TreeSitterConfig {
                 language,
                 atom_nodes: vec!["string"].into_iter().collect(),
                 delimiter_tokens: vec![("{", "}"), ("[", "]")],
+                sub_languages: vec![],
+                unordered_children: vec![("object", "pair")],
                 highlight_query: ts::Query::new(
                     language,
                     include_str!("../../vendor/highlights/json.scm"),
@@ -483,6 +576,8 @@ pub fn from_language(language: guess::Language) -> TreeSitterConfig {
                 .into_iter()
                 .collect(),
                 delimiter_tokens: vec![("{", "}"), ("[", "]"), ("(", ")")],
+                sub_languages: vec![],
+                unordered_children: vec![],
                 highlight_query: ts::Query::new(
                     language,
                     include_str!("../../vendor/highlights/julia.scm"),
@@ -500,6 +595,8 @@ pub fn from_language(language: guess::Language) -> TreeSitterConfig {
                 delimiter_tokens: vec![("(", ")"), ("{", "}"), ("[", "]"), ("<", ">")]
                     .into_iter()
                     .collect(),
+                sub_languages: vec![],
+                unordered_children: vec![],
                 highlight_query: ts::Query::new(
                     language,
                     include_str!("../../vendor/highlights/kotlin.scm"),
@@ -515,6 +612,8 @@ pub fn from_language(language: guess::Language) -> TreeSitterConfig {
                 delimiter_tokens: vec![("(", ")"), ("{", "}"), ("[", "]")]
                     .into_iter()
                     .collect(),
+                sub_languages: vec![],
+                unordered_children: vec![],
                 highlight_query: ts::Query::new(
                     language,
                     include_str!("../../vendor/highlights/lua.scm"),
@@ -528,6 +627,8 @@ pub fn from_language(language: guess::Language) -> TreeSitterConfig {
                 language,
                 atom_nodes: vec!["shell_text"].into_iter().collect(),
                 delimiter_tokens: vec![("(", ")")].into_iter().collect(),
+                sub_languages: vec![],
+                unordered_children: vec![],
                 highlight_query: ts::Query::new(
                     language,
                     include_str!("../../vendor/highlights/make.scm"),
@@ -543,6 +644,8 @@ pub fn from_language(language: guess::Language) -> TreeSitterConfig {
                     .into_iter()
                     .collect(),
                 delimiter_tokens: vec![("{", "}"), ("[", "]")].into_iter().collect(),
+                sub_languages: vec![],
+                unordered_children: vec![],
                 highlight_query: ts::Query::new(
                     language,
                     include_str!("../../vendor/highlights/nix.scm"),
@@ -556,6 +659,8 @@ pub fn from_language(language: guess::Language) -> TreeSitterConfig {
                 language,
                 atom_nodes: OCAML_ATOM_NODES.iter().copied().collect(),
                 delimiter_tokens: vec![("(", ")"), ("[", "]"), ("{", "}")],
+                sub_languages: vec![],
+                unordered_children: vec![],
                 highlight_query: ts::Query::new(
                     language,
                     include_str!("../../vendor/highlights/ocaml.scm"),
@@ -569,6 +674,8 @@ pub fn from_language(language: guess::Language) -> TreeSitterConfig {
                 language,
                 atom_nodes: OCAML_ATOM_NODES.iter().copied().collect(),
                 delimiter_tokens: vec![("(", ")"), ("[", "]"), ("{", "}")],
+                sub_languages: vec![],
+                unordered_children: vec![],
                 highlight_query: ts::Query::new(
                     language,
                     include_str!("../../vendor/highlights/ocaml.scm"),
@@ -582,6 +689,8 @@ pub fn from_language(language: guess::Language) -> TreeSitterConfig {
                 language,
                 atom_nodes: vec![].into_iter().collect(),
                 delimiter_tokens: vec![("(", ")"), ("[", "]")],
+                sub_languages: vec![],
+                unordered_children: vec![],
                 highlight_query: ts::Query::new(
                     language,
                     include_str!("../../vendor/highlights/pascal.scm"),
@@ -606,6 +715,8 @@ pub fn from_language(language: guess::Language) -> TreeSitterConfig {
                 .into_iter()
                 .collect(),
                 delimiter_tokens: vec![("(", ")"), ("{", "}"), ("[", "]")],
+                sub_languages: vec![],
+                unordered_children: vec![],
                 highlight_query: ts::Query::new(
                     language,
                     include_str!("../../vendor/highlights/perl.scm"),
@@ -619,6 +730,8 @@ pub fn from_language(language: guess::Language) -> TreeSitterConfig {
                 language,
                 atom_nodes: vec!["string", "encapsed_string"].into_iter().collect(),
                 delimiter_tokens: vec![("(", ")"), ("[", "]"), ("{", "}")],
+                sub_languages: vec![],
+                unordered_children: vec![],
                 highlight_query: ts::Query::new(
                     language,
                     include_str!("../../vendor/highlights/php.scm"),
@@ -632,6 +745,8 @@ pub fn from_language(language: guess::Language) -> TreeSitterConfig {
                 language,
                 atom_nodes: vec!["string"].into_iter().collect(),
                 delimiter_tokens: vec![("(", ")"), ("[", "]"), ("{", "}")],
+                sub_languages: vec![],
+                unordered_children: vec![],
                 highlight_query: ts::Query::new(
                     language,
                     include_str!("../../vendor/highlights/python.scm"),
@@ -647,6 +762,8 @@ pub fn from_language(language: guess::Language) -> TreeSitterConfig {
                     .into_iter()
                     .collect(),
                 delimiter_tokens: vec![("{", "}"), ("(", ")"), ("[", "]"), ("<", ">")],
+                sub_languages: vec![],
+                unordered_children: vec![],
                 highlight_query: ts::Query::new(
                     language,
                     concat!(
@@ -674,6 +791,8 @@ pub fn from_language(language: guess::Language) -> TreeSitterConfig {
                     ("begin", "end"),
                     ("class", "end"),
                 ],
+                sub_languages: vec![],
+                unordered_children: vec![],
                 highlight_query: ts::Query::new(
                     language,
                     include_str!("../../vendor/highlights/ruby.scm"),
@@ -687,6 +806,8 @@ pub fn from_language(language: guess::Language) -> TreeSitterConfig {
                 language,
                 atom_nodes: vec!["char_literal", "string_literal"].into_iter().collect(),
                 delimiter_tokens: vec![("{", "}"), ("(", ")"), ("[", "]"), ("|", "|"), ("<", ">")],
+                sub_languages: vec![],
+                unordered_children: vec![],
                 highlight_query: ts::Query::new(
                     language,
                     include_str!("../../vendor/highlights/rust.scm"),
@@ -700,6 +821,8 @@ pub fn from_language(language: guess::Language) -> TreeSitterConfig {
                 language,
                 atom_nodes: vec!["string", "template_string"].into_iter().collect(),
                 delimiter_tokens: vec![("{", "}"), ("(", ")"), ("[", "]")],
+                sub_languages: vec![],
+                unordered_children: vec![],
                 highlight_query: ts::Query::new(
                     language,
                     include_str!("../../vendor/highlights/scala.scm"),
@@ -713,6 +836,8 @@ pub fn from_language(language: guess::Language) -> TreeSitterConfig {
                 language,
                 atom_nodes: vec!["string", "identifier"].into_iter().collect(),
                 delimiter_tokens: vec![("(", ")")],
+                sub_languages: vec![],
+                unordered_children: vec![],
                 highlight_query: ts::Query::new(
                     language,
                     include_str!("../../vendor/highlights/sql.scm"),
@@ -726,6 +851,8 @@ pub fn from_language(language: guess::Language) -> TreeSitterConfig {
                 language,
                 atom_nodes: ["line_string_literal"].into(),
                 delimiter_tokens: vec![("{", "}"), ("(", ")"), ("[", "]"), ("<", ">")],
+                sub_languages: vec![],
+                unordered_children: vec![],
                 highlight_query: ts::Query::new(
                     language,
                     include_str!("../../vendor/highlights/swift.scm"),
@@ -739,6 +866,14 @@ pub fn from_language(language: guess::Language) -> TreeSitterConfig {
                 language,
                 atom_nodes: vec!["string"].into_iter().collect(),
                 delimiter_tokens: vec![("{", "}"), ("[", "]")],
+                sub_languages: vec![],
+                unordered_children: vec![
+                    ("document", "pair"),
+                    ("document", "table"),
+                    ("table", "pair"),
+                    ("table_array_element", "pair"),
+                    ("inline_table", "pair"),
+                ],
                 highlight_query: ts::Query::new(
                     language,
                     include_str!("../../vendor/highlights/toml.scm"),
@@ -752,6 +887,8 @@ pub fn from_language(language: guess::Language) -> TreeSitterConfig {
                 language,
                 atom_nodes: vec!["string", "template_string"].into_iter().collect(),
                 delimiter_tokens: vec![("{", "}"), ("(", ")"), ("[", "]"), ("<", ">")],
+                sub_languages: vec![],
+                unordered_children: vec![],
                 highlight_query: ts::Query::new(
                     language,
                     concat!(
@@ -770,6 +907,8 @@ pub fn from_language(language: guess::Language) -> TreeSitterConfig {
                     .into_iter()
                     .collect(),
                 delimiter_tokens: vec![("{", "}"), ("(", ")"), ("[", "]"), ("<", ">")],
+                sub_languages: vec![],
+                unordered_children: vec![],
                 highlight_query: ts::Query::new(
                     language,
                     concat!(
@@ -793,6 +932,11 @@ pub fn from_language(language: guess::Language) -> TreeSitterConfig {
                 .into_iter()
                 .collect(),
                 delimiter_tokens: (vec![("{", "}"), ("(", ")"), ("[", "]")]),
+                sub_languages: vec![],
+                unordered_children: vec![
+                    ("block_mapping", "block_mapping_pair"),
+                    ("flow_mapping", "flow_pair"),
+                ],
                 highlight_query: ts::Query::new(
                     language,
                     include_str!("../../vendor/highlights/yaml.scm"),
@@ -810,6 +954,8 @@ pub fn from_language(language: guess::Language) -> TreeSitterConfig {
                 delimiter_tokens: (vec![("{", "}"), ("[", "]"), ("(", ")")])
                     .into_iter()
                     .collect(),
+                sub_languages: vec![],
+                unordered_children: vec![],
                 highlight_query: ts::Query::new(
                     language,
                     include_str!("../../vendor/highlights/zig.scm"),
@@ -817,6 +963,23 @@ pub fn from_language(language: guess::Language) -> TreeSitterConfig {
                 .unwrap(),
             }
         }
+        Plugin(id) => {
+            let plugin = &plugins::all()[id];
+            let language = plugin.language.0;
+            TreeSitterConfig {
+                language,
+                atom_nodes: plugin.atom_nodes.iter().map(|n| n.as_str()).collect(),
+                delimiter_tokens: plugin
+                    .delimiter_tokens
+                    .iter()
+                    .map(|(open, close)| (open.as_str(), close.as_str()))
+                    .collect(),
+                sub_languages: vec![],
+                unordered_children: vec![],
+                highlight_query: ts::Query::new(language, &plugin.highlights)
+                    .expect("Plugin queries are validated when loading"),
+            }
+        }
     }
 }
 
@@ -830,6 +993,15 @@ pub fn parse_to_tree(src: &str, config: &TreeSitterConfig) -> tree_sitter::Tree
     parser.parse(src, None).unwrap()
 }
 
+/// Make `parser` give up if it's still running at `deadline`.
+fn set_deadline(parser: &mut ts::Parser, deadline: Option<Instant>) {
+    if let Some(deadline) = deadline {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        // A timeout of zero means no timeout, so use at least 1µs.
+        parser.set_timeout_micros(max(remaining.as_micros() as u64, 1));
+    }
+}
+
 /// Calculate which tree-sitter node IDs should have which syntax
 /// highlighting.
 fn tree_highlights(
@@ -960,15 +1132,34 @@ pub fn parse<'a>(
     src: &str,
     config: &TreeSitterConfig,
 ) -> Vec<&'a Syntax<'a>> {
+    parse_with_deadline(arena, src, config, None)
+        .expect("Parsing without a deadline cannot time out")
+}
+
+/// Parse `src` with tree-sitter and convert to difftastic Syntax.
+/// Returns `None` if tree-sitter is still parsing at `deadline`.
+pub fn parse_with_deadline<'a>(
+    arena: &'a Arena<Syntax<'a>>,
+    src: &str,
+    config: &TreeSitterConfig,
+    deadline: Option<Instant>,
+) -> Option<Vec<&'a Syntax<'a>>> {
     // Don't return anything on an empty input. Most parsers return a
     // zero-width top-level AST node on empty files, which is
     // confusing and not useful for diffing.
     if src.trim().is_empty() {
-        return vec![];
+        return Some(vec![]);
     }
 
-    let tree = parse_to_tree(src, config);
+    let mut parser = ts::Parser::new();
+    parser
+        .set_language(config.language)
+        .expect("Incompatible tree-sitter version");
+    set_deadline(&mut parser, deadline);
+
+    let tree = parser.parse(src, None)?;
     let highlights = tree_highlights(&tree, src, config);
+    let subtrees = parse_subtrees(src, config, &tree, deadline)?;
 
     let nl_pos = NewlinePositions::from(src);
     let mut cursor = tree.walk();
@@ -977,7 +1168,57 @@ pub fn parse<'a>(
     // each top level syntax item.
     cursor.goto_first_child();
 
-    all_syntaxes_from_cursor(arena, src, &nl_pos, &mut cursor, config, &highlights)
+    Some(all_syntaxes_from_cursor(
+        arena,
+        src,
+        &nl_pos,
+        &mut cursor,
+        config,
+        &highlights,
+        &subtrees,
+    ))
+}
+
+/// Regions of a file that have been parsed with a sub-language, keyed
+/// by the ID of the tree-sitter node in the outer tree.
+type SubtreeMap<'c> = HashMap<usize, (ts::Tree, &'c TreeSitterConfig, HighlightedNodeIds)>;
+
+/// Parse every region of `tree` that's written in a sub-language.
+fn parse_subtrees<'c>(
+    src: &str,
+    config: &'c TreeSitterConfig,
+    tree: &ts::Tree,
+    deadline: Option<Instant>,
+) -> Option<SubtreeMap<'c>> {
+    let mut subtrees = HashMap::new();
+
+    for sub_language in &config.sub_languages {
+        let sub_config = &sub_language.config;
+        let mut parser = ts::Parser::new();
+        parser
+            .set_language(sub_config.language)
+            .expect("Incompatible tree-sitter version");
+
+        let mut qc = ts::QueryCursor::new();
+        let q_matches = qc.matches(&sub_language.query, tree.root_node(), src.as_bytes());
+
+        for m in q_matches {
+            for c in m.captures {
+                // Parse only the captured range, so the tree-sitter
+                // node positions are still relative to `src`.
+                parser
+                    .set_included_ranges(&[c.node.range()])
+                    .expect("A single node range is always valid");
+                set_deadline(&mut parser, deadline);
+                let sub_tree = parser.parse(src, None)?;
+
+                let sub_highlights = tree_highlights(&sub_tree, src, sub_config);
+                subtrees.insert(c.node.id(), (sub_tree, sub_config, sub_highlights));
+            }
+        }
+    }
+
+    Some(subtrees)
 }
 
 fn child_tokens<'a>(src: &'a str, cursor: &mut ts::TreeCursor) -> Vec<Option<&'a str>> {
@@ -1046,13 +1287,21 @@ fn all_syntaxes_from_cursor<'a>(
     cursor: &mut ts::TreeCursor,
     config: &TreeSitterConfig,
     highlights: &HighlightedNodeIds,
+    subtrees: &SubtreeMap,
 ) -> Vec<&'a Syntax<'a>> {
+    let parent_kind = cursor.node().parent().map(|parent| parent.kind());
     let mut result: Vec<&Syntax> = vec![];
 
     loop {
-        result.extend(syntax_from_cursor(
-            arena, src, nl_pos, cursor, config, highlights,
-        ));
+        let node = cursor.node();
+        if let Some(syntax) =
+            syntax_from_cursor(arena, src, nl_pos, cursor, config, highlights, subtrees)
+        {
+            if let Some(parent_kind) = parent_kind {
+                mark_if_unordered(parent_kind, &node, syntax, config);
+            }
+            result.push(syntax);
+        }
 
         if !cursor.goto_next_sibling() {
             break;
@@ -1062,6 +1311,24 @@ fn all_syntaxes_from_cursor<'a>(
     result
 }
 
+/// Mark `syntax` as unordered if `node` is a child whose order
+/// doesn't affect the meaning of `parent_kind`. We keep the source
+/// order, and the diff pairs up unordered children by content.
+fn mark_if_unordered(
+    parent_kind: &str,
+    node: &ts::Node,
+    syntax: &Syntax,
+    config: &TreeSitterConfig,
+) {
+    let is_unordered = config
+        .unordered_children
+        .iter()
+        .any(|(parent, child)| *parent == parent_kind && *child == node.kind());
+    if is_unordered {
+        syntax.set_unordered();
+    }
+}
+
 /// Convert the tree-sitter node at `cursor` to a difftastic syntax
 /// node.
 fn syntax_from_cursor<'a>(
@@ -1071,9 +1338,28 @@ fn syntax_from_cursor<'a>(
     cursor: &mut ts::TreeCursor,
     config: &TreeSitterConfig,
     highlights: &HighlightedNodeIds,
+    subtrees: &SubtreeMap,
 ) -> Option<&'a Syntax<'a>> {
     let node = cursor.node();
 
+    if let Some((sub_tree, sub_config, sub_highlights)) = subtrees.get(&node.id()) {
+        let mut sub_cursor = sub_tree.walk();
+        if sub_cursor.goto_first_child() {
+            let children = all_syntaxes_from_cursor(
+                arena,
+                src,
+                nl_pos,
+                &mut sub_cursor,
+                sub_config,
+                sub_highlights,
+                &SubtreeMap::new(),
+            );
+
+            // The sub-language region has no delimiters of its own.
+            return Some(Syntax::new_list(arena, "", vec![], children, "", vec![]));
+        }
+    }
+
     if node.is_error() {
         let position = nl_pos.from_offsets(node.start_byte(), node.end_byte());
         let content = &src[node.start_byte()..node.end_byte()];
@@ -1091,7 +1377,7 @@ fn syntax_from_cursor<'a>(
         atom_from_cursor(arena, src, nl_pos, cursor, highlights)
     } else if node.child_count() > 0 {
         Some(list_from_cursor(
-            arena, src, nl_pos, cursor, config, highlights,
+            arena, src, nl_pos, cursor, config, highlights, subtrees,
         ))
     } else {
         atom_from_cursor(arena, src, nl_pos, cursor, highlights)
@@ -1107,6 +1393,7 @@ fn list_from_cursor<'a>(
     cursor: &mut ts::TreeCursor,
     config: &TreeSitterConfig,
     highlights: &HighlightedNodeIds,
+    subtrees: &SubtreeMap,
 ) -> &'a Syntax<'a> {
     let root_node = cursor.node();
 
@@ -1148,24 +1435,26 @@ fn list_from_cursor<'a>(
     cursor.goto_first_child();
     loop {
         let node = cursor.node();
-        if node_i < i {
-            before_delim.extend(syntax_from_cursor(
-                arena, src, nl_pos, cursor, config, highlights,
-            ));
-        } else if node_i == i {
+        if node_i == i {
             inner_open_content = &src[node.start_byte()..node.end_byte()];
             inner_open_position = nl_pos.from_offsets(node.start_byte(), node.end_byte());
-        } else if node_i < j {
-            between_delim.extend(syntax_from_cursor(
-                arena, src, nl_pos, cursor, config, highlights,
-            ));
         } else if node_i == j {
             inner_close_content = &src[node.start_byte()..node.end_byte()];
             inner_close_position = nl_pos.from_offsets(node.start_byte(), node.end_byte());
-        } else if node_i > j {
-            after_delim.extend(syntax_from_cursor(
-                arena, src, nl_pos, cursor, config, highlights,
-            ));
+        } else {
+            let section = if node_i < i {
+                &mut before_delim
+            } else if node_i < j {
+                &mut between_delim
+            } else {
+                &mut after_delim
+            };
+            if let Some(syntax) =
+                syntax_from_cursor(arena, src, nl_pos, cursor, config, highlights, subtrees)
+            {
+                mark_if_unordered(root_node.kind(), &node, syntax, config);
+                section.push(syntax);
+            }
         }
 
         if !cursor.goto_next_sibling() {
@@ -1276,6 +1565,27 @@ mod tests {
         parse(&arena, ".foo {}", &css_config);
     }
 
+    fn atom_contents<'a>(nodes: &[&'a Syntax<'a>], contents: &mut Vec<&'a str>) {
+        for node in nodes {
+            match node {
+                Syntax::List { children, .. } => atom_contents(children, contents),
+                Syntax::Atom { content, .. } => contents.push(content),
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_html_script_as_javascript() {
+        let arena = Arena::new();
+        let config = from_language(guess::Language::Html);
+        let res = parse(&arena, "<script>let x = 1;</script>", &config);
+
+        let mut contents = vec![];
+        atom_contents(&res, &mut contents);
+        assert!(contents.contains(&"x"));
+        assert!(!contents.contains(&"let x = 1;"));
+    }
+
     #[test]
     fn test_parse_empty_file() {
         let arena = Arena::new();
@@ -1285,4 +1595,27 @@ mod tests {
         let expected: Vec<&Syntax> = vec![];
         assert_eq!(res, expected);
     }
+
+    #[test]
+    fn test_parse_unordered_children() {
+        let arena = Arena::new();
+        let config = from_language(guess::Language::Json);
+        let res = parse(&arena, "{\"b\": 1, \"a\": [3, 2]}", &config);
+
+        // Unordered children keep their source order.
+        let mut contents = vec![];
+        atom_contents(&res, &mut contents);
+        assert_eq!(
+            contents,
+            vec!["\"b\"", ":", "1", ",", "\"a\"", ":", "3", ",", "2"]
+        );
+
+        match res[0] {
+            Syntax::List { children, .. } => {
+                let unordered: Vec<bool> = children.iter().map(|c| c.is_unordered()).collect();
+                assert_eq!(unordered, vec![true, false, true]);
+            }
+            _ => unreachable!(),
+        }
+    }
 }
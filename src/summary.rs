@@ -1,6 +1,8 @@
 //! Data types summarising the result of diffing content.
 
-use crate::parse::syntax::MatchedPos;
+use rustc_hash::FxHashMap;
+
+use crate::{display::hunks::Hunk, lines::LineNumber, parse::syntax::MatchedPos};
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum FileContent {
@@ -17,6 +19,182 @@ pub struct DiffResult {
     pub detected_language: Option<crate::parse::guess_language::Language>,
     pub lhs_src: FileContent,
     pub rhs_src: FileContent,
+    /// Whether the text ended with a newline. We discard the final
+    /// newline before diffing, but patches need to preserve it.
+    pub lhs_ends_with_newline: bool,
+    pub rhs_ends_with_newline: bool,
+    /// Whether the file doesn't exist, e.g. the LHS of an added
+    /// file. Missing files are diffed as if they were empty.
+    pub lhs_missing: bool,
+    pub rhs_missing: bool,
     pub lhs_positions: Vec<MatchedPos>,
     pub rhs_positions: Vec<MatchedPos>,
+    /// For each LHS line with changes, the innermost definition
+    /// (e.g. a function or class) containing the first change.
+    pub lhs_definitions: FxHashMap<LineNumber, String>,
+    pub rhs_definitions: FxHashMap<LineNumber, String>,
+    /// The definitions that were added, removed or modified, in the
+    /// order they occur in the file.
+    pub definition_changes: Vec<DefinitionChange>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DefinitionChangeKind {
+    Added,
+    Removed,
+    Modified,
+}
+
+/// A definition, such as a function or a class, that differs between
+/// the two inputs.
+#[derive(Debug, PartialEq, Eq)]
+pub struct DefinitionChange {
+    pub kind: DefinitionChangeKind,
+    /// A description such as `fn foo`.
+    pub name: String,
+    /// The number of enclosing definitions, e.g. 1 for a method
+    /// inside a class.
+    pub depth: usize,
+}
+
+/// How much two inputs differ. Ordered from least to most
+/// significant, so the overall status of several files is the
+/// maximum.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ChangeStatus {
+    Unchanged,
+    /// The content differs, but only in whitespace or other
+    /// formatting that doesn't affect the syntax.
+    FormattingOnly,
+    Changed,
+}
+
+impl ChangeStatus {
+    /// The process exit code used by `--exit-code`. We don't use 2,
+    /// as that's [`EXIT_ERROR`](crate::exit_codes::EXIT_ERROR).
+    pub fn exit_code(self) -> i32 {
+        match self {
+            ChangeStatus::Unchanged => 0,
+            ChangeStatus::Changed => 1,
+            ChangeStatus::FormattingOnly => 3,
+        }
+    }
+}
+
+impl DiffResult {
+    /// The path to show for this file in a list of files. Like the
+    /// hunk headers, we only show both paths for renames reported by
+    /// a VCS.
+    pub fn display_path(&self, in_vcs: bool) -> String {
+        if self.rhs_display_path == "/dev/null" {
+            self.lhs_display_path.clone()
+        } else if !in_vcs
+            || self.lhs_display_path == "/dev/null"
+            || self.lhs_display_path == self.rhs_display_path
+        {
+            self.rhs_display_path.clone()
+        } else {
+            format!("{} => {}", self.lhs_display_path, self.rhs_display_path)
+        }
+    }
+
+    /// The definition containing the first change in `hunk`,
+    /// preferring the RHS.
+    pub fn hunk_definition(&self, hunk: &Hunk) -> Option<&str> {
+        let rhs_definition = hunk
+            .novel_rhs
+            .iter()
+            .min()
+            .and_then(|line| self.rhs_definitions.get(line));
+        let lhs_definition = hunk
+            .novel_lhs
+            .iter()
+            .min()
+            .and_then(|line| self.lhs_definitions.get(line));
+
+        rhs_definition.or(lhs_definition).map(|s| s.as_str())
+    }
+
+    pub fn change_status(&self) -> ChangeStatus {
+        match (&self.lhs_src, &self.rhs_src) {
+            (FileContent::Text(lhs_src), FileContent::Text(rhs_src)) => {
+                let has_novel = self
+                    .lhs_positions
+                    .iter()
+                    .chain(self.rhs_positions.iter())
+                    .any(|mp| mp.kind.is_novel());
+
+                if has_novel {
+                    ChangeStatus::Changed
+                } else if lhs_src != rhs_src
+                    || self.lhs_ends_with_newline != self.rhs_ends_with_newline
+                {
+                    ChangeStatus::FormattingOnly
+                } else {
+                    ChangeStatus::Unchanged
+                }
+            }
+            (FileContent::Binary(lhs_bytes), FileContent::Binary(rhs_bytes)) => {
+                if lhs_bytes == rhs_bytes {
+                    ChangeStatus::Unchanged
+                } else {
+                    ChangeStatus::Changed
+                }
+            }
+            // We're diffing a binary file against a text file.
+            _ => ChangeStatus::Changed,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        parse::syntax::{AtomKind, MatchKind, MatchedPos, TokenKind},
+        positions::SingleLineSpan,
+        test_helpers,
+    };
+
+    fn text_result(lhs_src: &str, rhs_src: &str, lhs_positions: Vec<MatchedPos>) -> DiffResult {
+        DiffResult {
+            lhs_positions,
+            ..test_helpers::text_result("foo.js", lhs_src, rhs_src)
+        }
+    }
+
+    #[test]
+    fn test_change_status_formatting_only() {
+        let res = text_result("foo(1,2)", "foo(1, 2)", vec![]);
+        assert_eq!(res.change_status(), ChangeStatus::FormattingOnly);
+    }
+
+    #[test]
+    fn test_change_status_changed() {
+        let novel = MatchedPos {
+            kind: MatchKind::Novel {
+                highlight: TokenKind::Atom(AtomKind::Normal),
+            },
+            pos: SingleLineSpan {
+                line: 0.into(),
+                start_col: 0,
+                end_col: 3,
+            },
+        };
+        let res = text_result("foo", "bar", vec![novel]);
+        assert_eq!(res.change_status(), ChangeStatus::Changed);
+    }
+
+    #[test]
+    fn test_change_status_overall() {
+        let statuses = vec![
+            ChangeStatus::Unchanged,
+            ChangeStatus::FormattingOnly,
+            ChangeStatus::Unchanged,
+        ];
+        assert_eq!(
+            statuses.into_iter().max(),
+            Some(ChangeStatus::FormattingOnly)
+        );
+    }
 }
@@ -0,0 +1,48 @@
+//! Helpers for constructing values in unit tests.
+
+use rustc_hash::FxHashMap;
+
+use crate::{
+    display::{style::BackgroundColor, theme::Theme},
+    options::{DisplayMode, DisplayOptions},
+    summary::{DiffResult, FileContent},
+};
+
+/// A JavaScript diff result for `path` with no changed positions.
+pub fn text_result(path: &str, lhs_src: &str, rhs_src: &str) -> DiffResult {
+    DiffResult {
+        lhs_display_path: path.into(),
+        rhs_display_path: path.into(),
+        language: Some("JavaScript".into()),
+        detected_language: None,
+        lhs_src: FileContent::Text(lhs_src.into()),
+        rhs_src: FileContent::Text(rhs_src.into()),
+        lhs_ends_with_newline: true,
+        rhs_ends_with_newline: true,
+        lhs_missing: false,
+        rhs_missing: false,
+        lhs_positions: vec![],
+        rhs_positions: vec![],
+        lhs_definitions: FxHashMap::default(),
+        rhs_definitions: FxHashMap::default(),
+        definition_changes: vec![],
+    }
+}
+
+/// Display options for `display_mode` without color.
+pub fn display_options(display_mode: DisplayMode) -> DisplayOptions {
+    DisplayOptions {
+        background_color: BackgroundColor::Dark,
+        theme: Theme::default_for(BackgroundColor::Dark),
+        use_color: false,
+        display_mode,
+        print_unchanged: true,
+        print_formatting_only: true,
+        tab_width: 8,
+        display_width: 80,
+        num_context_lines: 3,
+        in_vcs: false,
+        interactive: false,
+        syntax_highlight: true,
+    }
+}